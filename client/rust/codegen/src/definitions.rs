@@ -0,0 +1,289 @@
+//! Loading the `Services` message to generate bindings from, either from
+//! the JSON definitions written by `tools/ServiceDefinitions` or from a
+//! running server.
+
+use base64::Engine;
+use krpc::schema::procedure::GameScene;
+use krpc::schema::{
+    r#type, Class, Enumeration, EnumerationValue, Exception, Parameter, Procedure, Service,
+    Services, Type,
+};
+use krpc::Client;
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+
+/// Fetches the services of a running server, by calling `KRPC.GetServices`.
+pub fn from_server(client: &Client) -> Result<Services> {
+    Ok(client.invoke_typed("KRPC", "GetServices", &[])?)
+}
+
+/// Reads JSON service definitions, which map each service name to its
+/// `id`, `documentation`, `procedures`, `classes`, `enumerations` and
+/// `exceptions`, as `tools/ServiceDefinitions` writes them.
+pub fn from_json(json: &str) -> Result<Services> {
+    let definitions: Value = serde_json::from_str(json)?;
+    let services = object(&definitions, "service definitions")?
+        .iter()
+        .map(|(name, definition)| service(name, definition))
+        .collect::<Result<_>>()?;
+    Ok(Services { services })
+}
+
+fn service(name: &str, definition: &Value) -> Result<Service> {
+    let context = format!("service {}", name);
+    let definition = object(definition, &context)?;
+    Ok(Service {
+        name: name.to_string(),
+        procedures: members(definition, "procedures", procedure)?,
+        classes: members(definition, "classes", |name, definition| {
+            Ok(Class {
+                name: name.to_string(),
+                documentation: documentation(definition),
+            })
+        })?,
+        enumerations: members(definition, "enumerations", enumeration)?,
+        exceptions: members(definition, "exceptions", |name, definition| {
+            Ok(Exception {
+                name: name.to_string(),
+                documentation: documentation(definition),
+            })
+        })?,
+        documentation: documentation(definition),
+    })
+}
+
+/// Converts each entry of the optional object `key`, passing its name.
+fn members<T>(
+    definition: &Map<String, Value>,
+    key: &str,
+    convert: impl Fn(&str, &Map<String, Value>) -> Result<T>,
+) -> Result<Vec<T>> {
+    let members = match definition.get(key) {
+        Some(members) => object(members, key)?,
+        None => return Ok(Vec::new()),
+    };
+    members
+        .iter()
+        .map(|(name, member)| convert(name, object(member, name)?))
+        .collect()
+}
+
+fn procedure(name: &str, definition: &Map<String, Value>) -> Result<Procedure> {
+    let parameters = match definition.get("parameters") {
+        Some(Value::Array(parameters)) => parameters
+            .iter()
+            .map(|parameter| self::parameter(name, parameter))
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(invalid(format!("parameters of {} are not a list", name))),
+        None => Vec::new(),
+    };
+    let game_scenes = match definition.get("game_scenes") {
+        Some(Value::Array(scenes)) => scenes
+            .iter()
+            .map(|scene| {
+                scene
+                    .as_str()
+                    .and_then(GameScene::from_str_name)
+                    .map(|scene| scene as i32)
+                    .ok_or_else(|| invalid(format!("unknown game scene {} in {}", scene, name)))
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(invalid(format!("game scenes of {} are not a list", name))),
+        None => Vec::new(),
+    };
+    Ok(Procedure {
+        name: name.to_string(),
+        parameters,
+        return_type: definition.get("return_type").map(typ).transpose()?,
+        return_is_nullable: definition
+            .get("return_is_nullable")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        game_scenes,
+        documentation: documentation(definition),
+    })
+}
+
+fn parameter(procedure: &str, definition: &Value) -> Result<Parameter> {
+    let context = format!("parameter of {}", procedure);
+    let definition = object(definition, &context)?;
+    let name = string(definition, "name", &context)?;
+    let typ = definition
+        .get("type")
+        .ok_or_else(|| invalid(format!("{} {} has no type", context, name)))?;
+    let default_value = match definition.get("default_value") {
+        Some(Value::String(value)) => base64::engine::general_purpose::STANDARD
+            .decode(value)
+            .map_err(|e| invalid(format!("default value of {} {}: {}", context, name, e)))?,
+        Some(_) => return Err(invalid(format!("default value of {} {}", context, name))),
+        None => Vec::new(),
+    };
+    Ok(Parameter {
+        name,
+        r#type: Some(self::typ(typ)?),
+        default_value: default_value.into(),
+    })
+}
+
+fn typ(definition: &Value) -> Result<Type> {
+    let context = "type";
+    let definition = object(definition, context)?;
+    let code = string(definition, "code", context)?;
+    let code = r#type::TypeCode::from_str_name(&code)
+        .ok_or_else(|| invalid(format!("unknown type code {}", code)))?;
+    let types = match definition.get("types") {
+        Some(Value::Array(types)) => types.iter().map(typ).collect::<Result<_>>()?,
+        Some(_) => return Err(invalid(format!("types of {:?} are not a list", code))),
+        None => Vec::new(),
+    };
+    Ok(Type {
+        code: code as i32,
+        service: optional_string(definition, "service"),
+        name: optional_string(definition, "name"),
+        types,
+    })
+}
+
+fn enumeration(name: &str, definition: &Map<String, Value>) -> Result<Enumeration> {
+    let context = format!("value of enumeration {}", name);
+    let values = match definition.get("values") {
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| {
+                let value = object(value, &context)?;
+                Ok(EnumerationValue {
+                    name: string(value, "name", &context)?,
+                    value: value
+                        .get("value")
+                        .and_then(Value::as_i64)
+                        .and_then(|value| i32::try_from(value).ok())
+                        .ok_or_else(|| invalid(format!("{} has no value", context)))?,
+                    documentation: documentation(value),
+                })
+            })
+            .collect::<Result<_>>()?,
+        _ => return Err(invalid(format!("enumeration {} has no values", name))),
+    };
+    Ok(Enumeration {
+        name: name.to_string(),
+        values,
+        documentation: documentation(definition),
+    })
+}
+
+fn object<'a>(value: &'a Value, context: &str) -> Result<&'a Map<String, Value>> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid(format!("{} is not an object", context)))
+}
+
+fn string(definition: &Map<String, Value>, key: &str, context: &str) -> Result<String> {
+    definition
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| invalid(format!("{} has no {}", context, key)))
+}
+
+fn optional_string(definition: &Map<String, Value>, key: &str) -> String {
+    definition
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn documentation(definition: &Map<String, Value>) -> String {
+    optional_string(definition, "documentation")
+}
+
+fn invalid(message: String) -> Error {
+    Error::Definitions(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_matches, assert_ok};
+
+    #[test]
+    fn reads_service_definitions() {
+        let json = r#"{
+            "SpaceCenter": {
+                "id": 2,
+                "documentation": "<doc><summary>Space center.</summary></doc>",
+                "procedures": {
+                    "Vessel_set_Name": {
+                        "id": 1,
+                        "parameters": [
+                            {"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                            {"name": "value", "type": {"code": "STRING"}}
+                        ],
+                        "documentation": ""
+                    },
+                    "get_Bodies": {
+                        "id": 2,
+                        "parameters": [],
+                        "return_type": {"code": "DICTIONARY", "types": [{"code": "STRING"}, {"code": "UINT64"}]},
+                        "return_is_nullable": false,
+                        "game_scenes": ["FLIGHT", "TRACKING_STATION"],
+                        "documentation": ""
+                    },
+                    "Launch": {
+                        "id": 3,
+                        "parameters": [{"name": "recover", "type": {"code": "BOOL"}, "default_value": "AQ=="}],
+                        "documentation": ""
+                    }
+                },
+                "classes": {"Vessel": {"documentation": "<doc><summary>A vessel.</summary></doc>"}},
+                "enumerations": {
+                    "GameMode": {
+                        "documentation": "",
+                        "values": [{"name": "Sandbox", "value": 0, "documentation": ""}]
+                    }
+                },
+                "exceptions": {}
+            }
+        }"#;
+        let services = assert_ok!(from_json(json));
+        assert_eq!(services.services.len(), 1);
+        let service = &services.services[0];
+        assert_eq!(service.name, "SpaceCenter");
+        assert_eq!(service.classes[0].name, "Vessel");
+        assert_eq!(service.enumerations[0].values[0].name, "Sandbox");
+
+        let names: Vec<&str> = service.procedures.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Launch", "Vessel_set_Name", "get_Bodies"]);
+        let launch = &service.procedures[0];
+        assert_eq!(launch.parameters[0].default_value.as_ref(), [1u8]);
+        assert!(launch.return_type.is_none());
+        let setter = &service.procedures[1];
+        let this = setter.parameters[0].r#type.as_ref().unwrap();
+        assert_eq!(this.code(), r#type::TypeCode::Class);
+        assert_eq!(
+            (this.service.as_str(), this.name.as_str()),
+            ("SpaceCenter", "Vessel")
+        );
+        let bodies = &service.procedures[2];
+        let typ = bodies.return_type.as_ref().unwrap();
+        assert_eq!(typ.code(), r#type::TypeCode::Dictionary);
+        assert_eq!(typ.types[1].code(), r#type::TypeCode::Uint64);
+        assert_eq!(
+            bodies.game_scenes,
+            [GameScene::Flight as i32, GameScene::TrackingStation as i32]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_definitions() {
+        assert_matches!(from_json("{"), Err(Error::Json(_)));
+        assert_matches!(from_json("[]"), Err(Error::Definitions(_)));
+        assert_matches!(
+            from_json(
+                r#"{"S": {"procedures": {"P": {"parameters": [{"name": "x", "type": {"code": "WIDGET"}}]}}}}"#
+            ),
+            Err(Error::Definitions(_))
+        );
+    }
+}
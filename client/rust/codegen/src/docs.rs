@@ -0,0 +1,211 @@
+//! Conversion of the XML documentation of services into Markdown for
+//! rustdoc.
+//!
+//! Services are documented with C# XML documentation comments. The tags are
+//! the ones the other clients' generators handle: `summary`, `remarks`,
+//! `param`, `returns`, `see`, `paramref`, `a`, `c`, `math`, `list` and
+//! `item`.
+
+/// A parsed XML node.
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn attribute(&self, key: &str) -> &str {
+        match self {
+            Node::Element { attributes, .. } => attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .unwrap_or(""),
+            Node::Text(_) => "",
+        }
+    }
+}
+
+/// Converts XML documentation to Markdown. Paragraphs are separated by a
+/// blank line. Documentation that is not well-formed XML is used as it is.
+pub fn to_markdown(xml: &str) -> String {
+    if xml.trim().is_empty() {
+        return String::new();
+    }
+    let markdown = match Parser::new(xml).parse_nodes() {
+        Some(nodes) => render_all(&nodes),
+        None => collapse_whitespace(xml),
+    };
+    markdown
+        .split('\n')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn render_all(nodes: &[Node]) -> String {
+    nodes.iter().map(render).collect()
+}
+
+fn render(node: &Node) -> String {
+    let (name, children) = match node {
+        Node::Text(text) => return collapse_whitespace(text),
+        Node::Element { name, children, .. } => (name.as_str(), children),
+    };
+    let content = || render_all(children).trim().to_string();
+    match name {
+        "remarks" | "returns" => format!("\n\n{}", content()),
+        "param" => format!("\n\n* `{}` - {}", node.attribute("name"), content()),
+        "see" => format!("`{}`", cref_name(node.attribute("cref"))),
+        "paramref" => format!("`{}`", node.attribute("name")),
+        "a" => format!("[{}]({})", content(), node.attribute("href")),
+        "c" => format!("`{}`", content()),
+        "list" => format!("\n{}\n", render_all(children)),
+        "item" => format!("\n* {}", content()),
+        _ => render_all(children),
+    }
+}
+
+/// The name a `cref` refers to, without its kind prefix and service, so
+/// that `M:SpaceCenter.Vessel.Recover` becomes `Vessel.Recover`.
+fn cref_name(cref: &str) -> &str {
+    let name = cref.split_once(':').map_or(cref, |(_, name)| name);
+    name.split_once('.').map_or(name, |(_, member)| member)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                result.push(' ');
+            }
+            in_space = true;
+        } else {
+            result.push(c);
+            in_space = false;
+        }
+    }
+    result
+}
+
+/// A parser for the subset of XML used in documentation comments: elements,
+/// attributes, text and the predefined entities.
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(xml: &'a str) -> Self {
+        Parser { rest: xml }
+    }
+
+    /// Parses nodes up to the end of the input or a closing tag.
+    fn parse_nodes(&mut self) -> Option<Vec<Node>> {
+        let mut nodes = Vec::new();
+        while !self.rest.is_empty() && !self.rest.starts_with("</") {
+            if self.rest.starts_with('<') {
+                nodes.push(self.parse_element()?);
+            } else {
+                let end = self.rest.find('<').unwrap_or(self.rest.len());
+                nodes.push(Node::Text(unescape(&self.rest[..end])));
+                self.rest = &self.rest[end..];
+            }
+        }
+        Some(nodes)
+    }
+
+    fn parse_element(&mut self) -> Option<Node> {
+        let end = self.rest.find('>')?;
+        let tag = &self.rest[1..end];
+        self.rest = &self.rest[end + 1..];
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let (name, mut attributes_text) = tag
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((tag.trim(), ""));
+        let mut attributes = Vec::new();
+        while let Some((key, rest)) = attributes_text.split_once('=') {
+            let rest = rest.trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let (value, rest) = rest[1..].split_once(quote)?;
+            attributes.push((key.trim().to_string(), unescape(value)));
+            attributes_text = rest;
+        }
+        let children = if empty {
+            Vec::new()
+        } else {
+            let children = self.parse_nodes()?;
+            let closing = format!("</{}>", name);
+            self.rest = self.rest.strip_prefix(closing.as_str())?;
+            children
+        };
+        Some(Node::Element {
+            name: name.to_string(),
+            attributes,
+            children,
+        })
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_and_remarks() {
+        let xml = "<doc>\n<summary>\nThe current universal time\nin seconds.\n</summary>\n\
+                   <remarks>Only available in <c>Flight</c>.</remarks>\n</doc>";
+        assert_eq!(
+            to_markdown(xml),
+            "The current universal time in seconds.\n\nOnly available in `Flight`."
+        );
+    }
+
+    #[test]
+    fn references() {
+        let xml = "<doc><summary>Recovers <paramref name=\"vessel\" />, see \
+                   <see cref=\"M:SpaceCenter.Vessel.Recover\" /> and \
+                   <a href=\"https://krpc.github.io\">the docs</a>.</summary>\
+                   <param name=\"vessel\">The vessel &amp; its crew.</param>\
+                   <returns>Whether it worked.</returns></doc>";
+        assert_eq!(
+            to_markdown(xml),
+            "Recovers `vessel`, see `Vessel.Recover` and [the docs](https://krpc.github.io).\n\n\
+             * `vessel` - The vessel & its crew.\n\nWhether it worked."
+        );
+    }
+
+    #[test]
+    fn lists() {
+        let xml = "<doc><summary>One of:<list><item>A</item><item>B</item></list></summary></doc>";
+        assert_eq!(to_markdown(xml), "One of:\n\n* A\n* B");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(to_markdown(""), "");
+        assert_eq!(
+            to_markdown("<doc><summary>Oops</doc>"),
+            "<doc><summary>Oops</doc>"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors in reading service definitions or generating bindings for them.
+#[derive(Debug)]
+pub enum Error {
+    /// A definitions file could not be read, or the bindings written.
+    Io(std::io::Error),
+    /// A definitions file was not valid JSON.
+    Json(serde_json::Error),
+    /// The definitions were valid JSON or protobuf, but did not describe a
+    /// service that bindings can be generated for.
+    Definitions(String),
+    /// The services could not be fetched from a running server.
+    Client(krpc::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "invalid JSON: {}", e),
+            Error::Definitions(message) => write!(f, "invalid service definitions: {}", message),
+            Error::Client(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Client(e) => Some(e),
+            Error::Definitions(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<krpc::Error> for Error {
+    fn from(e: krpc::Error) -> Self {
+        Error::Client(e)
+    }
+}
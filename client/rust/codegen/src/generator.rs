@@ -0,0 +1,701 @@
+//! Generation of Rust source code from service definitions.
+
+use std::collections::BTreeMap;
+
+use krpc::codec;
+use krpc::schema::r#type::TypeCode;
+use krpc::schema::{Enumeration, Parameter, Procedure, Service, Services, Type};
+
+use crate::docs;
+use crate::error::{Error, Result};
+use crate::names::{identifier, Member};
+
+/// Generates a module for each of `services`, named after the service in
+/// snake_case. The modules refer to each other's classes and enumerations
+/// through `super`, so they must be included side by side.
+pub fn generate(services: &Services) -> Result<String> {
+    let mut code = Code::default();
+    code.line("// Generated by krpc-codegen from the kRPC service definitions. Do not edit.");
+    let mut sorted: Vec<&Service> = services.services.iter().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    for service in sorted {
+        code.line("");
+        Generator { services, service }.write_service(&mut code)?;
+    }
+    Ok(code.text)
+}
+
+/// Source code under construction, indented four spaces per level.
+#[derive(Default)]
+struct Code {
+    text: String,
+    indent: usize,
+}
+
+impl Code {
+    fn line(&mut self, line: &str) {
+        if !line.is_empty() {
+            self.text.push_str(&"    ".repeat(self.indent));
+            self.text.push_str(line);
+        }
+        self.text.push('\n');
+    }
+
+    /// Writes a line and indents the lines that follow it.
+    fn open(&mut self, line: &str) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    /// Unindents, then writes a line.
+    fn close(&mut self, line: &str) {
+        self.indent -= 1;
+        self.line(line);
+    }
+
+    /// Writes XML documentation as `///` comments.
+    fn doc(&mut self, documentation: &str) {
+        self.markdown(&docs::to_markdown(documentation));
+    }
+
+    fn markdown(&mut self, markdown: &str) {
+        for line in markdown.lines() {
+            self.line(&format!(
+                "///{}{}",
+                if line.is_empty() { "" } else { " " },
+                line
+            ));
+        }
+    }
+}
+
+/// A parameter as it appears in a generated function.
+struct Argument<'a> {
+    name: String,
+    position: usize,
+    typ: String,
+    /// Whether `typ` is a reference, so the argument is passed as it is
+    /// rather than borrowed.
+    by_reference: bool,
+    parameter: &'a Parameter,
+}
+
+impl Argument<'_> {
+    fn optional(&self) -> bool {
+        !self.parameter.default_value.is_empty()
+    }
+
+    fn signature(&self) -> String {
+        if self.optional() {
+            format!("{}: Option<{}>", self.name, self.typ)
+        } else {
+            format!("{}: {}", self.name, self.typ)
+        }
+    }
+
+    fn value(&self) -> String {
+        if self.by_reference {
+            self.name.clone()
+        } else {
+            format!("&{}", self.name)
+        }
+    }
+}
+
+/// A class of the service, and the procedures that are its members.
+struct ClassMembers<'a> {
+    documentation: &'a str,
+    members: Vec<(&'a Procedure, Member<'a>)>,
+}
+
+struct Generator<'a> {
+    services: &'a Services,
+    service: &'a Service,
+}
+
+impl<'a> Generator<'a> {
+    fn write_service(&self, code: &mut Code) -> Result<()> {
+        code.doc(&self.service.documentation);
+        code.open(&format!("pub mod {} {{", identifier(&self.service.name)));
+        code.line("#![allow(clippy::all)]");
+
+        let mut classes: BTreeMap<&str, ClassMembers> = BTreeMap::new();
+        for class in &self.service.classes {
+            classes.insert(
+                class.name.as_str(),
+                ClassMembers {
+                    documentation: class.documentation.as_str(),
+                    members: Vec::new(),
+                },
+            );
+        }
+        let mut procedures = Vec::new();
+        for procedure in sorted_procedures(&self.service.procedures) {
+            let member = Member::parse(&procedure.name);
+            match member.class() {
+                Some(class) => classes
+                    .get_mut(class)
+                    .ok_or_else(|| {
+                        Error::Definitions(format!(
+                            "{}.{} is a member of unknown class {}",
+                            self.service.name, procedure.name, class
+                        ))
+                    })?
+                    .members
+                    .push((procedure, member)),
+                None => procedures.push((procedure, member)),
+            }
+        }
+
+        for (name, class) in &classes {
+            code.line("");
+            code.open("::krpc::remote_object! {");
+            code.doc(class.documentation);
+            code.line(&format!("pub struct {};", name));
+            code.close("}");
+        }
+
+        let mut enumerations: Vec<&Enumeration> = self.service.enumerations.iter().collect();
+        enumerations.sort_by(|a, b| a.name.cmp(&b.name));
+        for enumeration in enumerations {
+            code.line("");
+            self.write_enumeration(code, enumeration)?;
+        }
+
+        for (procedure, member) in procedures {
+            code.line("");
+            self.write_function(code, procedure, member)?;
+        }
+
+        for (name, class) in classes {
+            if class.members.is_empty() {
+                continue;
+            }
+            code.line("");
+            code.open(&format!("impl {} {{", name));
+            for (i, (procedure, member)) in class.members.into_iter().enumerate() {
+                if i > 0 {
+                    code.line("");
+                }
+                self.write_function(code, procedure, member)?;
+            }
+            code.close("}");
+        }
+
+        code.close("}");
+        Ok(())
+    }
+
+    fn write_enumeration(&self, code: &mut Code, enumeration: &Enumeration) -> Result<()> {
+        if enumeration.values.is_empty() {
+            return Err(Error::Definitions(format!(
+                "enumeration {}.{} has no values",
+                self.service.name, enumeration.name
+            )));
+        }
+        code.open("::krpc::remote_enum! {");
+        code.doc(&enumeration.documentation);
+        code.open(&format!("pub enum {} {{", enumeration.name));
+        for value in &enumeration.values {
+            code.doc(&value.documentation);
+            code.line(&format!("{} = {},", value.name, value.value));
+        }
+        code.close("}");
+        code.close("}");
+        Ok(())
+    }
+
+    /// Writes a function that builds the call to `procedure`. Instance
+    /// members take the object they are called on as `&self`.
+    fn write_function(&self, code: &mut Code, procedure: &Procedure, member: Member) -> Result<()> {
+        let has_receiver = matches!(
+            member,
+            Member::ClassMethod { .. }
+                | Member::ClassPropertyGetter { .. }
+                | Member::ClassPropertySetter { .. }
+        );
+        let skip = usize::from(has_receiver);
+        if procedure.parameters.len() < skip {
+            return Err(Error::Definitions(format!(
+                "{}.{} has no parameter for the object it is called on",
+                self.service.name, procedure.name
+            )));
+        }
+        let arguments = procedure
+            .parameters
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(position, parameter)| {
+                let (typ, by_reference) = self.parameter_type(type_of(procedure, parameter)?)?;
+                Ok(Argument {
+                    name: identifier(&parameter.name),
+                    position,
+                    typ,
+                    by_reference,
+                    parameter,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let return_type = match &procedure.return_type {
+            Some(typ) if procedure.return_is_nullable && typ.code() == TypeCode::Class => {
+                format!("Option<{}>", self.rust_type(typ)?)
+            }
+            Some(typ) => self.rust_type(typ)?,
+            None => "()".to_string(),
+        };
+
+        code.doc(&procedure.documentation);
+        let defaults = arguments
+            .iter()
+            .filter(|argument| argument.optional())
+            .map(|argument| self.default_doc(procedure, argument))
+            .collect::<Result<Vec<_>>>()?;
+        if !defaults.is_empty() {
+            if !procedure.documentation.trim().is_empty() {
+                code.line("///");
+            }
+            code.markdown(&defaults.join("\n"));
+        }
+
+        let mut parameters: Vec<String> = Vec::new();
+        if has_receiver {
+            parameters.push("&self".to_string());
+        }
+        parameters.extend(arguments.iter().map(Argument::signature));
+        code.open(&format!(
+            "pub fn {}({}) -> ::krpc::Call<{}> {{",
+            member.function_name(),
+            parameters.join(", "),
+            return_type
+        ));
+
+        let mut call = vec![format!(
+            "::krpc::Call::new({:?}, {:?})",
+            self.service.name, procedure.name
+        )];
+        if has_receiver {
+            call.push(".arg_at(0, self)".to_string());
+        }
+        for argument in arguments.iter().filter(|argument| !argument.optional()) {
+            call.push(format!(
+                ".arg_at({}, {})",
+                argument.position,
+                argument.value()
+            ));
+        }
+        let optional: Vec<&Argument> = arguments.iter().filter(|a| a.optional()).collect();
+        if optional.is_empty() {
+            write_chain(code, "", &call, "");
+        } else {
+            write_chain(code, "let mut call = ", &call, ";");
+            for argument in optional {
+                code.open(&format!("if let Some({0}) = {0} {{", argument.name));
+                code.line(&format!(
+                    "call = call.arg_at({}, {});",
+                    argument.position,
+                    argument.value()
+                ));
+                code.close("}");
+            }
+            code.line("call");
+        }
+        code.close("}");
+        Ok(())
+    }
+
+    /// The sentence documenting what passing `None` for an optional argument
+    /// does.
+    fn default_doc(&self, procedure: &Procedure, argument: &Argument) -> Result<String> {
+        let typ = type_of(procedure, argument.parameter)?;
+        Ok(
+            match self.default_literal(typ, &argument.parameter.default_value) {
+                Some(literal) => {
+                    format!("`{}` defaults to `{}` when `None`.", argument.name, literal)
+                }
+                None => format!(
+                    "`{}` takes the server's default value when `None`.",
+                    argument.name
+                ),
+            },
+        )
+    }
+
+    /// The default value of a parameter written as Rust, for the types that
+    /// have a literal.
+    fn default_literal(&self, typ: &Type, value: &[u8]) -> Option<String> {
+        match typ.code() {
+            TypeCode::Double => codec::decode::<f64>(value).ok().map(|v| format!("{:?}", v)),
+            TypeCode::Float => codec::decode::<f32>(value).ok().map(|v| format!("{:?}", v)),
+            TypeCode::Sint32 => codec::decode::<i32>(value).ok().map(|v| v.to_string()),
+            TypeCode::Sint64 => codec::decode::<i64>(value).ok().map(|v| v.to_string()),
+            TypeCode::Uint32 => codec::decode::<u32>(value).ok().map(|v| v.to_string()),
+            TypeCode::Uint64 => codec::decode::<u64>(value).ok().map(|v| v.to_string()),
+            TypeCode::Bool => codec::decode::<bool>(value).ok().map(|v| v.to_string()),
+            TypeCode::String => codec::decode::<String>(value)
+                .ok()
+                .map(|v| format!("{:?}", v)),
+            TypeCode::Enumeration => {
+                let value = codec::decode::<i32>(value).ok()?;
+                let enumeration = self
+                    .services
+                    .services
+                    .iter()
+                    .find(|service| service.name == typ.service)?
+                    .enumerations
+                    .iter()
+                    .find(|enumeration| enumeration.name == typ.name)?;
+                let variant = enumeration.values.iter().find(|v| v.value == value)?;
+                Some(format!("{}::{}", typ.name, variant.name))
+            }
+            _ => None,
+        }
+    }
+
+    /// The Rust type that a value of `typ` is decoded into.
+    fn rust_type(&self, typ: &Type) -> Result<String> {
+        let generic = |count: usize| -> Result<Vec<String>> {
+            if typ.types.len() != count {
+                return Err(Error::Definitions(format!(
+                    "{:?} type has {} type parameters, expected {}",
+                    typ.code(),
+                    typ.types.len(),
+                    count
+                )));
+            }
+            typ.types.iter().map(|t| self.rust_type(t)).collect()
+        };
+        Ok(match typ.code() {
+            TypeCode::None => "()".to_string(),
+            TypeCode::Double => "f64".to_string(),
+            TypeCode::Float => "f32".to_string(),
+            TypeCode::Sint32 => "i32".to_string(),
+            TypeCode::Sint64 => "i64".to_string(),
+            TypeCode::Uint32 => "u32".to_string(),
+            TypeCode::Uint64 => "u64".to_string(),
+            TypeCode::Bool => "bool".to_string(),
+            TypeCode::String => "String".to_string(),
+            TypeCode::Bytes => "::krpc::Bytes".to_string(),
+            TypeCode::Class | TypeCode::Enumeration => self.type_path(typ)?,
+            TypeCode::Tuple => {
+                let types = typ
+                    .types
+                    .iter()
+                    .map(|t| self.rust_type(t))
+                    .collect::<Result<Vec<_>>>()?;
+                match types.len() {
+                    1 => format!("({},)", types[0]),
+                    2..=8 => format!("({})", types.join(", ")),
+                    n => {
+                        return Err(Error::Definitions(format!(
+                            "tuple of {} values, expected 1 to 8",
+                            n
+                        )))
+                    }
+                }
+            }
+            TypeCode::List => format!("Vec<{}>", generic(1)?[0]),
+            TypeCode::Set => format!("::std::collections::HashSet<{}>", generic(1)?[0]),
+            TypeCode::Dictionary => {
+                let types = generic(2)?;
+                format!("::std::collections::HashMap<{}, {}>", types[0], types[1])
+            }
+            TypeCode::ProcedureCall => "::krpc::schema::ProcedureCall".to_string(),
+            TypeCode::Stream => "::krpc::schema::Stream".to_string(),
+            TypeCode::Event => "::krpc::schema::Event".to_string(),
+            TypeCode::Status => "::krpc::schema::Status".to_string(),
+            TypeCode::Services => "::krpc::schema::Services".to_string(),
+        })
+    }
+
+    /// The type a parameter of type `typ` is passed as, and whether it is a
+    /// reference. Strings, bytes, collections and messages are borrowed.
+    fn parameter_type(&self, typ: &Type) -> Result<(String, bool)> {
+        Ok(match typ.code() {
+            TypeCode::String => ("&str".to_string(), true),
+            TypeCode::Bytes => ("&[u8]".to_string(), true),
+            TypeCode::Double
+            | TypeCode::Float
+            | TypeCode::Sint32
+            | TypeCode::Sint64
+            | TypeCode::Uint32
+            | TypeCode::Uint64
+            | TypeCode::Bool
+            | TypeCode::Class
+            | TypeCode::Enumeration => (self.rust_type(typ)?, false),
+            _ => (format!("&{}", self.rust_type(typ)?), true),
+        })
+    }
+
+    /// The path to a class or enumeration, from within the module of the
+    /// service being generated.
+    fn type_path(&self, typ: &Type) -> Result<String> {
+        if typ.service == self.service.name {
+            return Ok(typ.name.clone());
+        }
+        if !self.services.services.iter().any(|s| s.name == typ.service) {
+            return Err(Error::Definitions(format!(
+                "{} refers to {}.{}, but service {} is not being generated",
+                self.service.name, typ.service, typ.name, typ.service
+            )));
+        }
+        Ok(format!("super::{}::{}", identifier(&typ.service), typ.name))
+    }
+}
+
+fn sorted_procedures(procedures: &[Procedure]) -> Vec<&Procedure> {
+    let mut sorted: Vec<&Procedure> = procedures.iter().collect();
+    sorted.sort_by_key(|procedure| {
+        let function = Member::parse(&procedure.name).function_name();
+        (function, procedure.name.as_str())
+    });
+    sorted
+}
+
+fn type_of<'p>(procedure: &Procedure, parameter: &'p Parameter) -> Result<&'p Type> {
+    parameter.r#type.as_ref().ok_or_else(|| {
+        Error::Definitions(format!(
+            "parameter {} of {} has no type",
+            parameter.name, procedure.name
+        ))
+    })
+}
+
+/// Writes a method chain, one call per line after the first.
+fn write_chain(code: &mut Code, prefix: &str, chain: &[String], suffix: &str) {
+    let last = chain.len() - 1;
+    for (i, link) in chain.iter().enumerate() {
+        let line = format!(
+            "{}{}{}",
+            if i == 0 { prefix } else { "    " },
+            link,
+            if i == last { suffix } else { "" }
+        );
+        code.line(&line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions;
+    use claim::{assert_matches, assert_ok};
+
+    const DEFINITIONS: &str = r#"{
+        "TestService": {
+            "id": 1,
+            "documentation": "<doc><summary>A test service.</summary></doc>",
+            "procedures": {
+                "get_Name": {
+                    "id": 1,
+                    "parameters": [],
+                    "return_type": {"code": "STRING"},
+                    "return_is_nullable": false,
+                    "documentation": "<doc><summary>The name.</summary></doc>"
+                },
+                "set_Name": {
+                    "id": 2,
+                    "parameters": [{"name": "value", "type": {"code": "STRING"}}],
+                    "documentation": ""
+                },
+                "CreateTestObject": {
+                    "id": 3,
+                    "parameters": [{"name": "value", "type": {"code": "STRING"}}],
+                    "return_type": {"code": "CLASS", "service": "TestService", "name": "TestClass"},
+                    "return_is_nullable": true,
+                    "documentation": ""
+                },
+                "EnumDefaultArg": {
+                    "id": 4,
+                    "parameters": [{
+                        "name": "x",
+                        "type": {"code": "ENUMERATION", "service": "TestService", "name": "TestEnum"},
+                        "default_value": "Ag=="
+                    }],
+                    "return_type": {"code": "ENUMERATION", "service": "TestService", "name": "TestEnum"},
+                    "return_is_nullable": false,
+                    "documentation": ""
+                },
+                "Increment": {
+                    "id": 5,
+                    "parameters": [
+                        {"name": "l", "type": {"code": "LIST", "types": [{"code": "SINT32"}]}},
+                        {"name": "d", "type": {"code": "DICTIONARY", "types": [
+                            {"code": "STRING"},
+                            {"code": "TUPLE", "types": [{"code": "BOOL"}]}
+                        ]}}
+                    ],
+                    "return_type": {"code": "LIST", "types": [{"code": "SINT32"}]},
+                    "return_is_nullable": false,
+                    "documentation": ""
+                },
+                "TestClass_FloatToString": {
+                    "id": 6,
+                    "parameters": [
+                        {"name": "this", "type": {"code": "CLASS", "service": "TestService", "name": "TestClass"}},
+                        {"name": "x", "type": {"code": "FLOAT"}}
+                    ],
+                    "return_type": {"code": "STRING"},
+                    "return_is_nullable": false,
+                    "documentation": "<doc><summary>Converts <paramref name=\"x\" /> to a string.</summary></doc>"
+                },
+                "TestClass_get_Value": {
+                    "id": 7,
+                    "parameters": [
+                        {"name": "this", "type": {"code": "CLASS", "service": "TestService", "name": "TestClass"}}
+                    ],
+                    "return_type": {"code": "STRING"},
+                    "return_is_nullable": false,
+                    "documentation": ""
+                },
+                "TestClass_static_Create": {
+                    "id": 8,
+                    "parameters": [{"name": "type", "type": {"code": "STRING"}}],
+                    "return_type": {"code": "CLASS", "service": "TestService", "name": "TestClass"},
+                    "return_is_nullable": false,
+                    "documentation": ""
+                }
+            },
+            "classes": {"TestClass": {"documentation": "<doc><summary>A class.</summary></doc>"}},
+            "enumerations": {
+                "TestEnum": {
+                    "documentation": "<doc><summary>An enum.</summary></doc>",
+                    "values": [
+                        {"name": "ValueA", "value": 0, "documentation": "<doc><summary>Value A.</summary></doc>"},
+                        {"name": "ValueB", "value": 1, "documentation": ""}
+                    ]
+                }
+            },
+            "exceptions": {}
+        },
+        "Drawing": {
+            "id": 2,
+            "documentation": "",
+            "procedures": {
+                "AddLine": {
+                    "id": 1,
+                    "parameters": [{
+                        "name": "obj",
+                        "type": {"code": "CLASS", "service": "TestService", "name": "TestClass"},
+                        "default_value": "AA=="
+                    }],
+                    "return_type": {"code": "CLASS", "service": "Drawing", "name": "Line"},
+                    "return_is_nullable": false,
+                    "documentation": ""
+                }
+            },
+            "classes": {"Line": {"documentation": ""}}
+        }
+    }"#;
+
+    const EXPECTED: &str = r##"// Generated by krpc-codegen from the kRPC service definitions. Do not edit.
+
+pub mod drawing {
+    #![allow(clippy::all)]
+
+    ::krpc::remote_object! {
+        pub struct Line;
+    }
+
+    /// `obj` takes the server's default value when `None`.
+    pub fn add_line(obj: Option<super::test_service::TestClass>) -> ::krpc::Call<Line> {
+        let mut call = ::krpc::Call::new("Drawing", "AddLine");
+        if let Some(obj) = obj {
+            call = call.arg_at(0, &obj);
+        }
+        call
+    }
+}
+
+/// A test service.
+pub mod test_service {
+    #![allow(clippy::all)]
+
+    ::krpc::remote_object! {
+        /// A class.
+        pub struct TestClass;
+    }
+
+    ::krpc::remote_enum! {
+        /// An enum.
+        pub enum TestEnum {
+            /// Value A.
+            ValueA = 0,
+            ValueB = 1,
+        }
+    }
+
+    pub fn create_test_object(value: &str) -> ::krpc::Call<Option<TestClass>> {
+        ::krpc::Call::new("TestService", "CreateTestObject")
+            .arg_at(0, value)
+    }
+
+    /// `x` defaults to `TestEnum::ValueB` when `None`.
+    pub fn enum_default_arg(x: Option<TestEnum>) -> ::krpc::Call<TestEnum> {
+        let mut call = ::krpc::Call::new("TestService", "EnumDefaultArg");
+        if let Some(x) = x {
+            call = call.arg_at(0, &x);
+        }
+        call
+    }
+
+    pub fn increment(l: &Vec<i32>, d: &::std::collections::HashMap<String, (bool,)>) -> ::krpc::Call<Vec<i32>> {
+        ::krpc::Call::new("TestService", "Increment")
+            .arg_at(0, l)
+            .arg_at(1, d)
+    }
+
+    /// The name.
+    pub fn name() -> ::krpc::Call<String> {
+        ::krpc::Call::new("TestService", "get_Name")
+    }
+
+    pub fn set_name(value: &str) -> ::krpc::Call<()> {
+        ::krpc::Call::new("TestService", "set_Name")
+            .arg_at(0, value)
+    }
+
+    impl TestClass {
+        pub fn create(r#type: &str) -> ::krpc::Call<TestClass> {
+            ::krpc::Call::new("TestService", "TestClass_static_Create")
+                .arg_at(0, r#type)
+        }
+
+        /// Converts `x` to a string.
+        pub fn float_to_string(&self, x: f32) -> ::krpc::Call<String> {
+            ::krpc::Call::new("TestService", "TestClass_FloatToString")
+                .arg_at(0, self)
+                .arg_at(1, &x)
+        }
+
+        pub fn value(&self) -> ::krpc::Call<String> {
+            ::krpc::Call::new("TestService", "TestClass_get_Value")
+                .arg_at(0, self)
+        }
+    }
+}
+"##;
+
+    #[test]
+    fn generates_bindings() {
+        let services = assert_ok!(definitions::from_json(DEFINITIONS));
+        let code = assert_ok!(generate(&services));
+        assert_eq!(code, EXPECTED);
+    }
+
+    #[test]
+    fn missing_service() {
+        let mut services = assert_ok!(definitions::from_json(DEFINITIONS));
+        services
+            .services
+            .retain(|service| service.name == "Drawing");
+        assert_matches!(generate(&services), Err(Error::Definitions(_)));
+    }
+
+    #[test]
+    fn unknown_class() {
+        let mut services = assert_ok!(definitions::from_json(DEFINITIONS));
+        services.services[1].classes.clear();
+        assert_matches!(generate(&services), Err(Error::Definitions(_)));
+    }
+}
@@ -0,0 +1,53 @@
+//! Generates typed Rust bindings for kRPC services, as
+//! `tools/krpctools/krpctools/clientgen` does for the other clients.
+//!
+//! The services are described by a `Services` message, read either from the
+//! JSON definitions written by `tools/ServiceDefinitions` or from a running
+//! server through `KRPC.GetServices`. Each service becomes a module holding:
+//!
+//! * a [`remote_object!`](krpc::remote_object) struct for each class,
+//! * a [`remote_enum!`](krpc::remote_enum) enum for each enumeration,
+//! * a function for each procedure, or a method of its class, that returns
+//!   the [`Call`](krpc::Call) for it, so that it can be invoked, streamed or
+//!   batched with any client.
+//!
+//! Procedures are named following their conventions: `get_UT` becomes
+//! `ut()`, `set_ActiveVessel` becomes `set_active_vessel(value)`,
+//! `Vessel_get_Name` becomes `Vessel::name(&self)` and
+//! `Vessel_static_Create` becomes `Vessel::create()`. Classes returned by
+//! procedures whose `return_is_nullable` is set are wrapped in `Option`, and
+//! parameters that have a default value are `Option`s that take the default
+//! when `None`.
+//!
+//! The bindings can be generated by the `krpc-codegen` binary, or from a
+//! build script:
+//!
+//! ```no_run
+//! # fn main() -> krpc_codegen::Result<()> {
+//! let json = std::fs::read_to_string("SpaceCenter.json")?;
+//! let services = krpc_codegen::definitions::from_json(&json)?;
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! std::fs::write(
+//!     format!("{}/services.rs", out_dir),
+//!     krpc_codegen::generate(&services)?,
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! and then used with `include!(concat!(env!("OUT_DIR"), "/services.rs"))`:
+//!
+//! ```ignore
+//! let vessel = client.call(&space_center::active_vessel())?;
+//! let flight = client.call(&vessel.flight(None))?;
+//! let altitude = client.add_stream(&flight.mean_altitude())?;
+//! ```
+
+pub mod definitions;
+mod docs;
+pub mod error;
+pub mod generator;
+pub mod names;
+
+pub use error::{Error, Result};
+pub use generator::generate;
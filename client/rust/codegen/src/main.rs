@@ -0,0 +1,114 @@
+use std::process::ExitCode;
+
+use krpc::schema::Services;
+use krpc::{Client, DEFAULT_RPC_PORT};
+use krpc_codegen::{definitions, generate, Error, Result};
+
+const USAGE: &str = "\
+usage: krpc-codegen [-h] [-o PATH] [-s SERVICE]... (DEFINITIONS... | -a ADDRESS [-p PORT])
+
+Generate Rust bindings for kRPC services
+
+  DEFINITIONS            JSON service definitions written by ServiceDefinitions
+  -a, --address ADDRESS  get the services from the server at ADDRESS instead
+  -p, --port PORT        the server's RPC port (default 50000)
+  -s, --service SERVICE  only generate SERVICE; may be given more than once
+  -o, --output PATH      write the bindings to PATH instead of standard output
+  -h, --help             show this help message and exit";
+
+#[derive(Default)]
+struct Args {
+    definitions: Vec<String>,
+    address: Option<String>,
+    port: Option<u16>,
+    services: Vec<String>,
+    output: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value", option))
+        };
+        match arg.as_str() {
+            "-a" | "--address" => parsed.address = Some(value(&arg)?),
+            "-p" | "--port" => {
+                let port = value(&arg)?;
+                parsed.port = Some(port.parse().map_err(|_| format!("invalid port {}", port))?);
+            }
+            "-s" | "--service" => parsed.services.push(value(&arg)?),
+            "-o" | "--output" => parsed.output = Some(value(&arg)?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => parsed.definitions.push(arg),
+        }
+    }
+    match (&parsed.address, parsed.definitions.is_empty()) {
+        (None, true) => Err("no definitions given".to_string()),
+        (Some(_), false) => Err("definitions and --address cannot be used together".to_string()),
+        _ => Ok(parsed),
+    }
+}
+
+fn load(args: &Args) -> Result<Services> {
+    let mut services = match &args.address {
+        Some(address) => {
+            let port = args.port.unwrap_or(DEFAULT_RPC_PORT);
+            let client = Client::connect("krpc-codegen", address, port, None)?;
+            definitions::from_server(&client)?
+        }
+        None => {
+            let mut services = Services::default();
+            for path in &args.definitions {
+                let json = std::fs::read_to_string(path)?;
+                services
+                    .services
+                    .extend(definitions::from_json(&json)?.services);
+            }
+            services
+        }
+    };
+    if !args.services.is_empty() {
+        for name in &args.services {
+            if !services.services.iter().any(|s| &s.name == name) {
+                return Err(Error::Definitions(format!("service {} not found", name)));
+            }
+        }
+        services
+            .services
+            .retain(|s| args.services.contains(&s.name));
+    }
+    Ok(services)
+}
+
+fn run(args: &Args) -> Result<()> {
+    let code = generate(&load(args)?)?;
+    match &args.output {
+        Some(path) => std::fs::write(path, code)?,
+        None => print!("{}", code),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(args.into_iter()) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
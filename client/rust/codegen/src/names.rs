@@ -0,0 +1,227 @@
+//! The naming conventions of procedures, and their Rust equivalents.
+//!
+//! The server names procedures after what they do, as described in
+//! `doc/src/communication-protocols/messages.rst`: `GetStatus` is a plain
+//! procedure, `get_Paused` and `set_Paused` access a service property,
+//! `Vessel_Recover` is a method of the `Vessel` class, `Vessel_get_Name` and
+//! `Vessel_set_Name` access one of its properties, and
+//! `Vessel_static_Create` is a static method.
+
+/// What a procedure is, going by its name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Member<'a> {
+    Procedure { name: &'a str },
+    PropertyGetter { property: &'a str },
+    PropertySetter { property: &'a str },
+    ClassMethod { class: &'a str, name: &'a str },
+    ClassStaticMethod { class: &'a str, name: &'a str },
+    ClassPropertyGetter { class: &'a str, property: &'a str },
+    ClassPropertySetter { class: &'a str, property: &'a str },
+}
+
+impl<'a> Member<'a> {
+    /// Classifies the procedure named `name`.
+    pub fn parse(name: &'a str) -> Self {
+        if let Some(property) = name.strip_prefix("get_") {
+            return Member::PropertyGetter { property };
+        }
+        if let Some(property) = name.strip_prefix("set_") {
+            return Member::PropertySetter { property };
+        }
+        let (class, rest) = match name.split_once('_') {
+            Some(parts) => parts,
+            None => return Member::Procedure { name },
+        };
+        let member = rest.rsplit('_').next().unwrap_or(rest);
+        if name.contains("_static_") {
+            Member::ClassStaticMethod {
+                class,
+                name: member,
+            }
+        } else if name.contains("_get_") {
+            Member::ClassPropertyGetter {
+                class,
+                property: member,
+            }
+        } else if name.contains("_set_") {
+            Member::ClassPropertySetter {
+                class,
+                property: member,
+            }
+        } else {
+            Member::ClassMethod {
+                class,
+                name: member,
+            }
+        }
+    }
+
+    /// The class the procedure belongs to, if any.
+    pub fn class(&self) -> Option<&'a str> {
+        match *self {
+            Member::ClassMethod { class, .. }
+            | Member::ClassStaticMethod { class, .. }
+            | Member::ClassPropertyGetter { class, .. }
+            | Member::ClassPropertySetter { class, .. } => Some(class),
+            _ => None,
+        }
+    }
+
+    /// The name of the Rust function the procedure is bound to.
+    pub fn function_name(&self) -> String {
+        match *self {
+            Member::Procedure { name }
+            | Member::ClassMethod { name, .. }
+            | Member::ClassStaticMethod { name, .. } => identifier(name),
+            Member::PropertyGetter { property } | Member::ClassPropertyGetter { property, .. } => {
+                identifier(property)
+            }
+            Member::PropertySetter { property } | Member::ClassPropertySetter { property, .. } => {
+                escape(format!("set_{}", snake_case(property)))
+            }
+        }
+    }
+}
+
+/// Converts a CamelCase name to snake_case, the same way as the Python
+/// client's `krpc.utils.snake_case`, so that both clients name things alike.
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 {
+            let previous = chars[i - 1];
+            let next = chars.get(i + 1).copied();
+            if c == '_'
+                || (c.is_ascii_uppercase()
+                    && (previous.is_ascii_lowercase()
+                        || previous.is_ascii_digit()
+                        || (previous.is_ascii_uppercase()
+                            && next.is_some_and(|n| n.is_ascii_lowercase() || n.is_ascii_digit()))))
+            {
+                result.push('_');
+            }
+        }
+        result.push(c.to_ascii_lowercase());
+    }
+    result
+}
+
+/// Words that cannot be used as identifiers, even raw ones.
+const UNRAWABLE: &[&str] = &["crate", "self", "super"];
+
+/// Keywords that must be written as raw identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// The snake_case Rust identifier for `name`, escaped if it is a keyword.
+pub fn identifier(name: &str) -> String {
+    escape(snake_case(name))
+}
+
+fn escape(name: String) -> String {
+    if UNRAWABLE.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_like_python_client() {
+        let cases = [
+            ("Server", "server"),
+            ("MyServer", "my_server"),
+            ("Int32ToString", "int32_to_string"),
+            ("32ToString", "32_to_string"),
+            ("ToInt32", "to_int32"),
+            ("HTTPS", "https"),
+            ("HTTPServer", "http_server"),
+            ("MyHTTPServer", "my_http_server"),
+            ("HTTPServerSSL", "http_server_ssl"),
+            ("_HTTPServer", "_http_server"),
+            ("HTTP_Server", "http__server"),
+            ("foobar", "foobar"),
+            ("foo_bar", "foo__bar"),
+            ("_foobar", "_foobar"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(snake_case(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn escapes_keywords() {
+        assert_eq!(identifier("Type"), "r#type");
+        assert_eq!(identifier("Self"), "self_");
+        assert_eq!(identifier("MeanAltitude"), "mean_altitude");
+    }
+
+    #[test]
+    fn classifies_procedures() {
+        let cases = [
+            (
+                "GetStatus",
+                Member::Procedure { name: "GetStatus" },
+                "get_status",
+            ),
+            ("get_UT", Member::PropertyGetter { property: "UT" }, "ut"),
+            (
+                "set_ActiveVessel",
+                Member::PropertySetter {
+                    property: "ActiveVessel",
+                },
+                "set_active_vessel",
+            ),
+            (
+                "Vessel_Recover",
+                Member::ClassMethod {
+                    class: "Vessel",
+                    name: "Recover",
+                },
+                "recover",
+            ),
+            (
+                "Vessel_static_Create",
+                Member::ClassStaticMethod {
+                    class: "Vessel",
+                    name: "Create",
+                },
+                "create",
+            ),
+            (
+                "Vessel_get_Name",
+                Member::ClassPropertyGetter {
+                    class: "Vessel",
+                    property: "Name",
+                },
+                "name",
+            ),
+            (
+                "Vessel_set_Type",
+                Member::ClassPropertySetter {
+                    class: "Vessel",
+                    property: "Type",
+                },
+                "set_type",
+            ),
+        ];
+        for (name, member, function) in cases {
+            assert_eq!(Member::parse(name), member);
+            assert_eq!(member.function_name(), function);
+        }
+        assert_eq!(Member::parse("Vessel_get_Name").class(), Some("Vessel"));
+        assert_eq!(Member::parse("get_UT").class(), None);
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+
+/// The schema shared with the server and all other clients.
+const PROTO_DIR: &str = "../../../protobuf";
+/// Where release archives put a copy of the schema, outside of the repository.
+const PACKAGED_PROTO_DIR: &str = "protobuf";
+const PROTO_FILE: &str = "krpc.proto";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let packaged = PathBuf::from(PACKAGED_PROTO_DIR);
+    let proto_dir = if packaged.join(PROTO_FILE).exists() {
+        packaged
+    } else {
+        PathBuf::from(PROTO_DIR)
+    };
+    let proto_file = proto_dir.join(PROTO_FILE);
+    println!("cargo:rerun-if-changed={}", proto_file.display());
+
+    // protox parses the schema in pure Rust, so building the crate does not
+    // require `protoc` to be installed.
+    let file_descriptors = protox::compile([&proto_file], [&proto_dir])?;
+    prost_build::Config::new()
+        .bytes(["."])
+        .compile_fds(file_descriptors)?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Storage for the message being sent or received.
+
+use alloc::vec::Vec;
+
+use crate::error::Result;
+
+/// A resizable run of bytes that holds one message at a time.
+///
+/// `Vec<u8>` grows to fit any message. With the `heapless` feature a
+/// `heapless::Vec<u8, N>` can be used instead, which never allocates and
+/// fails with `Error::MessageTooLarge` on a message bigger than `N` bytes.
+pub trait Buffer {
+    /// Resizes the buffer to exactly `len` bytes. The contents are
+    /// unspecified afterwards.
+    fn resize(&mut self, len: usize) -> Result<()>;
+
+    fn as_slice(&self) -> &[u8];
+
+    fn as_mut_slice(&mut self) -> &mut [u8];
+}
+
+impl Buffer for Vec<u8> {
+    fn resize(&mut self, len: usize) -> Result<()> {
+        Vec::resize(self, len, 0);
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> Buffer for heapless::Vec<u8, N> {
+    fn resize(&mut self, len: usize) -> Result<()> {
+        heapless::Vec::resize(self, len, 0).map_err(|_| crate::Error::MessageTooLarge {
+            size: len as u64,
+            max: N,
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl<B: Buffer + ?Sized> Buffer for &mut B {
+    fn resize(&mut self, len: usize) -> Result<()> {
+        (**self).resize(len)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+}
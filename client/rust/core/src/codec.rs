@@ -0,0 +1,860 @@
+//! Encoding and decoding of argument and return values.
+//!
+//! Values are encoded the same way as `server/src/Server/ProtocolBuffers/Encoder.cs`:
+//!
+//! | kRPC type               | Rust type                          | Encoding                  |
+//! |-------------------------|------------------------------------|---------------------------|
+//! | `DOUBLE`, `FLOAT`       | `f64`, `f32`                       | little-endian fixed width |
+//! | `SINT32`, `SINT64`      | `i32`, `i64`                       | zigzag varint             |
+//! | `UINT32`, `UINT64`      | `u32`, `u64`                       | varint                    |
+//! | `BOOL`                  | `bool`                             | varint                    |
+//! | `STRING`, `BYTES`       | `String`, `Bytes`                  | length-prefixed           |
+//! | `CLASS`                 | [`RemoteObject`] types             | `u64` object id           |
+//! | `ENUMERATION`           | [`RemoteEnum`] types               | `sint32` value            |
+//! | `TUPLE`                 | `(A,)` to `(A, B, C, D, E, F, G, H)` | `Tuple` message         |
+//! | `LIST`                  | `Vec<T>`                           | `List` message            |
+//! | `SET`                   | `BTreeSet<T>`, `HashSet<T>`        | `Set` message             |
+//! | `DICTIONARY`            | `BTreeMap<K, V>`, `HashMap<K, V>`  | `Dictionary` message      |
+//! | `STREAM`, `EVENT`, `STATUS`, `SERVICES`, `PROCEDURE_CALL` | the [`schema`] messages | the message itself |
+//!
+//! A class value that may be null is represented as `Option<T>`, with null
+//! encoded as object id 0. `HashSet` and `HashMap` need the `std` feature.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+
+use bytes::{Buf, Bytes};
+use prost::encoding::{decode_varint, encode_varint};
+
+use crate::error::{Error, Result};
+use crate::schema;
+
+/// A value that can be passed to a remote procedure.
+pub trait Encode {
+    /// Appends the encoded value to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+/// A value that can be returned from a remote procedure.
+pub trait Decode: Sized {
+    /// Decodes a value from the whole of `data`.
+    fn decode(data: &[u8]) -> Result<Self>;
+}
+
+/// An instance of a class defined by a service, which the server refers to
+/// by object id. Implemented by [`remote_object!`](crate::remote_object).
+pub trait RemoteObject: Sized {
+    fn from_id(id: u64) -> Self;
+    fn id(&self) -> u64;
+}
+
+/// An enumeration defined by a service. Implemented by
+/// [`remote_enum!`](crate::remote_enum).
+pub trait RemoteEnum: Sized {
+    fn from_value(value: i32) -> Option<Self>;
+    fn value(&self) -> i32;
+}
+
+/// Encodes `value` into a new buffer.
+pub fn encode<T: Encode + ?Sized>(value: &T) -> Bytes {
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+    buf.into()
+}
+
+/// Decodes a value of type `T` from `data`.
+pub fn decode<T: Decode>(data: &[u8]) -> Result<T> {
+    T::decode(data)
+}
+
+fn encode_zigzag32(value: i32) -> u64 {
+    u64::from(((value << 1) ^ (value >> 31)) as u32)
+}
+
+fn decode_zigzag32(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+fn encode_zigzag64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn decode_zigzag64(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn encode_message<M: prost::Message>(message: &M, buf: &mut Vec<u8>) {
+    message
+        .encode(buf)
+        .expect("Vec<u8> grows to fit the message");
+}
+
+fn decode_message<M: prost::Message + Default>(data: &[u8]) -> Result<M> {
+    Ok(M::decode(data)?)
+}
+
+/// Checks that decoding a value used up all of its data.
+fn finish(data: &[u8]) -> Result<()> {
+    if data.has_remaining() {
+        Err(Error::Encoding(format!(
+            "{} unexpected trailing bytes",
+            data.remaining()
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn take_fixed<const N: usize>(data: &[u8]) -> Result<[u8; N]> {
+    <[u8; N]>::try_from(data)
+        .map_err(|_| Error::Encoding(format!("expected {} bytes, got {}", N, data.len())))
+}
+
+fn decode_single_varint(data: &[u8]) -> Result<u64> {
+    let mut data = data;
+    let value = decode_varint(&mut data)?;
+    finish(data)?;
+    Ok(value)
+}
+
+/// Decodes a length-prefixed run of bytes.
+fn decode_length_prefixed(data: &[u8]) -> Result<&[u8]> {
+    let mut rest = data;
+    let length = decode_varint(&mut rest)?;
+    if length != rest.len() as u64 {
+        return Err(Error::Encoding(format!(
+            "length prefix of {} does not match {} bytes of data",
+            length,
+            rest.len()
+        )));
+    }
+    Ok(rest)
+}
+
+impl Encode for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for f64 {
+    fn decode(data: &[u8]) -> Result<Self> {
+        Ok(f64::from_le_bytes(take_fixed(data)?))
+    }
+}
+
+impl Encode for f32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for f32 {
+    fn decode(data: &[u8]) -> Result<Self> {
+        Ok(f32::from_le_bytes(take_fixed(data)?))
+    }
+}
+
+impl Encode for i32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(encode_zigzag32(*self), buf);
+    }
+}
+
+impl Decode for i32 {
+    fn decode(data: &[u8]) -> Result<Self> {
+        let value = u32::try_from(decode_single_varint(data)?)
+            .map_err(|_| Error::Encoding("sint32 value out of range".to_string()))?;
+        Ok(decode_zigzag32(value))
+    }
+}
+
+impl Encode for i64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(encode_zigzag64(*self), buf);
+    }
+}
+
+impl Decode for i64 {
+    fn decode(data: &[u8]) -> Result<Self> {
+        Ok(decode_zigzag64(decode_single_varint(data)?))
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(u64::from(*self), buf);
+    }
+}
+
+impl Decode for u32 {
+    fn decode(data: &[u8]) -> Result<Self> {
+        u32::try_from(decode_single_varint(data)?)
+            .map_err(|_| Error::Encoding("uint32 value out of range".to_string()))
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(*self, buf);
+    }
+}
+
+impl Decode for u64 {
+    fn decode(data: &[u8]) -> Result<Self> {
+        decode_single_varint(data)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(u64::from(*self), buf);
+    }
+}
+
+impl Decode for bool {
+    fn decode(data: &[u8]) -> Result<Self> {
+        Ok(decode_single_varint(data)? != 0)
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(self.len() as u64, buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode(buf);
+    }
+}
+
+impl Decode for String {
+    fn decode(data: &[u8]) -> Result<Self> {
+        let data = decode_length_prefixed(data)?;
+        String::from_utf8(data.to_vec()).map_err(|e| Error::Encoding(e.to_string()))
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(self.len() as u64, buf);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl Encode for Bytes {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_ref().encode(buf);
+    }
+}
+
+impl Decode for Bytes {
+    fn decode(data: &[u8]) -> Result<Self> {
+        Ok(Bytes::copy_from_slice(decode_length_prefixed(data)?))
+    }
+}
+
+/// Procedures that return nothing have an empty result value.
+impl Decode for () {
+    fn decode(_data: &[u8]) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+}
+
+impl<T: RemoteObject> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint(self.as_ref().map_or(0, RemoteObject::id), buf);
+    }
+}
+
+impl<T: RemoteObject> Decode for Option<T> {
+    fn decode(data: &[u8]) -> Result<Self> {
+        match u64::decode(data)? {
+            0 => Ok(None),
+            id => Ok(Some(T::from_id(id))),
+        }
+    }
+}
+
+/// Encodes each item of a collection into the `items` of a wrapper message.
+fn encode_items<'a, T, I>(items: I) -> Vec<Bytes>
+where
+    T: Encode + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    items.into_iter().map(|item| encode(item)).collect()
+}
+
+fn decode_items<T: Decode, C: FromIterator<T>>(items: Vec<Bytes>) -> Result<C> {
+    items.iter().map(|item| T::decode(item)).collect()
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_message(
+            &schema::List {
+                items: encode_items(self),
+            },
+            buf,
+        );
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(data: &[u8]) -> Result<Self> {
+        decode_items(decode_message::<schema::List>(data)?.items)
+    }
+}
+
+/// Sets and dictionaries are encoded in order of their encoded items, so that
+/// equal collections always encode to the same bytes.
+#[cfg(feature = "std")]
+fn sorted(mut items: Vec<Bytes>) -> Vec<Bytes> {
+    items.sort();
+    items
+}
+
+#[cfg(feature = "std")]
+impl<T: Encode> Encode for HashSet<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_message(
+            &schema::Set {
+                items: sorted(encode_items(self)),
+            },
+            buf,
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Decode + Eq + Hash> Decode for HashSet<T> {
+    fn decode(data: &[u8]) -> Result<Self> {
+        decode_items(decode_message::<schema::Set>(data)?.items)
+    }
+}
+
+impl<T: Encode> Encode for BTreeSet<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_message(
+            &schema::Set {
+                items: encode_items(self),
+            },
+            buf,
+        );
+    }
+}
+
+impl<T: Decode + Ord> Decode for BTreeSet<T> {
+    fn decode(data: &[u8]) -> Result<Self> {
+        decode_items(decode_message::<schema::Set>(data)?.items)
+    }
+}
+
+fn encode_entries<'a, K, V, I>(entries: I) -> Vec<schema::DictionaryEntry>
+where
+    K: Encode + 'a,
+    V: Encode + 'a,
+    I: IntoIterator<Item = (&'a K, &'a V)>,
+{
+    entries
+        .into_iter()
+        .map(|(key, value)| schema::DictionaryEntry {
+            key: encode(key),
+            value: encode(value),
+        })
+        .collect()
+}
+
+fn decode_entries<K: Decode, V: Decode, C: FromIterator<(K, V)>>(data: &[u8]) -> Result<C> {
+    decode_message::<schema::Dictionary>(data)?
+        .entries
+        .iter()
+        .map(|entry| Ok((K::decode(&entry.key)?, V::decode(&entry.value)?)))
+        .collect()
+}
+
+#[cfg(feature = "std")]
+impl<K: Encode, V: Encode> Encode for HashMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut entries = encode_entries(self);
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        encode_message(&schema::Dictionary { entries }, buf);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Decode + Eq + Hash, V: Decode> Decode for HashMap<K, V> {
+    fn decode(data: &[u8]) -> Result<Self> {
+        decode_entries(data)
+    }
+}
+
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_message(
+            &schema::Dictionary {
+                entries: encode_entries(self),
+            },
+            buf,
+        );
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode(data: &[u8]) -> Result<Self> {
+        decode_entries(data)
+    }
+}
+
+macro_rules! tuple_codec {
+    ($len:expr; $($name:ident $index:tt),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                encode_message(
+                &schema::Tuple {
+                    items: alloc::vec![$(encode(&self.$index)),+],
+                },
+                buf,
+            );
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(data: &[u8]) -> Result<Self> {
+                let items = decode_message::<schema::Tuple>(data)?.items;
+                if items.len() != $len {
+                    return Err(Error::Encoding(format!(
+                        "tuple has wrong number of elements: expected {}, got {}",
+                        $len,
+                        items.len()
+                    )));
+                }
+                Ok(($($name::decode(&items[$index])?,)+))
+            }
+        }
+    };
+}
+
+tuple_codec!(1; A 0);
+tuple_codec!(2; A 0, B 1);
+tuple_codec!(3; A 0, B 1, C 2);
+tuple_codec!(4; A 0, B 1, C 2, D 3);
+tuple_codec!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_codec!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_codec!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_codec!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+macro_rules! message_codec {
+    ($($message:ty),+) => {
+        $(
+            impl Encode for $message {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    encode_message(self, buf);
+                }
+            }
+
+            impl Decode for $message {
+                fn decode(data: &[u8]) -> Result<Self> {
+                    decode_message(data)
+                }
+            }
+        )+
+    };
+}
+
+message_codec!(
+    schema::ProcedureCall,
+    schema::Stream,
+    schema::Event,
+    schema::Status,
+    schema::Services
+);
+
+/// Declares a struct for a class defined by a service. Values of the struct
+/// are handles to objects that live on the server, identified by object id.
+///
+/// ```
+/// krpc_core::remote_object! {
+///     /// A vessel.
+///     pub struct Vessel;
+/// }
+/// ```
+#[macro_export]
+macro_rules! remote_object {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident;) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        $vis struct $name {
+            id: u64,
+        }
+
+        impl $crate::codec::RemoteObject for $name {
+            fn from_id(id: u64) -> Self {
+                $name { id }
+            }
+
+            fn id(&self) -> u64 {
+                self.id
+            }
+        }
+
+        impl $crate::codec::Encode for $name {
+            fn encode(&self, buf: &mut $crate::__private::Vec<u8>) {
+                $crate::codec::Encode::encode(&self.id, buf);
+            }
+        }
+
+        impl $crate::codec::Decode for $name {
+            fn decode(data: &[u8]) -> $crate::Result<Self> {
+                match <u64 as $crate::codec::Decode>::decode(data)? {
+                    0 => Err($crate::Error::Encoding($crate::__private::ToString::to_string(
+                        concat!("unexpected null ", stringify!($name), " object"),
+                    ))),
+                    id => Ok($name { id }),
+                }
+            }
+        }
+    };
+}
+
+/// Declares an enum for an enumeration defined by a service. Every variant
+/// must have an explicit value.
+///
+/// ```
+/// krpc_core::remote_enum! {
+///     /// The game scene.
+///     pub enum GameScene {
+///         SpaceCenter = 0,
+///         Flight = 1,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! remote_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:expr),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[repr(i32)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant = $value),+
+        }
+
+        impl $crate::codec::RemoteEnum for $name {
+            fn from_value(value: i32) -> Option<Self> {
+                $(if value == $value {
+                    return Some($name::$variant);
+                })+
+                None
+            }
+
+            fn value(&self) -> i32 {
+                *self as i32
+            }
+        }
+
+        impl $crate::codec::Encode for $name {
+            fn encode(&self, buf: &mut $crate::__private::Vec<u8>) {
+                $crate::codec::Encode::encode(&(*self as i32), buf);
+            }
+        }
+
+        impl $crate::codec::Decode for $name {
+            fn decode(data: &[u8]) -> $crate::Result<Self> {
+                let value = <i32 as $crate::codec::Decode>::decode(data)?;
+                <$name as $crate::codec::RemoteEnum>::from_value(value).ok_or_else(|| {
+                    $crate::Error::Encoding($crate::__private::format!(
+                        "{} is not a valid {} value",
+                        value,
+                        stringify!($name)
+                    ))
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_matches, assert_ok};
+
+    fn unhexlify(data: &str) -> Vec<u8> {
+        (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn hexlify(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn check<T>(cases: &[(T, &str)])
+    where
+        T: Encode + Decode + PartialEq + std::fmt::Debug,
+    {
+        for (decoded, encoded) in cases {
+            assert_eq!(
+                hexlify(&encode(decoded)),
+                *encoded,
+                "encoding {:?}",
+                decoded
+            );
+            assert_eq!(&assert_ok!(T::decode(&unhexlify(encoded))), decoded);
+        }
+    }
+
+    crate::remote_object! {
+        struct TestClass;
+    }
+
+    crate::remote_enum! {
+        enum TestEnum {
+            ValueA = 0,
+            ValueB = 1,
+            ValueC = -33,
+        }
+    }
+
+    // The vectors are shared with the Python client's tests, which use this
+    // approximation of pi.
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn double() {
+        check(&[
+            (0.0f64, "0000000000000000"),
+            (-1.0, "000000000000f0bf"),
+            (3.14159265359, "ea2e4454fb210940"),
+            (f64::INFINITY, "000000000000f07f"),
+            (f64::NEG_INFINITY, "000000000000f0ff"),
+        ]);
+        assert_eq!(hexlify(&encode(&f64::NAN)), "000000000000f87f");
+        assert!(assert_ok!(f64::decode(&unhexlify("000000000000f87f"))).is_nan());
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant, clippy::excessive_precision)]
+    fn float() {
+        check(&[
+            (3.14159265359f32, "db0f4940"),
+            (-1.0, "000080bf"),
+            (0.0, "00000000"),
+            (f32::INFINITY, "0000807f"),
+            (f32::NEG_INFINITY, "000080ff"),
+        ]);
+        assert_eq!(hexlify(&encode(&f32::NAN)), "0000c07f");
+        assert!(assert_ok!(f32::decode(&unhexlify("0000c07f"))).is_nan());
+    }
+
+    #[test]
+    fn sint32() {
+        check(&[
+            (0i32, "00"),
+            (1, "02"),
+            (42, "54"),
+            (300, "d804"),
+            (-33, "41"),
+            (2147483647, "feffffff0f"),
+            (-2147483648, "ffffffff0f"),
+        ]);
+    }
+
+    #[test]
+    fn sint64() {
+        check(&[
+            (0i64, "00"),
+            (1, "02"),
+            (42, "54"),
+            (300, "d804"),
+            (1234567890000, "a091d89fee47"),
+            (-33, "41"),
+        ]);
+    }
+
+    #[test]
+    fn uint32() {
+        check(&[(0u32, "00"), (1, "01"), (42, "2a"), (300, "ac02")]);
+        assert_err!(u32::decode(&unhexlify("ffffffffffffffff7f")));
+    }
+
+    #[test]
+    fn uint64() {
+        check(&[
+            (0u64, "00"),
+            (1, "01"),
+            (42, "2a"),
+            (300, "ac02"),
+            (1234567890000, "d088ec8ff723"),
+        ]);
+    }
+
+    #[test]
+    fn boolean() {
+        check(&[(true, "01"), (false, "00")]);
+    }
+
+    #[test]
+    fn string() {
+        check(&[
+            (String::new(), "00"),
+            ("testing".to_string(), "0774657374696e67"),
+            (
+                "One small step for Kerbal-kind!".to_string(),
+                "1f4f6e6520736d616c6c207374657020666f72204b657262616c2d6b696e6421",
+            ),
+            ("\u{2122}".to_string(), "03e284a2"),
+            (
+                "Mystery Goo\u{2122} Containment Unit".to_string(),
+                "1f4d79737465727920476f6fe284a220436f6e7461696e6d656e7420556e6974",
+            ),
+        ]);
+        assert_eq!(hexlify(&encode("\u{2122}")), "03e284a2");
+    }
+
+    #[test]
+    fn bytes() {
+        check(&[
+            (Bytes::new(), "00"),
+            (Bytes::from_static(b"\xba\xda\x55"), "03bada55"),
+            (Bytes::from_static(b"\xde\xad\xbe\xef"), "04deadbeef"),
+        ]);
+    }
+
+    #[test]
+    fn tuple() {
+        check(&[((1u32,), "0a0101")]);
+        check(&[((1u32, "jeb".to_string(), false), "0a01010a04036a65620a0100")]);
+        assert_matches!(
+            <(u32, u32, u32)>::decode(&unhexlify("0a01000a0101")),
+            Err(Error::Encoding(_))
+        );
+    }
+
+    #[test]
+    fn list() {
+        check(&[
+            (Vec::<u32>::new(), ""),
+            (vec![1], "0a0101"),
+            (vec![1, 2, 3, 4], "0a01010a01020a01030a0104"),
+        ]);
+    }
+
+    #[test]
+    fn set() {
+        check(&[
+            (BTreeSet::<u32>::new(), ""),
+            (BTreeSet::from([1]), "0a0101"),
+            (BTreeSet::from([1, 2, 3, 4]), "0a01010a01020a01030a0104"),
+        ]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_set() {
+        check(&[
+            (HashSet::<u32>::new(), ""),
+            (HashSet::from([1]), "0a0101"),
+            (HashSet::from([1, 2, 3, 4]), "0a01010a01020a01030a0104"),
+        ]);
+    }
+
+    #[test]
+    fn dictionary() {
+        check(&[
+            (BTreeMap::<String, u32>::new(), ""),
+            (BTreeMap::from([(String::new(), 0)]), "0a060a0100120100"),
+            (
+                BTreeMap::from([
+                    ("foo".to_string(), 42),
+                    ("bar".to_string(), 365),
+                    ("baz".to_string(), 3),
+                ]),
+                "0a0a0a04036261721202ed020a090a040362617a1201030a090a0403666f6f12012a",
+            ),
+        ]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_map() {
+        check(&[
+            (HashMap::<String, u32>::new(), ""),
+            (HashMap::from([(String::new(), 0)]), "0a060a0100120100"),
+            (
+                HashMap::from([
+                    ("foo".to_string(), 42),
+                    ("bar".to_string(), 365),
+                    ("baz".to_string(), 3),
+                ]),
+                "0a0a0a04036261721202ed020a090a040362617a1201030a090a0403666f6f12012a",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn class() {
+        check(&[(TestClass::from_id(300), "ac02")]);
+        check(&[(Some(TestClass::from_id(300)), "ac02"), (None, "00")]);
+        assert_matches!(TestClass::decode(&unhexlify("00")), Err(Error::Encoding(_)));
+    }
+
+    #[test]
+    fn enumeration() {
+        check(&[
+            (TestEnum::ValueA, "00"),
+            (TestEnum::ValueB, "02"),
+            (TestEnum::ValueC, "41"),
+        ]);
+        assert_matches!(TestEnum::decode(&unhexlify("04")), Err(Error::Encoding(_)));
+    }
+
+    #[test]
+    fn message() {
+        let call = schema::ProcedureCall {
+            service: "ServiceName".to_string(),
+            procedure: "ProcedureName".to_string(),
+            ..Default::default()
+        };
+        check(&[(
+            call,
+            "0a0b536572766963654e616d65120d50726f6365647572654e616d65",
+        )]);
+        check(&[(schema::Stream { id: 300 }, "08ac02")]);
+        check(&[(
+            schema::Event {
+                stream: Some(schema::Stream { id: 1 }),
+            },
+            "0a020801",
+        )]);
+    }
+
+    #[test]
+    fn trailing_data() {
+        assert_matches!(u32::decode(&unhexlify("0101")), Err(Error::Encoding(_)));
+        assert_matches!(
+            f32::decode(&unhexlify("0000000000")),
+            Err(Error::Encoding(_))
+        );
+        assert_matches!(String::decode(&unhexlify("0361")), Err(Error::Encoding(_)));
+    }
+}
@@ -0,0 +1,56 @@
+//! The handshake that opens a connection to the RPC or stream server.
+
+use alloc::format;
+use alloc::string::ToString;
+
+use crate::error::{Error, Result};
+use crate::schema::{
+    connection_request, connection_response, ConnectionRequest, ConnectionResponse,
+};
+
+/// The unique identifier the server assigns to a client when it connects.
+pub type ClientIdentifier = [u8; 16];
+
+/// The request that opens a connection to the RPC server.
+pub fn rpc_connection_request(client_name: &str) -> ConnectionRequest {
+    ConnectionRequest {
+        r#type: connection_request::Type::Rpc as i32,
+        client_name: client_name.to_string(),
+        ..Default::default()
+    }
+}
+
+/// The request that attaches a connection to the stream server.
+pub fn stream_connection_request(client_identifier: &ClientIdentifier) -> ConnectionRequest {
+    ConnectionRequest {
+        r#type: connection_request::Type::Stream as i32,
+        client_identifier: bytes::Bytes::copy_from_slice(client_identifier),
+        ..Default::default()
+    }
+}
+
+/// Maps an unsuccessful handshake to its error, and returns the client
+/// identifier of a successful one.
+pub fn check_connection_response(response: ConnectionResponse) -> Result<ClientIdentifier> {
+    let status = connection_response::Status::try_from(response.status).map_err(|_| {
+        Error::InvalidConnectionResponse(format!("unknown status {}", response.status))
+    })?;
+    match status {
+        connection_response::Status::Ok => {}
+        connection_response::Status::MalformedMessage => {
+            return Err(Error::MalformedConnectionRequest(response.message))
+        }
+        connection_response::Status::Timeout => {
+            return Err(Error::ConnectionTimeout(response.message))
+        }
+        connection_response::Status::WrongType => {
+            return Err(Error::WrongConnectionType(response.message))
+        }
+    }
+    ClientIdentifier::try_from(response.client_identifier.as_ref()).map_err(|_| {
+        Error::InvalidConnectionResponse(format!(
+            "client identifier is {} bytes, expected 16",
+            response.client_identifier.len()
+        ))
+    })
+}
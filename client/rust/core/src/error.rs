@@ -0,0 +1,73 @@
+use alloc::string::String;
+use core::fmt;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Errors in encoding, decoding or framing messages, and in the replies the
+/// server sends to them.
+#[derive(Debug)]
+pub enum Error {
+    /// The length prefix of a received message was not a valid varint.
+    MalformedLength,
+    /// A message was larger than the maximum size, or than a fixed-capacity
+    /// buffer can hold.
+    MessageTooLarge { size: u64, max: usize },
+    /// A received message could not be decoded.
+    Decode(prost::DecodeError),
+    /// A value could not be encoded or decoded.
+    Encoding(String),
+
+    /// The server could not decode the connection request.
+    MalformedConnectionRequest(String),
+    /// The server did not receive the connection request in time.
+    ConnectionTimeout(String),
+    /// The connection request was for a different kind of server, for
+    /// example an RPC request sent to the stream port.
+    WrongConnectionType(String),
+    /// The server's connection response did not make sense.
+    InvalidConnectionResponse(String),
+
+    /// The server's response to a request did not make sense.
+    InvalidResponse(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MalformedLength => write!(f, "malformed message length prefix"),
+            Error::MessageTooLarge { size, max } => write!(
+                f,
+                "message of {} bytes exceeds the maximum of {} bytes",
+                size, max
+            ),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+            Error::Encoding(message) => write!(f, "encoding error: {}", message),
+            Error::MalformedConnectionRequest(message) => {
+                write!(f, "malformed connection request: {}", message)
+            }
+            Error::ConnectionTimeout(message) => write!(f, "connection timed out: {}", message),
+            Error::WrongConnectionType(message) => {
+                write!(f, "wrong connection type: {}", message)
+            }
+            Error::InvalidConnectionResponse(message) => {
+                write!(f, "invalid connection response: {}", message)
+            }
+            Error::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
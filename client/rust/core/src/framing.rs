@@ -0,0 +1,228 @@
+//! Length-prefixed framing: each message on the wire is preceded by its
+//! length in bytes, encoded as a varint.
+
+use prost::encoding::{encode_varint, encoded_len_varint};
+use prost::Message;
+
+use crate::buffer::Buffer;
+use crate::error::Error;
+use crate::io::{self, Read, Write};
+
+/// The largest message `read_frame` accepts by default. Anything bigger is
+/// treated as a corrupt length prefix rather than allocated.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The maximum number of bytes in a protobuf varint encoding a `u64`.
+pub const MAX_VARINT_LENGTH: usize = 10;
+
+/// Reads a varint one byte at a time, so that no bytes of the following
+/// message are consumed.
+pub fn read_varint<R: Read>(reader: &mut R) -> Result<u64, io::Error<R::Error>> {
+    let mut value = 0u64;
+    for i in 0..MAX_VARINT_LENGTH {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte).map_err(io::Error::Io)?;
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::MalformedLength.into())
+}
+
+/// Writes `value` as a varint.
+pub fn write_varint<W: Write>(writer: &mut W, value: u64) -> Result<(), io::Error<W::Error>> {
+    let mut bytes = [0u8; MAX_VARINT_LENGTH];
+    let mut rest = &mut bytes[..];
+    encode_varint(value, &mut rest);
+    writer
+        .write_all(&bytes[..encoded_len_varint(value)])
+        .map_err(io::Error::Io)
+}
+
+/// Reads the body of a length-prefixed message into `buffer`.
+pub fn read_frame<R: Read, B: Buffer>(
+    reader: &mut R,
+    buffer: &mut B,
+    max_message_size: usize,
+) -> Result<(), io::Error<R::Error>> {
+    let size = read_varint(reader)?;
+    if size > max_message_size as u64 {
+        return Err(Error::MessageTooLarge {
+            size,
+            max: max_message_size,
+        }
+        .into());
+    }
+    buffer.resize(size as usize)?;
+    reader
+        .read_exact(buffer.as_mut_slice())
+        .map_err(io::Error::Io)
+}
+
+/// Reads a length-prefixed message, using `buffer` to hold its body.
+pub fn read_message<R: Read, B: Buffer, M: Message + Default>(
+    reader: &mut R,
+    buffer: &mut B,
+    max_message_size: usize,
+) -> Result<M, io::Error<R::Error>> {
+    read_frame(reader, buffer, max_message_size)?;
+    Ok(M::decode(buffer.as_slice())?)
+}
+
+/// Writes `body`, prefixed with its length.
+pub fn write_frame<W: Write>(writer: &mut W, body: &[u8]) -> Result<(), io::Error<W::Error>> {
+    write_varint(writer, body.len() as u64)?;
+    writer.write_all(body).map_err(io::Error::Io)?;
+    writer.flush().map_err(io::Error::Io)
+}
+
+/// Encodes `message` into `buffer` with its length prefix and writes it out
+/// in one go.
+pub fn write_message<W: Write, B: Buffer, M: Message>(
+    writer: &mut W,
+    message: &M,
+    buffer: &mut B,
+) -> Result<(), io::Error<W::Error>> {
+    let len = message.encoded_len();
+    buffer.resize(encoded_len_varint(len as u64) + len)?;
+    let mut rest = buffer.as_mut_slice();
+    message
+        .encode_length_delimited(&mut rest)
+        .expect("the buffer was resized to fit the message");
+    writer.write_all(buffer.as_slice()).map_err(io::Error::Io)?;
+    writer.flush().map_err(io::Error::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::ProcedureCall;
+    use claim::{assert_matches, assert_ok};
+
+    /// A pipe that hands out at most one byte per read, and records writes.
+    #[derive(Default)]
+    struct Pipe {
+        data: Vec<u8>,
+        position: usize,
+        reads: usize,
+    }
+
+    impl io::ErrorType for Pipe {
+        type Error = &'static str;
+    }
+
+    impl Read for Pipe {
+        fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+            let end = self.position + buf.len();
+            let data = self.data.get(self.position..end).ok_or("end of pipe")?;
+            buf.copy_from_slice(data);
+            self.position = end;
+            self.reads += 1;
+            Ok(())
+        }
+    }
+
+    impl Write for Pipe {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+            self.data.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    fn procedure_call() -> ProcedureCall {
+        ProcedureCall {
+            service: "ServiceName".to_string(),
+            procedure: "ProcedureName".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn write_message_with_size() {
+        let mut pipe = Pipe::default();
+        assert_ok!(write_message(&mut pipe, &procedure_call(), &mut Vec::new()));
+        let mut expected = vec![0x1c];
+        expected.extend_from_slice(b"\x0a\x0bServiceName\x12\x0dProcedureName");
+        assert_eq!(pipe.data, expected);
+
+        let mut framed = Pipe::default();
+        assert_ok!(write_frame(&mut framed, &expected[1..]));
+        assert_eq!(framed.data, expected);
+    }
+
+    #[test]
+    fn read_messages_one_after_another() {
+        let mut pipe = Pipe::default();
+        let mut buffer = Vec::new();
+        assert_ok!(write_message(&mut pipe, &procedure_call(), &mut buffer));
+        assert_ok!(write_message(&mut pipe, &procedure_call(), &mut buffer));
+        for _ in 0..2 {
+            let call: ProcedureCall = assert_ok!(read_message(
+                &mut pipe,
+                &mut buffer,
+                DEFAULT_MAX_MESSAGE_SIZE
+            ));
+            assert_eq!(call, procedure_call());
+        }
+        // The length prefix is read a byte at a time, then the body at once.
+        assert_eq!(pipe.reads, 4);
+        assert_matches!(
+            read_frame(&mut pipe, &mut buffer, DEFAULT_MAX_MESSAGE_SIZE),
+            Err(io::Error::Io("end of pipe"))
+        );
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, 127, 128, 300, u64::MAX] {
+            let mut pipe = Pipe::default();
+            assert_ok!(write_varint(&mut pipe, value));
+            assert_eq!(pipe.data.len(), encoded_len_varint(value));
+            assert_eq!(assert_ok!(read_varint(&mut pipe)), value);
+        }
+        let mut pipe = Pipe {
+            data: vec![0xff; MAX_VARINT_LENGTH],
+            ..Default::default()
+        };
+        assert_matches!(
+            read_varint(&mut pipe),
+            Err(io::Error::Protocol(Error::MalformedLength))
+        );
+    }
+
+    #[test]
+    fn message_too_large() {
+        let mut pipe = Pipe::default();
+        assert_ok!(write_message(&mut pipe, &procedure_call(), &mut Vec::new()));
+        assert_matches!(
+            read_frame(&mut pipe, &mut Vec::new(), 16),
+            Err(io::Error::Protocol(Error::MessageTooLarge {
+                size: 28,
+                max: 16
+            }))
+        );
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn fixed_capacity_buffer() {
+        let mut pipe = Pipe::default();
+        let mut small = heapless::Vec::<u8, 16>::new();
+        assert_matches!(
+            write_message(&mut pipe, &procedure_call(), &mut small),
+            Err(io::Error::Protocol(Error::MessageTooLarge {
+                size: 29,
+                max: 16
+            }))
+        );
+        let mut buffer = heapless::Vec::<u8, 64>::new();
+        assert_ok!(write_message(&mut pipe, &procedure_call(), &mut buffer));
+        let call: ProcedureCall = assert_ok!(read_message(
+            &mut pipe,
+            &mut buffer,
+            DEFAULT_MAX_MESSAGE_SIZE
+        ));
+        assert_eq!(call, procedure_call());
+    }
+}
@@ -0,0 +1,121 @@
+//! The byte pipe a client talks to the server over.
+//!
+//! These are deliberately smaller than `std::io::Read` and `Write`, so that
+//! a UART or USB driver can implement them in a few lines. Reads and writes
+//! are all-or-nothing: a driver retries short transfers itself, and reports
+//! anything else through its own error type.
+
+use crate::error;
+
+/// The error type of a byte pipe.
+pub trait ErrorType {
+    type Error;
+}
+
+/// The receiving end of a byte pipe.
+pub trait Read: ErrorType {
+    /// Fills the whole of `buf`, blocking until enough bytes have arrived.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// The sending end of a byte pipe.
+pub trait Write: ErrorType {
+    /// Writes the whole of `buf`.
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error>;
+
+    /// Sends anything the pipe has buffered. Called after each message.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<T: ErrorType + ?Sized> ErrorType for &mut T {
+    type Error = T::Error;
+}
+
+impl<T: Read + ?Sized> Read for &mut T {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        (**self).read_exact(buf)
+    }
+}
+
+impl<T: Write + ?Sized> Write for &mut T {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        (**self).write_all(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush()
+    }
+}
+
+/// Reading from or writing to a byte pipe failed, either in the pipe itself
+/// or in the messages carried over it.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The pipe failed.
+    Io(E),
+    /// A message could not be framed, encoded or decoded.
+    Protocol(error::Error),
+}
+
+impl<E> From<error::Error> for Error<E> {
+    fn from(e: error::Error) -> Self {
+        Error::Protocol(e)
+    }
+}
+
+impl<E> From<prost::DecodeError> for Error<E> {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Protocol(e.into())
+    }
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Protocol(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Protocol(e) => Some(e),
+        }
+    }
+}
+
+/// Adapts a `std::io` reader or writer to the traits of this module.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FromStd<T>(pub T);
+
+#[cfg(feature = "std")]
+impl<T> ErrorType for FromStd<T> {
+    type Error = std::io::Error;
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read> Read for FromStd<T> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read_exact(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Write> Write for FromStd<T> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
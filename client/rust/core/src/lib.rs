@@ -0,0 +1,38 @@
+//! The parts of the kRPC client that need no operating system: the protocol
+//! schema, the encoding of values, and the framing of messages.
+//!
+//! The crate is `no_std` and only needs an allocator, so that a client can
+//! run on a microcontroller talking to the server's SerialIO server, as the
+//! C-nano client does. Bytes are read and written through the [`io`] traits,
+//! which a serial driver implements, and messages are held in a [`Buffer`],
+//! which may be a heapless vector of fixed capacity.
+//!
+//! The `std` feature adds codecs for `HashMap` and `HashSet`, and
+//! [`io::FromStd`] for using any `std::io` reader or writer.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod buffer;
+pub mod codec;
+pub mod connection;
+pub mod error;
+pub mod framing;
+pub mod io;
+pub mod schema;
+pub mod serial;
+
+pub use buffer::Buffer;
+pub use codec::{Decode, Encode};
+pub use connection::ClientIdentifier;
+pub use error::{Error, Result};
+
+/// Items used by the code the macros expand to, which must not depend on
+/// the prelude of the crate using them.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::format;
+    pub use alloc::string::ToString;
+    pub use alloc::vec::Vec;
+}
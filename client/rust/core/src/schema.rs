@@ -0,0 +1,36 @@
+//! Protocol Buffers messages used to communicate with the server.
+//!
+//! These types are generated at build time from `protobuf/krpc.proto`, the
+//! same schema the server and all other clients are built from.
+
+#![allow(clippy::all)]
+
+include!(concat!(env!("OUT_DIR"), "/krpc.schema.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn procedure_call_round_trip() {
+        let call = ProcedureCall {
+            service: "ServiceName".to_string(),
+            procedure: "ProcedureName".to_string(),
+            ..Default::default()
+        };
+        let data = call.encode_to_vec();
+        assert_eq!(data, b"\x0a\x0bServiceName\x12\x0dProcedureName".to_vec());
+        assert_eq!(ProcedureCall::decode(data.as_slice()).unwrap(), call);
+    }
+
+    #[test]
+    fn nested_enums() {
+        let request = ConnectionRequest {
+            r#type: connection_request::Type::Stream as i32,
+            ..Default::default()
+        };
+        assert_eq!(request.r#type(), connection_request::Type::Stream);
+        assert_eq!(r#type::TypeCode::Dictionary as i32, 303);
+    }
+}
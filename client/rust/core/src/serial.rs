@@ -0,0 +1,366 @@
+//! The SerialIO protocol, for talking to the server over a serial port or
+//! any other byte pipe.
+//!
+//! The server's SerialIO server carries every message over the one byte
+//! stream. The client opens the connection by sending a `MultiplexedRequest`
+//! holding its `ConnectionRequest`, and the server replies with a plain
+//! `ConnectionResponse`. After that each `Request` is sent wrapped in a
+//! `MultiplexedRequest`, and each `Response` comes back wrapped in a
+//! `MultiplexedResponse`. All messages are length-prefixed, as over TCP.
+//!
+//! The SerialIO server has no stream server, so streams and events are not
+//! available over it.
+
+use alloc::string::ToString;
+
+use prost::encoding::{
+    decode_key, decode_varint, encode_key, encode_varint, skip_field, DecodeContext, WireType,
+};
+use prost::Message;
+
+use crate::buffer::Buffer;
+use crate::connection::{check_connection_response, rpc_connection_request, ClientIdentifier};
+use crate::error::Error;
+use crate::framing::{self, DEFAULT_MAX_MESSAGE_SIZE};
+use crate::io::{self, Read, Write};
+use crate::schema::{ConnectionResponse, MultiplexedRequest, Request, Response};
+
+/// The field of `MultiplexedRequest` that holds a `Request`.
+const REQUEST_FIELD: u32 = 2;
+
+/// The field of `MultiplexedResponse` that holds a `Response`.
+const RESPONSE_FIELD: u32 = 1;
+
+/// Opens the connection, identifying the client as `client_name`, and
+/// returns the identifier the server assigned.
+pub fn connect<P, B>(
+    port: &mut P,
+    buffer: &mut B,
+    client_name: &str,
+) -> Result<ClientIdentifier, io::Error<P::Error>>
+where
+    P: Read + Write,
+    B: Buffer,
+{
+    let request = MultiplexedRequest {
+        connection_request: Some(rpc_connection_request(client_name)),
+        ..Default::default()
+    };
+    framing::write_message(port, &request, buffer)?;
+    let response: ConnectionResponse =
+        framing::read_message(port, buffer, DEFAULT_MAX_MESSAGE_SIZE)?;
+    Ok(check_connection_response(response)?)
+}
+
+/// Writes an encoded `Request`, wrapped in a `MultiplexedRequest`.
+pub fn write_request<W: Write>(writer: &mut W, request: &[u8]) -> Result<(), io::Error<W::Error>> {
+    // An encoded `Request` becomes a `MultiplexedRequest` by prefixing it
+    // with the key and length of the field that holds it.
+    let mut wrapper = [0u8; 1 + framing::MAX_VARINT_LENGTH];
+    let capacity = wrapper.len();
+    let mut rest = &mut wrapper[..];
+    encode_key(REQUEST_FIELD, WireType::LengthDelimited, &mut rest);
+    encode_varint(request.len() as u64, &mut rest);
+    let wrapper_len = capacity - rest.len();
+    framing::write_varint(writer, (wrapper_len + request.len()) as u64)?;
+    writer
+        .write_all(&wrapper[..wrapper_len])
+        .map_err(io::Error::Io)?;
+    writer.write_all(request).map_err(io::Error::Io)?;
+    writer.flush().map_err(io::Error::Io)
+}
+
+/// Reads a `Response`, unwrapping it from its `MultiplexedResponse`.
+pub fn read_response<R: Read, B: Buffer>(
+    reader: &mut R,
+    buffer: &mut B,
+    max_message_size: usize,
+) -> Result<Response, io::Error<R::Error>> {
+    let response = read_encoded_response(reader, buffer, max_message_size)?;
+    Ok(Response::decode(response).map_err(Error::from)?)
+}
+
+/// Reads a `MultiplexedResponse` into `buffer`, and returns the encoded
+/// `Response` it holds without decoding it.
+pub fn read_encoded_response<'a, R: Read, B: Buffer>(
+    reader: &mut R,
+    buffer: &'a mut B,
+    max_message_size: usize,
+) -> Result<&'a [u8], io::Error<R::Error>> {
+    framing::read_frame(reader, buffer, max_message_size)?;
+    let buffer: &'a B = buffer;
+    Ok(response_field(buffer.as_slice())?)
+}
+
+/// Finds the encoded `Response` in an encoded `MultiplexedResponse`. The
+/// other fields are skipped over, and if the field appears more than once
+/// the last is used, as decoding the whole message would.
+fn response_field(mut message: &[u8]) -> Result<&[u8], Error> {
+    let mut response = None;
+    while !message.is_empty() {
+        let (tag, wire_type) = decode_key(&mut message)?;
+        if tag != RESPONSE_FIELD || wire_type != WireType::LengthDelimited {
+            skip_field(wire_type, tag, &mut message, DecodeContext::default())?;
+            continue;
+        }
+        let len = decode_varint(&mut message)?;
+        if len > message.len() as u64 {
+            return Err(Error::InvalidResponse(
+                "multiplexed response is truncated".to_string(),
+            ));
+        }
+        let (field, rest) = message.split_at(len as usize);
+        response = Some(field);
+        message = rest;
+    }
+    response
+        .ok_or_else(|| Error::InvalidResponse("multiplexed response has no response".to_string()))
+}
+
+/// A connection to the SerialIO server over `port`, which holds each
+/// message in `buffer` while it is sent or received.
+///
+/// ```
+/// # fn invoke<P: krpc_core::io::Read + krpc_core::io::Write>(port: P)
+/// # -> Result<(), krpc_core::io::Error<P::Error>> {
+/// use krpc_core::schema::{ProcedureCall, Request};
+///
+/// let buffer = Vec::new();
+/// let (mut connection, _) = krpc_core::serial::Connection::connect(port, buffer, "Jeb")?;
+/// let request = Request {
+///     calls: vec![ProcedureCall {
+///         service: "KRPC".to_string(),
+///         procedure: "GetStatus".to_string(),
+///         ..Default::default()
+///     }],
+/// };
+/// let response = connection.invoke(&request)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Connection<P, B> {
+    port: P,
+    buffer: B,
+    max_message_size: usize,
+}
+
+impl<P: Read + Write, B: Buffer> Connection<P, B> {
+    /// Opens the connection, identifying the client as `client_name`, and
+    /// returns it with the identifier the server assigned.
+    pub fn connect(
+        mut port: P,
+        mut buffer: B,
+        client_name: &str,
+    ) -> Result<(Self, ClientIdentifier), io::Error<P::Error>> {
+        let client_identifier = connect(&mut port, &mut buffer, client_name)?;
+        let connection = Connection {
+            port,
+            buffer,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        };
+        Ok((connection, client_identifier))
+    }
+
+    /// Sets the largest response, in bytes, that `invoke` accepts.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+
+    /// Sends `request` and waits for its response. Errors reported by the
+    /// server are left in the response, for the caller to check.
+    pub fn invoke(&mut self, request: &Request) -> Result<Response, io::Error<P::Error>> {
+        self.buffer.resize(request.encoded_len())?;
+        let mut rest = self.buffer.as_mut_slice();
+        request
+            .encode(&mut rest)
+            .expect("the buffer was resized to fit the request");
+        write_request(&mut self.port, self.buffer.as_slice())?;
+        read_response(&mut self.port, &mut self.buffer, self.max_message_size)
+    }
+
+    /// Closes the connection, giving back the port and buffer.
+    pub fn into_inner(self) -> (P, B) {
+        (self.port, self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::schema::{
+        connection_request, connection_response, MultiplexedResponse, ProcedureCall,
+        ProcedureResult,
+    };
+    use claim::{assert_matches, assert_ok};
+
+    const IDENTIFIER: ClientIdentifier = *b"0123456789abcdef";
+
+    /// A pipe that replays the server's side of a conversation, and records
+    /// what the client sends.
+    #[derive(Default)]
+    struct Pipe {
+        received: Vec<u8>,
+        sent: Vec<u8>,
+    }
+
+    impl io::ErrorType for Pipe {
+        type Error = &'static str;
+    }
+
+    impl Read for Pipe {
+        fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+            if buf.len() > self.received.len() {
+                return Err("end of pipe");
+            }
+            buf.copy_from_slice(&self.received[..buf.len()]);
+            self.received.drain(..buf.len());
+            Ok(())
+        }
+    }
+
+    impl Write for Pipe {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+            self.sent.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    /// Queues `message` for the client to receive.
+    fn queue<M: Message>(pipe: &mut Pipe, message: &M) {
+        let mut server = Pipe::default();
+        assert_ok!(framing::write_message(
+            &mut server,
+            message,
+            &mut Vec::new()
+        ));
+        pipe.received.extend(server.sent);
+    }
+
+    fn connection_response(status: connection_response::Status) -> ConnectionResponse {
+        ConnectionResponse {
+            status: status as i32,
+            client_identifier: IDENTIFIER.to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn connects_and_invokes() {
+        let mut pipe = Pipe::default();
+        queue(
+            &mut pipe,
+            &connection_response(connection_response::Status::Ok),
+        );
+        let result = ProcedureResult {
+            value: codec::encode(&42u32),
+            ..Default::default()
+        };
+        queue(
+            &mut pipe,
+            &MultiplexedResponse {
+                response: Some(Response {
+                    results: vec![result.clone()],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let (mut connection, identifier) =
+            assert_ok!(Connection::connect(&mut pipe, Vec::new(), "Jeb"));
+        assert_eq!(identifier, IDENTIFIER);
+        let request = Request {
+            calls: vec![ProcedureCall {
+                service: "KRPC".to_string(),
+                procedure: "GetStatus".to_string(),
+                ..Default::default()
+            }],
+        };
+        let response = assert_ok!(connection.invoke(&request));
+        assert_eq!(response.results, [result]);
+        drop(connection);
+
+        let mut sent = Pipe {
+            received: core::mem::take(&mut pipe.sent),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        let opened: MultiplexedRequest = assert_ok!(framing::read_message(
+            &mut sent,
+            &mut buffer,
+            DEFAULT_MAX_MESSAGE_SIZE
+        ));
+        let opened = opened.connection_request.unwrap();
+        assert_eq!(opened.r#type(), connection_request::Type::Rpc);
+        assert_eq!(opened.client_name, "Jeb");
+        let wrapped: MultiplexedRequest = assert_ok!(framing::read_message(
+            &mut sent,
+            &mut buffer,
+            DEFAULT_MAX_MESSAGE_SIZE
+        ));
+        assert_eq!(wrapped.request, Some(request));
+        assert!(sent.received.is_empty());
+    }
+
+    #[test]
+    fn connection_refused() {
+        let mut pipe = Pipe::default();
+        queue(
+            &mut pipe,
+            &connection_response(connection_response::Status::WrongType),
+        );
+        assert_matches!(
+            connect(&mut pipe, &mut Vec::new(), "Jeb"),
+            Err(io::Error::Protocol(Error::WrongConnectionType(_)))
+        );
+    }
+
+    #[test]
+    fn encoded_response() {
+        let response = Response {
+            results: vec![ProcedureResult {
+                value: codec::encode(&42u32),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut pipe = Pipe::default();
+        queue(
+            &mut pipe,
+            &MultiplexedResponse {
+                response: Some(response.clone()),
+                stream_update: Some(Default::default()),
+            },
+        );
+        let mut buffer = Vec::new();
+        let encoded = assert_ok!(read_encoded_response(
+            &mut pipe,
+            &mut buffer,
+            DEFAULT_MAX_MESSAGE_SIZE
+        ));
+        assert_eq!(encoded, response.encode_to_vec());
+    }
+
+    #[test]
+    fn response_truncated() {
+        let mut pipe = Pipe::default();
+        // A response field claiming 5 bytes, with only 1 following.
+        framing::write_frame(&mut pipe, &[0x0a, 0x05, 0x00]).unwrap();
+        pipe.received = core::mem::take(&mut pipe.sent);
+        assert_matches!(
+            read_response(&mut pipe, &mut Vec::new(), DEFAULT_MAX_MESSAGE_SIZE),
+            Err(io::Error::Protocol(Error::InvalidResponse(_)))
+        );
+    }
+
+    #[test]
+    fn response_missing() {
+        let mut pipe = Pipe::default();
+        queue(&mut pipe, &MultiplexedResponse::default());
+        assert_matches!(
+            read_response(&mut pipe, &mut Vec::new(), DEFAULT_MAX_MESSAGE_SIZE),
+            Err(io::Error::Protocol(Error::InvalidResponse(_)))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::async_client::AsyncClient;
+use crate::batch::{next_batch_id, BatchCall, BatchResults};
+use crate::call::Call;
+use crate::codec::Decode;
+use crate::error::Result;
+use crate::schema::{ProcedureCall, Request};
+
+/// Queues procedure calls and sends them to the server in a single request,
+/// through an `AsyncClient`.
+///
+/// It behaves as [`Batch`](crate::Batch) does, except that sending it is a
+/// future.
+///
+/// ```no_run
+/// # async fn run() -> krpc::Result<()> {
+/// # let client = krpc::AsyncClient::connect("", "127.0.0.1", 50000, None).await?;
+/// let mut batch = client.batch();
+/// let ut = batch.add(krpc::Call::<f64>::new("SpaceCenter", "get_UT"));
+/// let paused = batch.add(krpc::Call::<bool>::new("KRPC", "get_Paused"));
+/// let results = batch.send().await?;
+/// println!("{} {}", results.get(&ut)?, results.get(&paused)?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncBatch<'a> {
+    client: &'a AsyncClient,
+    id: u64,
+    calls: Vec<ProcedureCall>,
+}
+
+impl<'a> AsyncBatch<'a> {
+    pub(crate) fn new(client: &'a AsyncClient) -> Self {
+        AsyncBatch {
+            client,
+            id: next_batch_id(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Queues `call`, returning a handle to retrieve its result with.
+    pub fn add<T: Decode>(&mut self, call: Call<T>) -> BatchCall<T> {
+        self.calls.push(call.into_message());
+        BatchCall::new(self.id, self.calls.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Sends all queued calls in one request, as `Batch::send` does.
+    pub async fn send(self) -> Result<BatchResults> {
+        if self.calls.is_empty() {
+            return Ok(BatchResults::empty(self.id));
+        }
+        let expected = self.calls.len();
+        let request = Request { calls: self.calls };
+        let response = self.client.send_request(request).await?;
+        BatchResults::from_response(self.id, expected, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_client::AsyncClient;
+    use crate::call::Call;
+    use crate::codec;
+    use crate::error::Error;
+    use crate::schema::{self, ProcedureResult, Request, Response};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+
+    /// Squares each `KRPC.Square` call and fails anything else.
+    fn handle(request: Request) -> Response {
+        let results = request
+            .calls
+            .iter()
+            .map(|call| match call.procedure.as_str() {
+                "Square" => {
+                    let x: i32 = codec::decode(&call.arguments[0].value).unwrap();
+                    ProcedureResult {
+                        value: codec::encode(&(x * x)),
+                        ..Default::default()
+                    }
+                }
+                _ => ProcedureResult {
+                    error: Some(schema::Error {
+                        service: "KRPC".to_string(),
+                        name: "InvalidOperationException".to_string(),
+                        description: format!("No procedure {}", call.procedure),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            })
+            .collect();
+        Response {
+            results,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_calls_together() {
+        let server = TestServer::start(handle);
+        let client =
+            assert_ok!(AsyncClient::connect("Jeb", "127.0.0.1", server.rpc_port, None).await);
+
+        let mut batch = client.batch();
+        let square = batch.add(Call::<i32>::new("KRPC", "Square").arg(&3));
+        let missing = batch.add(Call::<i32>::new("KRPC", "Cube").arg(&3));
+        assert_eq!(batch.len(), 2);
+        let results = assert_ok!(batch.send().await);
+        assert_eq!(assert_ok!(results.get(&square)), 9);
+        assert_matches!(results.get(&missing), Err(Error::InvalidOperation(_)));
+
+        assert!(assert_ok!(client.batch().send().await).is_empty());
+    }
+}
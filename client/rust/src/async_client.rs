@@ -0,0 +1,448 @@
+//! A client for the tokio runtime, enabled by the `async` feature.
+//!
+//! [`AsyncClient`] mirrors [`Client`](crate::Client), except that calls,
+//! and waits for streams and events, are futures, and stream updates are
+//! received by a task rather than a thread.
+//!
+//! On wasm32 targets, such as in a web browser, it also needs the
+//! `websocket` feature. It then connects over the host's WebSocket API, in
+//! the same way as `Client::connect_websocket`, and its tasks run on the
+//! host's event loop rather than on tokio. The addresses and ports are the
+//! same, and so is the rest of its API.
+//!
+//! Calls are pipelined: any number of tasks may make calls through the same
+//! client at once, and each request is sent without waiting for the
+//! responses to earlier ones.
+//!
+//! ```no_run
+//! # async fn run() -> krpc::Result<()> {
+//! use krpc::AsyncClient;
+//!
+//! let client = AsyncClient::connect("", "127.0.0.1", 50000, Some(50001)).await?;
+//! let ut = client.add_stream(&krpc::Call::<f64>::new("SpaceCenter", "get_UT")).await?;
+//! ut.wait(None).await?;
+//! println!("{}", ut.get()?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::async_batch::AsyncBatch;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::async_connection::AsyncConnection;
+use crate::async_event::AsyncEvent;
+use crate::async_stream::AsyncStream;
+#[cfg(target_arch = "wasm32")]
+use crate::async_websocket::AsyncConnection;
+use crate::call::Call;
+use crate::codec::{Decode, Encode};
+use crate::connection::ClientIdentifier;
+use crate::error::{Error, Result};
+use crate::expression::Expression;
+use crate::pipeline::Pipeline;
+use crate::runtime::{self, Task};
+use crate::schema::{self, ProcedureCall, Request, Response, StreamUpdate};
+use crate::stream::CallbackId;
+use crate::stream_manager::{StreamManager, UpdateCallback};
+
+/// A kRPC client for the tokio runtime.
+///
+/// Like `Client`, it owns the connections to the RPC server and, optionally,
+/// the stream server, and closes them once it and every stream added through
+/// it have been dropped, or when `close` is called. As with `Client`, clones
+/// are handles to the same session.
+#[derive(Clone)]
+pub struct AsyncClient {
+    shared: Arc<AsyncShared>,
+}
+
+/// The state shared by an `AsyncClient` and the streams added through it.
+pub(crate) struct AsyncShared {
+    rpc: Pipeline,
+    client_identifier: ClientIdentifier,
+    streams: Option<Arc<StreamManager>>,
+    receiver: Mutex<Option<Task>>,
+}
+
+impl AsyncClient {
+    /// Connects to the RPC server at `address` and `rpc_port`, identifying
+    /// the client as `name` in the in-game UI. If `stream_port` is given,
+    /// also connects to the stream server on that port and spawns the task
+    /// that receives stream updates.
+    pub async fn connect(
+        name: &str,
+        address: &str,
+        rpc_port: u16,
+        stream_port: Option<u16>,
+    ) -> Result<Self> {
+        Self::connect_with_timeout(name, address, rpc_port, stream_port, None).await
+    }
+
+    /// Like `connect`, but gives up opening each connection once `timeout`
+    /// elapses, failing with an `Error::Io` of kind `TimedOut`. `None` waits
+    /// for as long as the operating system allows, as `connect` does.
+    pub async fn connect_with_timeout(
+        name: &str,
+        address: &str,
+        rpc_port: u16,
+        stream_port: Option<u16>,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let (rpc, client_identifier) =
+            AsyncConnection::connect_rpc(address, rpc_port, name, timeout).await?;
+        let (reader, writer) = rpc.split();
+
+        let (streams, receiver) = match stream_port {
+            Some(port) => {
+                let connection =
+                    AsyncConnection::connect_stream(address, port, &client_identifier, timeout)
+                        .await?;
+                let manager = Arc::new(StreamManager::new());
+                let receiver = runtime::spawn(receive_updates(connection, Arc::clone(&manager)));
+                (Some(manager), Some(receiver))
+            }
+            None => (None, None),
+        };
+
+        Ok(AsyncClient {
+            shared: Arc::new(AsyncShared {
+                rpc: Pipeline::new(reader, writer),
+                client_identifier,
+                streams,
+                receiver: Mutex::new(receiver),
+            }),
+        })
+    }
+
+    /// The identifier the server assigned to this client.
+    pub fn client_identifier(&self) -> &ClientIdentifier {
+        &self.shared.client_identifier
+    }
+
+    /// Invokes the procedure named `procedure` in the service named
+    /// `service` and returns its encoded result, as `Client::invoke` does.
+    ///
+    /// The arguments are encoded before the future is returned, so it does
+    /// not borrow them.
+    pub fn invoke(
+        &self,
+        service: &str,
+        procedure: &str,
+        args: &[&dyn Encode],
+    ) -> impl Future<Output = Result<Bytes>> + Send + '_ {
+        let call = Call::<Bytes>::new(service, procedure).args(args);
+        async move { self.invoke_call(call.message()).await }
+    }
+
+    /// Like `invoke`, but decodes the result as a `T`.
+    pub fn invoke_typed<'a, T: Decode + 'a>(
+        &'a self,
+        service: &str,
+        procedure: &str,
+        args: &[&dyn Encode],
+    ) -> impl Future<Output = Result<T>> + Send + 'a {
+        let call = Call::new(service, procedure).args(args);
+        async move { self.call(&call).await }
+    }
+
+    /// Makes a typed call and decodes its result.
+    pub async fn call<T: Decode>(&self, call: &Call<T>) -> Result<T> {
+        self.shared.call(call).await
+    }
+
+    /// Sends `call` to the server, which may address the procedure either by
+    /// name or by id, and returns its encoded result.
+    pub async fn invoke_call(&self, call: &ProcedureCall) -> Result<Bytes> {
+        self.shared.invoke_call(call).await
+    }
+
+    /// Starts a batch of calls to send to the server in a single request.
+    pub fn batch(&self) -> AsyncBatch<'_> {
+        AsyncBatch::new(self)
+    }
+
+    /// Sends a request and waits for its response. Fails if the server
+    /// rejected the request as a whole.
+    pub(crate) async fn send_request(&self, request: Request) -> Result<Response> {
+        self.shared.send_request(request).await
+    }
+
+    /// Streams the result of `call`. The server starts sending updates
+    /// straight away.
+    pub async fn add_stream<T>(&self, call: &Call<T>) -> Result<AsyncStream<T>>
+    where
+        T: Decode + Clone + Send + Sync + 'static,
+    {
+        AsyncStream::add(&self.shared, call, true).await
+    }
+
+    /// Like `add_stream`, but the server does not send updates until
+    /// `AsyncStream::start` is called.
+    pub async fn add_stream_paused<T>(&self, call: &Call<T>) -> Result<AsyncStream<T>>
+    where
+        T: Decode + Clone + Send + Sync + 'static,
+    {
+        AsyncStream::add(&self.shared, call, false).await
+    }
+
+    /// Calls a procedure that returns an event, such as `KRPC.AddEvent`.
+    pub async fn event(&self, call: &Call<schema::Event>) -> Result<AsyncEvent> {
+        self.shared.streams()?;
+        let event = self.call(call).await?;
+        let stream = event
+            .stream
+            .ok_or_else(|| Error::InvalidResponse("event has no stream".to_string()))?;
+        Ok(AsyncEvent::new(AsyncStream::from_id(
+            &self.shared,
+            stream.id,
+        )?))
+    }
+
+    /// Creates an event that occurs whenever `condition`, which must be a
+    /// boolean expression, becomes true.
+    pub async fn add_event(&self, condition: &Expression) -> Result<AsyncEvent> {
+        self.event(&Call::new("KRPC", "AddEvent").arg(condition))
+            .await
+    }
+
+    /// Resolves once a `StreamUpdate` is received after the call, whichever
+    /// streams it touches. Resolves to false if `timeout` elapses first, and
+    /// fails if the client is not, or stops being, connected to the stream
+    /// server.
+    pub fn wait_for_stream_update(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<bool>> + Send + '_ {
+        let seen = self.shared.streams().map(|streams| streams.signal.count());
+        async move {
+            let seen = seen?;
+            let signal = &self.shared.streams()?.signal;
+            signal.wait_since_async(seen, timeout).await
+        }
+    }
+
+    /// Adds a callback that is run after each `StreamUpdate` has been
+    /// processed, as `Client::add_stream_update_callback` does. Callbacks
+    /// run on the task that receives stream updates, so must not block.
+    pub fn add_stream_update_callback<F>(&self, callback: F) -> Result<CallbackId>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let callback: Arc<UpdateCallback> = Arc::new(Mutex::new(callback));
+        Ok(self.shared.streams()?.callbacks.add(callback))
+    }
+
+    /// Removes a callback added with `add_stream_update_callback`. Returns
+    /// false if it had already been removed.
+    pub fn remove_stream_update_callback(&self, id: CallbackId) -> bool {
+        match self.shared.streams() {
+            Ok(streams) => streams.callbacks.remove(id),
+            Err(_) => false,
+        }
+    }
+
+    /// Closes the connections to the RPC and stream servers. Streams added
+    /// through the client stop receiving updates.
+    pub async fn close(&mut self) -> Result<()> {
+        self.shared.stop_receiver();
+        self.shared.rpc.close().await
+    }
+}
+
+impl AsyncShared {
+    pub async fn call<T: Decode>(&self, call: &Call<T>) -> Result<T> {
+        Ok(T::decode(&self.invoke_call(call.message()).await?)?)
+    }
+
+    pub async fn invoke_call(&self, call: &ProcedureCall) -> Result<Bytes> {
+        let request = Request {
+            calls: vec![call.clone()],
+        };
+        let response = self.send_request(request).await?;
+        let mut results = response.results;
+        if results.len() != 1 {
+            return Err(Error::InvalidResponse(format!(
+                "expected 1 result, got {}",
+                results.len()
+            )));
+        }
+        let result = results.remove(0);
+        match result.error {
+            Some(error) => Err(Error::from_procedure_error(error)),
+            None => Ok(result.value),
+        }
+    }
+
+    pub async fn send_request(&self, request: Request) -> Result<Response> {
+        let response = self.rpc.send_request(request).await?;
+        match response.error {
+            Some(error) => Err(Error::from_request_error(error)),
+            None => Ok(response),
+        }
+    }
+
+    /// The manager for the client's streams, or an error if the client is
+    /// not connected to the stream server.
+    pub fn streams(&self) -> Result<&Arc<StreamManager>> {
+        self.streams.as_ref().ok_or(Error::NoStreamConnection)
+    }
+
+    /// Stops receiving stream updates, waking everything waiting for one.
+    fn stop_receiver(&self) {
+        // Dropping the task stops it.
+        self.receiver.lock().unwrap().take();
+        if let Some(streams) = &self.streams {
+            streams.close();
+        }
+    }
+}
+
+impl Drop for AsyncShared {
+    fn drop(&mut self) {
+        // The sockets close as they are dropped.
+        self.stop_receiver();
+    }
+}
+
+async fn receive_updates(connection: AsyncConnection, manager: Arc<StreamManager>) {
+    // The sending half is kept open, as the server treats its shutdown as a
+    // disconnection.
+    let (mut reader, _writer) = connection.split();
+    while let Ok(update) = reader.receive_message::<StreamUpdate>().await {
+        manager.update(update);
+    }
+    manager.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::schema::{connection_request, ProcedureResult};
+    use crate::test_server::{TestServer, IDENTIFIER};
+    use claim::{assert_matches, assert_ok};
+
+    fn echo_server() -> TestServer {
+        TestServer::start(|request: Request| Response {
+            results: request
+                .calls
+                .into_iter()
+                .map(|call| ProcedureResult {
+                    value: call
+                        .arguments
+                        .first()
+                        .map(|a| a.value.clone())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn connect_invoke_and_close() {
+        let server = echo_server();
+        let mut client = assert_ok!(
+            AsyncClient::connect(
+                "Jeb",
+                "127.0.0.1",
+                server.rpc_port,
+                Some(server.stream_port)
+            )
+            .await
+        );
+        assert_eq!(client.client_identifier(), &IDENTIFIER);
+        let value = assert_ok!(client.invoke("KRPC", "Echo", &[&42u32]).await);
+        assert_eq!(value, codec::encode(&42u32));
+        let value: String = assert_ok!(client.invoke_typed("KRPC", "Echo", &[&"Kerbin"]).await);
+        assert_eq!(value, "Kerbin");
+        assert_ok!(client.close().await);
+
+        let request = tokio::task::spawn_blocking(move || server.join())
+            .await
+            .unwrap();
+        assert_eq!(request.r#type(), connection_request::Type::Rpc);
+        assert_eq!(request.client_name, "Jeb");
+    }
+
+    #[tokio::test]
+    async fn connect_with_timeout() {
+        let server = echo_server();
+        let timeout = Some(Duration::from_secs(5));
+        let client = assert_ok!(
+            AsyncClient::connect_with_timeout("Jeb", "127.0.0.1", server.rpc_port, None, timeout)
+                .await
+        );
+        assert_eq!(client.client_identifier(), &IDENTIFIER);
+    }
+
+    #[tokio::test]
+    async fn unresolvable_address() {
+        assert_matches!(
+            AsyncClient::connect("Jeb", "no-such-host.invalid", 50000, None)
+                .await
+                .err(),
+            Some(Error::AddressResolution { .. })
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_calls() {
+        let server = echo_server();
+        let client = Arc::new(assert_ok!(
+            AsyncClient::connect("Jeb", "127.0.0.1", server.rpc_port, None).await
+        ));
+        let calls = (0..16u32).map(|i| {
+            let client = Arc::clone(&client);
+            tokio::spawn(async move {
+                let call = client.invoke_typed::<u32>("KRPC", "Echo", &[&i]);
+                call.await
+            })
+        });
+        for (i, call) in calls.collect::<Vec<_>>().into_iter().enumerate() {
+            assert_eq!(assert_ok!(call.await.unwrap()), i as u32);
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_for_stream_update() {
+        let server = echo_server();
+        let client = assert_ok!(
+            AsyncClient::connect(
+                "Jeb",
+                "127.0.0.1",
+                server.rpc_port,
+                Some(server.stream_port)
+            )
+            .await
+        );
+        let timeout = Some(Duration::from_millis(10));
+        assert!(!assert_ok!(client.wait_for_stream_update(timeout).await));
+
+        let wait = client.wait_for_stream_update(None);
+        server.send_update(StreamUpdate::default());
+        assert!(assert_ok!(wait.await));
+    }
+
+    #[tokio::test]
+    async fn connect_without_stream_server() {
+        let server = echo_server();
+        let mut client =
+            assert_ok!(AsyncClient::connect("Jeb", "127.0.0.1", server.rpc_port, None).await);
+        assert_matches!(
+            client.add_stream(&Call::<u32>::new("KRPC", "Add")).await,
+            Err(Error::NoStreamConnection)
+        );
+        assert_matches!(
+            client.wait_for_stream_update(None).await,
+            Err(Error::NoStreamConnection)
+        );
+        assert_ok!(client.close().await);
+    }
+}
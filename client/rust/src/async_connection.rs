@@ -0,0 +1,189 @@
+//! Length-prefixed framing over tokio sockets, for the async client.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use prost::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+
+use crate::connection::{
+    check_connection_response, rpc_connection_request, stream_connection_request, ClientIdentifier,
+    DEFAULT_MAX_MESSAGE_SIZE, MAX_VARINT_LENGTH,
+};
+use crate::error::{Error, Result};
+use crate::runtime;
+use crate::schema::ConnectionRequest;
+
+/// An open connection to the RPC or stream server.
+pub(crate) struct AsyncConnection {
+    reader: AsyncReader,
+    writer: AsyncWriter,
+}
+
+/// The receiving half of an `AsyncConnection`.
+pub(crate) struct AsyncReader {
+    reader: BufReader<OwnedReadHalf>,
+    buffer: Vec<u8>,
+    max_message_size: usize,
+}
+
+/// The sending half of an `AsyncConnection`.
+pub(crate) struct AsyncWriter {
+    writer: OwnedWriteHalf,
+    buffer: Vec<u8>,
+}
+
+impl AsyncConnection {
+    /// Opens a TCP connection to the server, trying every address that
+    /// `address` resolves to in turn, as `Connection::connect` does. Each
+    /// attempt fails with `ErrorKind::TimedOut` if `timeout` elapses first.
+    pub async fn connect(address: &str, port: u16, timeout: Option<Duration>) -> Result<Self> {
+        let resolution_error = || Error::AddressResolution {
+            address: address.to_string(),
+            port,
+        };
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((address, port))
+            .await
+            .map_err(|_| resolution_error())?
+            .collect();
+        if addrs.is_empty() {
+            return Err(resolution_error());
+        }
+
+        let mut last_error = None;
+        for addr in addrs {
+            let result = match timeout {
+                Some(timeout) => runtime::timeout(timeout, TcpStream::connect(addr))
+                    .await
+                    .unwrap_or_else(|| Err(std::io::ErrorKind::TimedOut.into())),
+                None => TcpStream::connect(addr).await,
+            };
+            match result {
+                Ok(stream) => return Self::new(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(Error::Io(last_error.unwrap()))
+    }
+
+    fn new(stream: TcpStream) -> Result<Self> {
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        Ok(AsyncConnection {
+            reader: AsyncReader {
+                reader: BufReader::new(reader),
+                buffer: Vec::new(),
+                max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            },
+            writer: AsyncWriter {
+                writer,
+                buffer: Vec::new(),
+            },
+        })
+    }
+
+    /// Connects to the RPC server and identifies the client as `name`,
+    /// returning the identifier the server assigned.
+    pub async fn connect_rpc(
+        address: &str,
+        port: u16,
+        name: &str,
+        timeout: Option<Duration>,
+    ) -> Result<(Self, ClientIdentifier)> {
+        let mut connection = Self::connect(address, port, timeout).await?;
+        let client_identifier = connection.handshake_rpc(name).await?;
+        Ok((connection, client_identifier))
+    }
+
+    /// Connects to the stream server on behalf of the client identified by
+    /// `client_identifier`.
+    pub async fn connect_stream(
+        address: &str,
+        port: u16,
+        client_identifier: &ClientIdentifier,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let mut connection = Self::connect(address, port, timeout).await?;
+        connection.handshake_stream(client_identifier).await?;
+        Ok(connection)
+    }
+
+    /// Identifies this client to the RPC server by name, and returns the
+    /// identifier the server assigned.
+    async fn handshake_rpc(&mut self, client_name: &str) -> Result<ClientIdentifier> {
+        self.handshake(&rpc_connection_request(client_name)).await
+    }
+
+    /// Attaches this connection to the stream server on behalf of the client
+    /// identified by `client_identifier`.
+    async fn handshake_stream(&mut self, client_identifier: &ClientIdentifier) -> Result<()> {
+        self.handshake(&stream_connection_request(client_identifier))
+            .await
+            .map(|_| ())
+    }
+
+    async fn handshake(&mut self, request: &ConnectionRequest) -> Result<ClientIdentifier> {
+        self.writer.send_message(request).await?;
+        check_connection_response(self.reader.receive_message().await?)
+    }
+
+    pub fn split(self) -> (AsyncReader, AsyncWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+impl AsyncReader {
+    /// Receives a varint length-prefixed message.
+    pub async fn receive_message<M: Message + Default>(&mut self) -> Result<M> {
+        let size = self.read_varint().await?;
+        if size > self.max_message_size as u64 {
+            return Err(Error::MessageTooLarge {
+                size,
+                max: self.max_message_size,
+            });
+        }
+        self.buffer.resize(size as usize, 0);
+        self.reader.read_exact(&mut self.buffer).await?;
+        Ok(M::decode(self.buffer.as_slice())?)
+    }
+
+    async fn read_varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for i in 0..MAX_VARINT_LENGTH {
+            let byte = self.reader.read_u8().await?;
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::MalformedLength)
+    }
+}
+
+impl AsyncWriter {
+    /// Sends a message, prefixed with its length encoded as a varint.
+    pub async fn send_message<M: Message>(&mut self, message: &M) -> Result<()> {
+        self.queue_message(message);
+        self.flush().await
+    }
+
+    /// Adds a length-prefixed message to those sent by the next `flush`.
+    pub fn queue_message<M: Message>(&mut self, message: &M) {
+        message
+            .encode_length_delimited(&mut self.buffer)
+            .expect("Vec<u8> grows to fit the message");
+    }
+
+    /// Sends the queued messages.
+    pub async fn flush(&mut self) -> Result<()> {
+        let result = self.writer.write_all(&self.buffer).await;
+        self.buffer.clear();
+        Ok(result?)
+    }
+
+    pub async fn shutdown(&mut self) -> Result<()> {
+        Ok(self.writer.shutdown().await?)
+    }
+}
@@ -0,0 +1,148 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::async_stream::AsyncStream;
+use crate::error::Result;
+use crate::runtime;
+use crate::stream::CallbackId;
+
+/// An event on the server, created through an `AsyncClient`.
+///
+/// It behaves as [`Event`](crate::Event) does, except that waiting for it is
+/// a future.
+#[derive(Debug)]
+pub struct AsyncEvent {
+    stream: AsyncStream<bool>,
+}
+
+impl AsyncEvent {
+    pub(crate) fn new(stream: AsyncStream<bool>) -> Self {
+        AsyncEvent { stream }
+    }
+
+    /// Asks the server to start evaluating the event. Waiting for the event
+    /// starts it too.
+    pub async fn start(&self) -> Result<()> {
+        self.stream.start().await
+    }
+
+    /// Resolves once the event occurs after the call, starting it first if
+    /// needed. Resolves to false if `timeout` elapses first, and fails if the
+    /// client is disconnected from the stream server while waiting.
+    pub fn wait(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<bool>> + Send + '_ {
+        let signal = self.stream.signal();
+        let mut seen = signal.count();
+        let occurred = async move {
+            self.start().await?;
+            loop {
+                signal.wait_since_async(seen, None).await?;
+                seen = signal.count();
+                if let Ok(true) = self.stream.get() {
+                    return Ok(true);
+                }
+            }
+        };
+        async move {
+            match timeout {
+                Some(timeout) => runtime::timeout(timeout, occurred)
+                    .await
+                    .unwrap_or(Ok(false)),
+                None => occurred.await,
+            }
+        }
+    }
+
+    /// Adds a callback that is run each time the event occurs, as
+    /// `Event::add_callback` does.
+    pub fn add_callback<F>(&self, mut callback: F) -> CallbackId
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.stream.add_callback(move |value| {
+            if let Ok(true) = value {
+                callback();
+            }
+        })
+    }
+
+    /// Removes a callback added with `add_callback`. Returns false if it had
+    /// already been removed.
+    pub fn remove_callback(&self, id: CallbackId) -> bool {
+        self.stream.remove_callback(id)
+    }
+
+    /// The stream of the event's condition.
+    pub fn stream(&self) -> &AsyncStream<bool> {
+        &self.stream
+    }
+
+    /// Removes the event from the server, as dropping it does, but waits for
+    /// that and reports any error in doing so.
+    pub async fn remove(self) -> Result<()> {
+        self.stream.remove().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_client::AsyncClient;
+    use crate::codec::{self, RemoteObject};
+    use crate::expression::Expression;
+    use crate::schema::{self, ProcedureResult, Request, Response, StreamResult, StreamUpdate};
+    use crate::test_server::TestServer;
+    use claim::assert_ok;
+    use std::time::Duration;
+
+    fn update(value: bool) -> StreamUpdate {
+        StreamUpdate {
+            results: vec![StreamResult {
+                id: 7,
+                result: Some(ProcedureResult {
+                    value: codec::encode(&value),
+                    ..Default::default()
+                }),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_until_condition_true() {
+        let server = TestServer::start(|request: Request| {
+            let value = match request.calls[0].procedure.as_str() {
+                "AddEvent" => codec::encode(&schema::Event {
+                    stream: Some(schema::Stream { id: 7 }),
+                }),
+                _ => Default::default(),
+            };
+            Response {
+                results: vec![ProcedureResult {
+                    value,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        });
+        let client = assert_ok!(
+            AsyncClient::connect(
+                "Jeb",
+                "127.0.0.1",
+                server.rpc_port,
+                Some(server.stream_port)
+            )
+            .await
+        );
+        let event = assert_ok!(client.add_event(&Expression::from_id(1)).await);
+        assert!(!assert_ok!(
+            event.wait(Some(Duration::from_millis(10))).await
+        ));
+
+        let wait = event.wait(Some(Duration::from_secs(5)));
+        server.send_update(update(false));
+        server.send_update(update(true));
+        assert!(assert_ok!(wait.await));
+        assert_ok!(event.remove().await);
+    }
+}
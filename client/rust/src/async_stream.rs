@@ -0,0 +1,358 @@
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::async_client::AsyncShared;
+use crate::call::Call;
+use crate::codec::Decode;
+use crate::error::{Error, Result};
+use crate::runtime;
+use crate::schema;
+use crate::stream::CallbackId;
+use crate::stream_manager::{decode_any, StreamCallback, StreamState, StreamValue, UpdateSignal};
+
+type Changed = Pin<Box<dyn Future<Output = Result<bool>> + Send + Sync>>;
+
+/// A streamed procedure call on an `AsyncClient`.
+///
+/// It behaves as [`Stream`](crate::Stream) does, except that waiting for an
+/// update is a future. It is also a [`futures_core::Stream`] of its values:
+/// each item is the latest value when polled, so a slow consumer skips the
+/// updates it missed rather than falling behind. Only updates received after
+/// the handle was created are yielded, a paused stream yields nothing until
+/// started, and the stream ends once the client is disconnected from the
+/// stream server.
+///
+/// Dropping the last handle removes the stream from the server with a task
+/// spawned on the current runtime, if there is one. Use `remove` to wait for
+/// that and see any error.
+pub struct AsyncStream<T> {
+    shared: Arc<AsyncShared>,
+    state: Arc<StreamState>,
+    removed: bool,
+    seen: u64,
+    changed: Option<Changed>,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> AsyncStream<T>
+where
+    T: Decode + Clone + Send + Sync + 'static,
+{
+    pub(crate) async fn add(
+        shared: &Arc<AsyncShared>,
+        call: &Call<T>,
+        start: bool,
+    ) -> Result<Self> {
+        shared.streams()?;
+        // Added paused and started once registered, as `Stream::add` does.
+        let add = Call::<schema::Stream>::new("KRPC", "AddStream")
+            .arg(call.message())
+            .arg(&false);
+        let stream = Self::from_id(shared, shared.call(&add).await?.id)?;
+        if start {
+            stream.start().await?;
+        }
+        Ok(stream)
+    }
+
+    /// Wraps a paused stream that already exists on the server, such as the
+    /// stream of an event.
+    pub(crate) fn from_id(shared: &Arc<AsyncShared>, id: u64) -> Result<Self> {
+        let state = shared.streams()?.register(id, false, decode_any::<T>);
+        Ok(AsyncStream {
+            shared: Arc::clone(shared),
+            seen: state.signal.count(),
+            state,
+            removed: false,
+            changed: None,
+            value: PhantomData,
+        })
+    }
+
+    /// The most recently received value of the stream, or the error the
+    /// procedure failed with. Fails with `Error::NoStreamValue` if no update
+    /// has been received yet.
+    pub fn get(&self) -> Result<T> {
+        match self.state.value() {
+            Some(value) => value.get(),
+            None => Err(Error::NoStreamValue),
+        }
+    }
+
+    /// Adds a callback that is run with each value received for the stream,
+    /// as `Stream::add_callback` does. Callbacks run on the task that
+    /// receives stream updates, so must not block.
+    pub fn add_callback<F>(&self, mut callback: F) -> CallbackId
+    where
+        F: FnMut(Result<T>) + Send + 'static,
+    {
+        let callback: Arc<StreamCallback> =
+            Arc::new(Mutex::new(move |value: &StreamValue| callback(value.get())));
+        self.state.callbacks.add(callback)
+    }
+}
+
+impl<T> AsyncStream<T> {
+    /// The id the server assigned to the stream.
+    pub fn id(&self) -> u64 {
+        self.state.id
+    }
+
+    /// Whether the server has been asked to send updates for the stream.
+    pub fn started(&self) -> bool {
+        self.state.started.load(Ordering::SeqCst)
+    }
+
+    /// Asks the server to start sending updates for a stream added with
+    /// `AsyncClient::add_stream_paused`. Does nothing if it was already
+    /// started.
+    pub async fn start(&self) -> Result<()> {
+        if self.started() {
+            return Ok(());
+        }
+        self.shared
+            .call(&Call::<()>::new("KRPC", "StartStream").arg(&self.id()))
+            .await?;
+        self.state.started.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Resolves once an update for the stream is received after the call,
+    /// starting the stream first if it is paused. Resolves to false if
+    /// `timeout` elapses first, and fails if the client is disconnected from
+    /// the stream server while waiting.
+    pub fn wait(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<bool>> + Send + '_ {
+        let seen = self.state.signal.count();
+        async move {
+            self.start().await?;
+            self.state.signal.wait_since_async(seen, timeout).await
+        }
+    }
+
+    pub(crate) fn signal(&self) -> &UpdateSignal {
+        &self.state.signal
+    }
+
+    /// Removes a callback added with `add_callback`. Returns false if it had
+    /// already been removed.
+    pub fn remove_callback(&self, id: CallbackId) -> bool {
+        self.state.callbacks.remove(id)
+    }
+
+    /// The update rate of the stream in Hertz, or zero if it is unlimited.
+    pub fn rate(&self) -> f32 {
+        *self.state.rate.lock().unwrap()
+    }
+
+    /// Sets the update rate of the stream in Hertz. Zero removes the limit.
+    pub async fn set_rate(&self, rate: f32) -> Result<()> {
+        self.shared
+            .call(
+                &Call::<()>::new("KRPC", "SetStreamRate")
+                    .arg(&self.id())
+                    .arg(&rate),
+            )
+            .await?;
+        *self.state.rate.lock().unwrap() = rate;
+        Ok(())
+    }
+
+    /// Removes the stream from the server, as dropping it does, but waits
+    /// for that and reports any error in doing so.
+    pub async fn remove(mut self) -> Result<()> {
+        self.removed = true;
+        if self.unregister() {
+            self.shared.call(&remove_stream(self.id())).await?;
+        }
+        Ok(())
+    }
+
+    /// Drops this handle, returning true if it was the last one, in which
+    /// case the stream should be removed from the server.
+    fn unregister(&self) -> bool {
+        match self.shared.streams() {
+            Ok(streams) => streams.unregister(self.id()),
+            Err(_) => false,
+        }
+    }
+}
+
+fn remove_stream(id: u64) -> Call<()> {
+    Call::new("KRPC", "RemoveStream").arg(&id)
+}
+
+impl<T> futures_core::Stream for AsyncStream<T>
+where
+    T: Decode + Clone + Send + Sync + 'static,
+{
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<T>>> {
+        let this = &mut *self;
+        let changed = this.changed.get_or_insert_with(|| {
+            let state = Arc::clone(&this.state);
+            let seen = this.seen;
+            Box::pin(async move { state.signal.wait_since_async(seen, None).await })
+        });
+        let result = std::task::ready!(changed.as_mut().poll(cx));
+        this.changed = None;
+        match result {
+            Ok(_) => {
+                // Values are stored before the count is incremented, so the
+                // value read here is at least as recent as update `seen`.
+                this.seen = this.state.signal.count();
+                Poll::Ready(Some(this.get()))
+            }
+            Err(_) => Poll::Ready(None),
+        }
+    }
+}
+
+impl<T> Drop for AsyncStream<T> {
+    fn drop(&mut self) {
+        if !self.removed && self.unregister() {
+            // Without a runtime the client cannot be in use, and the server
+            // discards the stream once it disconnects.
+            let shared = Arc::clone(&self.shared);
+            let id = self.id();
+            runtime::detach(async move {
+                let _ = shared.call(&remove_stream(id)).await;
+            });
+        }
+    }
+}
+
+impl<T> fmt::Debug for AsyncStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncStream")
+            .field("id", &self.id())
+            .field("started", &self.started())
+            .field("rate", &self.rate())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_client::AsyncClient;
+    use crate::codec;
+    use crate::schema::{ProcedureResult, Request, Response, StreamResult, StreamUpdate};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+
+    /// Answers the `KRPC` stream procedures, streaming every call with id 7,
+    /// and records the name of each procedure called.
+    fn start_server() -> (TestServer, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&calls);
+        let server = TestServer::start(move |request: Request| {
+            let call = &request.calls[0];
+            log.lock().unwrap().push(call.procedure.clone());
+            let value = match call.procedure.as_str() {
+                "AddStream" => codec::encode(&schema::Stream { id: 7 }),
+                _ => Default::default(),
+            };
+            Response {
+                results: vec![ProcedureResult {
+                    value,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        });
+        (server, calls)
+    }
+
+    async fn connect(server: &TestServer) -> AsyncClient {
+        assert_ok!(
+            AsyncClient::connect(
+                "Jeb",
+                "127.0.0.1",
+                server.rpc_port,
+                Some(server.stream_port)
+            )
+            .await
+        )
+    }
+
+    fn update(value: f64) -> StreamUpdate {
+        StreamUpdate {
+            results: vec![StreamResult {
+                id: 7,
+                result: Some(ProcedureResult {
+                    value: codec::encode(&value),
+                    ..Default::default()
+                }),
+            }],
+        }
+    }
+
+    async fn next<S: futures_core::Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn wait_for_updates() {
+        let (server, calls) = start_server();
+        let client = connect(&server).await;
+        let stream = assert_ok!(
+            client
+                .add_stream_paused(&Call::<f64>::new("SpaceCenter", "get_UT"))
+                .await
+        );
+        assert_matches!(stream.get(), Err(Error::NoStreamValue));
+        let timeout = Some(Duration::from_millis(10));
+        assert!(!assert_ok!(stream.wait(timeout).await));
+        assert!(stream.started());
+
+        let wait = stream.wait(None);
+        server.send_update(update(1.5));
+        assert!(assert_ok!(wait.await));
+        assert_eq!(assert_ok!(stream.get()), 1.5);
+
+        assert_ok!(stream.set_rate(5.0).await);
+        assert_eq!(stream.rate(), 5.0);
+        assert_ok!(stream.remove().await);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["AddStream", "StartStream", "SetStreamRate", "RemoveStream"]
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_of_updates() {
+        let (server, _) = start_server();
+        let mut client = connect(&server).await;
+        let mut stream = assert_ok!(client.add_stream(&Call::<f64>::new("KRPC", "get_UT")).await);
+
+        server.send_update(update(1.0));
+        assert_eq!(assert_ok!(next(&mut stream).await.unwrap()), 1.0);
+        server.send_update(update(2.0));
+        assert_eq!(assert_ok!(next(&mut stream).await.unwrap()), 2.0);
+
+        assert_ok!(client.close().await);
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn removes_stream_when_dropped() {
+        let (server, calls) = start_server();
+        let client = connect(&server).await;
+        let stream = assert_ok!(client.add_stream(&Call::<f64>::new("KRPC", "get_UT")).await);
+        drop(stream);
+        // Let the spawned removal send its request before this call does.
+        tokio::task::yield_now().await;
+        assert_ok!(client.call(&Call::<()>::new("KRPC", "GetStatus")).await);
+        assert!(calls.lock().unwrap().contains(&"RemoveStream".to_string()));
+    }
+}
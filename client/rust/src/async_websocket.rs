@@ -0,0 +1,254 @@
+//! The async client's transport on wasm32 targets, over the host's
+//! WebSocket API.
+//!
+//! Sockets there cannot be read by blocking, only by handling the events
+//! they raise, so the handlers queue the messages received for the
+//! `AsyncReader` to take in turn. It otherwise offers what the TCP
+//! transport does, so the same `Pipeline` and stream receiver run over it.
+//! Each message is a single binary frame, as described in the `websocket`
+//! module.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use prost::Message;
+use tokio::sync::{mpsc, oneshot};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{BinaryType, CloseEvent, Event, MessageEvent, WebSocket};
+
+use crate::client::single_result;
+use crate::connection::ClientIdentifier;
+use crate::error::{Error, Result};
+use crate::runtime;
+use crate::schema::Response;
+use crate::websocket;
+
+/// An open connection to the RPC or stream server.
+pub(crate) struct AsyncConnection {
+    reader: AsyncReader,
+    writer: AsyncWriter,
+}
+
+/// The receiving half of an `AsyncConnection`.
+pub(crate) struct AsyncReader {
+    messages: mpsc::UnboundedReceiver<Result<Bytes>>,
+    _socket: Rc<Socket>,
+}
+
+/// The sending half of an `AsyncConnection`.
+pub(crate) struct AsyncWriter {
+    socket: Rc<Socket>,
+    queued: Vec<Vec<u8>>,
+}
+
+impl AsyncConnection {
+    /// Connects to the RPC server, identifying the client as `name`, and
+    /// returns the identifier the server assigned. Opening the socket fails
+    /// with `ErrorKind::TimedOut` if `timeout` elapses first.
+    pub async fn connect_rpc(
+        address: &str,
+        port: u16,
+        name: &str,
+        timeout: Option<Duration>,
+    ) -> Result<(Self, ClientIdentifier)> {
+        let mut connection =
+            Self::connect(&websocket::rpc_uri(address, port, name), timeout).await?;
+        connection
+            .writer
+            .send_message(&websocket::client_id_request())
+            .await?;
+        let response: Response = connection.reader.receive_message().await?;
+        if let Some(error) = response.error {
+            return Err(Error::from_request_error(error));
+        }
+        let client_identifier = websocket::decode_client_identifier(&single_result(response)?)?;
+        Ok((connection, client_identifier))
+    }
+
+    /// Connects to the stream server on behalf of the client identified by
+    /// `client_identifier`.
+    pub async fn connect_stream(
+        address: &str,
+        port: u16,
+        client_identifier: &ClientIdentifier,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        Self::connect(
+            &websocket::stream_uri(address, port, client_identifier),
+            timeout,
+        )
+        .await
+    }
+
+    async fn connect(uri: &str, timeout: Option<Duration>) -> Result<Self> {
+        let (received, messages) = mpsc::unbounded_channel();
+        let socket = match timeout {
+            Some(timeout) => runtime::timeout(timeout, Socket::open(uri, received))
+                .await
+                .unwrap_or_else(|| Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into())),
+            None => Socket::open(uri, received).await,
+        };
+        let socket = Rc::new(socket?);
+        Ok(AsyncConnection {
+            reader: AsyncReader {
+                messages,
+                _socket: Rc::clone(&socket),
+            },
+            writer: AsyncWriter {
+                socket,
+                queued: Vec::new(),
+            },
+        })
+    }
+
+    pub fn split(self) -> (AsyncReader, AsyncWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+impl AsyncReader {
+    /// Receives the next message. Fails with `Error::NotConnected` once the
+    /// socket has closed.
+    pub async fn receive_message<M: Message + Default>(&mut self) -> Result<M> {
+        let data = self
+            .messages
+            .recv()
+            .await
+            .unwrap_or(Err(Error::NotConnected))?;
+        Ok(M::decode(data)?)
+    }
+}
+
+impl AsyncWriter {
+    /// Sends a message.
+    pub async fn send_message<M: Message>(&mut self, message: &M) -> Result<()> {
+        self.queue_message(message);
+        self.flush().await
+    }
+
+    /// Adds a message to those sent by the next `flush`.
+    pub fn queue_message<M: Message>(&mut self, message: &M) {
+        self.queued.push(message.encode_to_vec());
+    }
+
+    /// Sends the queued messages, each in a frame of its own.
+    pub async fn flush(&mut self) -> Result<()> {
+        let result = self
+            .queued
+            .iter()
+            .try_for_each(|message| self.socket.send(message));
+        self.queued.clear();
+        result
+    }
+
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.socket.close();
+        Ok(())
+    }
+}
+
+/// An open WebSocket, and the event handlers it calls. The handlers are
+/// only held to keep them alive for as long as the socket.
+struct Socket {
+    socket: WebSocket,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+    _on_open: Closure<dyn FnMut(Event)>,
+}
+
+impl Socket {
+    /// Opens a WebSocket to `uri`, and waits until it is open. Each message
+    /// received is passed to `received`, and once the socket closes it is
+    /// passed `Error::NotConnected`.
+    async fn open(uri: &str, received: mpsc::UnboundedSender<Result<Bytes>>) -> Result<Self> {
+        let socket = WebSocket::new(uri).map_err(from_js_error)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let (opened, open) = oneshot::channel::<Result<()>>();
+        let opened = Rc::new(RefCell::new(Some(opened)));
+
+        let on_open = {
+            let opened = Rc::clone(&opened);
+            Closure::<dyn FnMut(Event)>::new(move |_| {
+                if let Some(opened) = opened.borrow_mut().take() {
+                    let _ = opened.send(Ok(()));
+                }
+            })
+        };
+        let on_message = {
+            let received = received.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                let message = match event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                    Ok(data) => Ok(js_sys::Uint8Array::new(&data).to_vec().into()),
+                    Err(_) => Err(Error::WebSocket(
+                        "received a text message, expected binary".to_string(),
+                    )),
+                };
+                // The reader may have gone, in which case so has the socket.
+                let _ = received.send(message);
+            })
+        };
+        let on_close = {
+            let uri = uri.to_string();
+            Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+                match opened.borrow_mut().take() {
+                    Some(opened) => {
+                        let _ = opened.send(Err(Error::WebSocket(format!(
+                            "could not connect to {} (close code {})",
+                            uri,
+                            event.code()
+                        ))));
+                    }
+                    None => {
+                        let _ = received.send(Err(Error::NotConnected));
+                    }
+                }
+            })
+        };
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        let socket = Socket {
+            socket,
+            _on_message: on_message,
+            _on_close: on_close,
+            _on_open: on_open,
+        };
+        open.await.map_err(|_| Error::NotConnected)??;
+        Ok(socket)
+    }
+
+    fn send(&self, message: &[u8]) -> Result<()> {
+        if self.socket.ready_state() != WebSocket::OPEN {
+            return Err(Error::NotConnected);
+        }
+        self.socket
+            .send_with_u8_array(message)
+            .map_err(from_js_error)
+    }
+
+    fn close(&self) {
+        // The server may already have gone, so failing to say goodbye is not
+        // an error.
+        let _ = self.socket.close();
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // The handlers are freed with the socket, so the host must not call
+        // them afterwards.
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        self.close();
+    }
+}
+
+fn from_js_error(error: JsValue) -> Error {
+    Error::WebSocket(error.as_string().unwrap_or_else(|| format!("{:?}", error)))
+}
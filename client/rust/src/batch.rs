@@ -0,0 +1,275 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+
+use crate::call::Call;
+use crate::client::Client;
+use crate::codec::Decode;
+use crate::error::{Error, Result};
+use crate::schema::{ProcedureCall, ProcedureResult, Request, Response};
+
+/// Queues procedure calls and sends them to the server in a single request.
+///
+/// The server runs the calls in the order they were added, and each call
+/// succeeds or fails on its own: a failing call does not stop the ones after
+/// it from running.
+///
+/// ```no_run
+/// # fn main() -> krpc::Result<()> {
+/// # let client = krpc::Client::connect("", "127.0.0.1", 50000, None)?;
+/// let mut batch = client.batch();
+/// let ut = batch.add(krpc::Call::<f64>::new("SpaceCenter", "get_UT"));
+/// let paused = batch.add(krpc::Call::<bool>::new("KRPC", "get_Paused"));
+/// let results = batch.send()?;
+/// println!("{} {}", results.get(&ut)?, results.get(&paused)?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Batch<'a> {
+    client: &'a Client,
+    id: u64,
+    calls: Vec<ProcedureCall>,
+}
+
+/// Refers to a call queued in a `Batch`, and to its result in the
+/// `BatchResults` returned when the batch is sent.
+#[derive(Debug)]
+pub struct BatchCall<T> {
+    batch: u64,
+    index: usize,
+    result: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for BatchCall<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BatchCall<T> {}
+
+impl<T> BatchCall<T> {
+    pub(crate) fn new(batch: u64, index: usize) -> Self {
+        BatchCall {
+            batch,
+            index,
+            result: PhantomData,
+        }
+    }
+}
+
+/// A new batch id, unique within the process, so that results are only
+/// looked up with calls queued in the same batch.
+pub(crate) fn next_batch_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(client: &'a Client) -> Self {
+        Batch {
+            client,
+            id: next_batch_id(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Queues `call`, returning a handle to retrieve its result with.
+    pub fn add<T: Decode>(&mut self, call: Call<T>) -> BatchCall<T> {
+        self.calls.push(call.into_message());
+        BatchCall::new(self.id, self.calls.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Sends all queued calls in one request. Fails only if the request as a
+    /// whole fails; errors from individual calls are reported by
+    /// `BatchResults::get`.
+    pub fn send(self) -> Result<BatchResults> {
+        if self.calls.is_empty() {
+            return Ok(BatchResults::empty(self.id));
+        }
+        let expected = self.calls.len();
+        let request = Request { calls: self.calls };
+        let response = self.client.send_request(&request)?;
+        BatchResults::from_response(self.id, expected, response)
+    }
+}
+
+/// The results of the calls in a sent `Batch`.
+#[derive(Debug)]
+pub struct BatchResults {
+    batch: u64,
+    results: Vec<ProcedureResult>,
+}
+
+impl BatchResults {
+    /// The results of a batch with no calls, which is not sent.
+    pub(crate) fn empty(batch: u64) -> Self {
+        BatchResults {
+            batch,
+            results: Vec::new(),
+        }
+    }
+
+    /// The results of the `expected` calls in `batch`, from the response to
+    /// the request that sent them.
+    pub(crate) fn from_response(batch: u64, expected: usize, response: Response) -> Result<Self> {
+        if response.results.len() != expected {
+            return Err(Error::InvalidResponse(format!(
+                "expected {} results, got {}",
+                expected,
+                response.results.len()
+            )));
+        }
+        Ok(BatchResults {
+            batch,
+            results: response.results,
+        })
+    }
+
+    /// Decodes the result of `call`, or returns the error the procedure
+    /// failed with. Fails with `Error::InvalidArguments` if `call` was
+    /// queued in a different batch.
+    pub fn get<T: Decode>(&self, call: &BatchCall<T>) -> Result<T> {
+        if call.batch != self.batch {
+            return Err(Error::InvalidArguments(
+                "the call was queued in a different batch".to_string(),
+            ));
+        }
+        Ok(T::decode(&self.raw(call.index)?)?)
+    }
+
+    /// The encoded result of the call at `index`, in the order the calls
+    /// were added. Fails with `Error::InvalidArguments` if the batch had no
+    /// call at `index`.
+    pub fn raw(&self, index: usize) -> Result<Bytes> {
+        let result = self.results.get(index).ok_or_else(|| {
+            Error::InvalidArguments(format!(
+                "no call at index {} in a batch of {}",
+                index,
+                self.results.len()
+            ))
+        })?;
+        match &result.error {
+            Some(error) => Err(Error::from_procedure_error(error.clone())),
+            None => Ok(result.value.clone()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::schema;
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Squares each `KRPC.Square` call and fails anything else.
+    fn handle(request: Request) -> Response {
+        let results = request
+            .calls
+            .iter()
+            .map(|call| {
+                if call.procedure != "Square" {
+                    return ProcedureResult {
+                        error: Some(schema::Error {
+                            service: "KRPC".to_string(),
+                            name: "InvalidOperationException".to_string(),
+                            description: format!("No procedure {}", call.procedure),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    };
+                }
+                let x: i32 = codec::decode(&call.arguments[0].value).unwrap();
+                ProcedureResult {
+                    value: codec::encode(&(x * x)),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        Response {
+            results,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn one_round_trip() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let server = TestServer::start(move |request| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            handle(request)
+        });
+        let client = assert_ok!(Client::connect("", "127.0.0.1", server.rpc_port, None));
+
+        let mut batch = client.batch();
+        let calls: Vec<_> = (0..20)
+            .map(|x| batch.add(Call::<i32>::new("KRPC", "Square").arg(&x)))
+            .collect();
+        assert_eq!(batch.len(), 20);
+        let results = assert_ok!(batch.send());
+        for (x, call) in calls.iter().enumerate() {
+            assert_eq!(assert_ok!(results.get(call)), (x * x) as i32);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn failing_call_does_not_hide_others() {
+        let server = TestServer::start(handle);
+        let client = assert_ok!(Client::connect("", "127.0.0.1", server.rpc_port, None));
+
+        let mut batch = client.batch();
+        let first = batch.add(Call::<i32>::new("KRPC", "Square").arg(&3));
+        let missing = batch.add(Call::<i32>::new("KRPC", "Cube").arg(&3));
+        let last = batch.add(Call::<i32>::new("KRPC", "Square").arg(&4));
+        let results = assert_ok!(batch.send());
+        assert_eq!(assert_ok!(results.get(&first)), 9);
+        assert_matches!(results.get(&missing), Err(Error::InvalidOperation(_)));
+        assert_eq!(assert_ok!(results.get(&last)), 16);
+    }
+
+    #[test]
+    fn rejects_calls_from_other_batches() {
+        let server = TestServer::start(handle);
+        let client = assert_ok!(Client::connect("", "127.0.0.1", server.rpc_port, None));
+
+        let mut small = client.batch();
+        small.add(Call::<i32>::new("KRPC", "Square").arg(&2));
+        let mut large = client.batch();
+        large.add(Call::<i32>::new("KRPC", "Square").arg(&3));
+        let second = large.add(Call::<i32>::new("KRPC", "Square").arg(&4));
+        let small = assert_ok!(small.send());
+        assert_matches!(small.get(&second), Err(Error::InvalidArguments(_)));
+        assert_matches!(small.raw(1), Err(Error::InvalidArguments(_)));
+        assert_eq!(assert_ok!(small.raw(0)), codec::encode(&4));
+    }
+
+    #[test]
+    fn empty_batch() {
+        let server = TestServer::start(handle);
+        let client = assert_ok!(Client::connect("", "127.0.0.1", server.rpc_port, None));
+        assert!(assert_ok!(client.batch().send()).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::codec::{self, Encode};
+use crate::schema::{Argument, ProcedureCall};
+
+/// A remote procedure call whose result decodes to a `T`.
+///
+/// A procedure is addressed either by service and procedure name, or by the
+/// `service_id` and `procedure_id` of its service and procedure, as described
+/// in `doc/src/communication-protocols/messages.rst`. Arguments are added in
+/// the order of the procedure's parameters; `arg_at` skips over parameters
+/// that should take their default value.
+pub struct Call<T> {
+    message: ProcedureCall,
+    next_position: u32,
+    result: PhantomData<fn() -> T>,
+}
+
+impl<T> Call<T> {
+    /// Calls the procedure named `procedure` in the service named `service`.
+    pub fn new(service: &str, procedure: &str) -> Self {
+        Self::from_message(ProcedureCall {
+            service: service.to_string(),
+            procedure: procedure.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Calls the procedure with id `procedure_id` in the service with id
+    /// `service_id`.
+    pub fn by_id(service_id: u32, procedure_id: u32) -> Self {
+        Self::from_message(ProcedureCall {
+            service_id,
+            procedure_id,
+            ..Default::default()
+        })
+    }
+
+    /// Wraps an existing `ProcedureCall` message.
+    pub fn from_message(message: ProcedureCall) -> Self {
+        let next_position = message
+            .arguments
+            .iter()
+            .map(|arg| arg.position + 1)
+            .max()
+            .unwrap_or(0);
+        Call {
+            message,
+            next_position,
+            result: PhantomData,
+        }
+    }
+
+    /// Adds `value` as the argument following the last one added.
+    pub fn arg<A: Encode + ?Sized>(self, value: &A) -> Self {
+        let position = self.next_position;
+        self.arg_at(position, value)
+    }
+
+    /// Adds `value` as the argument at the zero-indexed `position`.
+    pub fn arg_at<A: Encode + ?Sized>(mut self, position: u32, value: &A) -> Self {
+        self.message.arguments.push(Argument {
+            position,
+            value: codec::encode(value),
+        });
+        self.next_position = self.next_position.max(position + 1);
+        self
+    }
+
+    /// Adds each of `values` in turn, as `arg` does.
+    pub fn args(self, values: &[&dyn Encode]) -> Self {
+        values.iter().fold(self, |call, value| call.arg(*value))
+    }
+
+    pub fn message(&self) -> &ProcedureCall {
+        &self.message
+    }
+
+    pub fn into_message(self) -> ProcedureCall {
+        self.message
+    }
+}
+
+impl<T> Clone for Call<T> {
+    fn clone(&self) -> Self {
+        Call {
+            message: self.message.clone(),
+            next_position: self.next_position,
+            result: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Call<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Call").field(&self.message).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positional_arguments() {
+        let call = Call::<()>::new("SpaceCenter", "Foo")
+            .arg(&1u32)
+            .arg("jeb")
+            .arg_at(4, &true)
+            .arg(&2u32);
+        let message = call.message();
+        assert_eq!(message.service, "SpaceCenter");
+        assert_eq!(message.procedure, "Foo");
+        let positions: Vec<u32> = message.arguments.iter().map(|a| a.position).collect();
+        assert_eq!(positions, [0, 1, 4, 5]);
+        assert_eq!(message.arguments[1].value.as_ref(), b"\x03jeb");
+    }
+
+    #[test]
+    fn by_id() {
+        let call = Call::<()>::by_id(2, 7).args(&[&300u32]);
+        let message = call.into_message();
+        assert_eq!((message.service_id, message.procedure_id), (2, 7));
+        assert!(message.service.is_empty() && message.procedure.is_empty());
+        assert_eq!(message.arguments[0].value.as_ref(), b"\xac\x02");
+    }
+}
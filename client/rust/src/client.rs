@@ -0,0 +1,561 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::batch::Batch;
+use crate::call::Call;
+use crate::codec::{Decode, Encode};
+use crate::connection::{ClientIdentifier, Connection};
+use crate::error::{Error, Result};
+use crate::event::Event;
+use crate::expression::Expression;
+use crate::schema::{self, ProcedureCall, Request, Response};
+use crate::serial::SerialConnection;
+use crate::stream::{CallbackId, Stream};
+use crate::stream_manager::{StreamConnection, StreamManager, UpdateCallback};
+use crate::transport::Transport;
+#[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+use crate::websocket::{self, WebSocketConnection};
+
+/// A kRPC client, through which all remote procedure calls are made.
+///
+/// The client owns the connection to the RPC server and, optionally, a
+/// connection to the stream server. Both are closed together, either by
+/// calling `close` or once the client and every stream added through it
+/// have been dropped.
+///
+/// A `Client` is a handle to the session. Cloning it is cheap, and the
+/// clones share the same connections, so they can be moved to other threads
+/// and used at the same time. Each request is sent and its response received
+/// with the RPC connection locked, so the calls of different threads never
+/// interleave on the socket; they run one after another, in the order the
+/// threads reach the connection. Closing any clone closes the session for
+/// all of them.
+#[derive(Clone)]
+pub struct Client {
+    shared: Arc<Shared>,
+}
+
+/// The state shared by a `Client` and the streams added through it.
+pub(crate) struct Shared {
+    rpc: Mutex<Box<dyn Transport>>,
+    client_identifier: ClientIdentifier,
+    streams: Option<Arc<StreamManager>>,
+    stream_connection: Mutex<Option<StreamConnection>>,
+}
+
+impl Client {
+    /// Connects to the RPC server at `address` and `rpc_port`, identifying
+    /// the client as `name` in the in-game UI. If `stream_port` is given,
+    /// also connects to the stream server on that port.
+    ///
+    /// The default ports are [`DEFAULT_RPC_PORT`](crate::DEFAULT_RPC_PORT) and
+    /// [`DEFAULT_STREAM_PORT`](crate::DEFAULT_STREAM_PORT).
+    pub fn connect(
+        name: &str,
+        address: &str,
+        rpc_port: u16,
+        stream_port: Option<u16>,
+    ) -> Result<Self> {
+        let mut rpc = Connection::new(address.to_string(), rpc_port);
+        rpc.connect()?;
+        let client_identifier = rpc.handshake_rpc(name)?;
+
+        let stream_connection = match stream_port {
+            Some(port) => Some(StreamConnection::connect(
+                address.to_string(),
+                port,
+                &client_identifier,
+                None,
+            )?),
+            None => None,
+        };
+
+        Ok(Client::new(
+            Box::new(rpc),
+            client_identifier,
+            stream_connection,
+        ))
+    }
+
+    /// Connects to the server's SerialIO server over `port`, which may be a
+    /// serial port or any other byte pipe, identifying the client as `name`.
+    ///
+    /// The SerialIO server has no stream server, so adding streams and events
+    /// fails with `Error::NoStreamConnection`.
+    ///
+    /// Reads that time out, as a serial port's do when no byte arrives in
+    /// time, are retried for as long as the server takes to respond. Use
+    /// `connect_serial_with_timeout` to bound that.
+    pub fn connect_serial<S>(name: &str, port: S) -> Result<Self>
+    where
+        S: Read + Write + Send + 'static,
+    {
+        Self::connect_serial_with_timeout(name, port, None)
+    }
+
+    /// Like `connect_serial`, but the handshake and each call fail with an
+    /// `Error::Io` of kind `TimedOut` if the server's response has not
+    /// arrived within `timeout`, such as when the link is dead. After a call
+    /// times out, the connection is closed, and later calls fail with
+    /// `Error::NotConnected`.
+    pub fn connect_serial_with_timeout<S>(
+        name: &str,
+        port: S,
+        timeout: Option<Duration>,
+    ) -> Result<Self>
+    where
+        S: Read + Write + Send + 'static,
+    {
+        let (rpc, client_identifier) = SerialConnection::connect(port, name, timeout)?;
+        Ok(Client::new(Box::new(rpc), client_identifier, None))
+    }
+
+    /// Like `connect`, but talks to the server over WebSockets, which can
+    /// pass through HTTP proxies. The ports are the same as for `connect`.
+    ///
+    /// On wasm32 targets, use [`AsyncClient`](crate::AsyncClient) instead,
+    /// which connects over WebSockets there.
+    #[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+    pub fn connect_websocket(
+        name: &str,
+        address: &str,
+        rpc_port: u16,
+        stream_port: Option<u16>,
+    ) -> Result<Self> {
+        let mut rpc = WebSocketConnection::connect_rpc(address, rpc_port, name)?;
+        let response = send_request(&mut rpc, &websocket::client_id_request())?;
+        let client_identifier = websocket::decode_client_identifier(&single_result(response)?)?;
+
+        let stream_connection = match stream_port {
+            Some(port) => {
+                let stream =
+                    WebSocketConnection::connect_stream(address, port, &client_identifier)?;
+                let socket = stream.try_clone_socket()?;
+                Some(StreamConnection::start(Box::new(stream), socket)?)
+            }
+            None => None,
+        };
+
+        Ok(Client::new(
+            Box::new(rpc),
+            client_identifier,
+            stream_connection,
+        ))
+    }
+
+    fn new(
+        rpc: Box<dyn Transport>,
+        client_identifier: ClientIdentifier,
+        stream_connection: Option<StreamConnection>,
+    ) -> Self {
+        Client {
+            shared: Arc::new(Shared {
+                rpc: Mutex::new(rpc),
+                client_identifier,
+                streams: stream_connection.as_ref().map(|c| Arc::clone(c.manager())),
+                stream_connection: Mutex::new(stream_connection),
+            }),
+        }
+    }
+
+    /// The identifier the server assigned to this client.
+    pub fn client_identifier(&self) -> &ClientIdentifier {
+        &self.shared.client_identifier
+    }
+
+    /// Invokes the procedure named `procedure` in the service named
+    /// `service` and returns its encoded result. Each of `args` is passed in
+    /// order as the procedure's positional arguments.
+    ///
+    /// This is the escape hatch for services that have no generated bindings.
+    pub fn invoke(&self, service: &str, procedure: &str, args: &[&dyn Encode]) -> Result<Bytes> {
+        self.invoke_call(Call::<Bytes>::new(service, procedure).args(args).message())
+    }
+
+    /// Like `invoke`, but decodes the result as a `T`.
+    pub fn invoke_typed<T: Decode>(
+        &self,
+        service: &str,
+        procedure: &str,
+        args: &[&dyn Encode],
+    ) -> Result<T> {
+        self.call(&Call::new(service, procedure).args(args))
+    }
+
+    /// Makes a typed call and decodes its result.
+    pub fn call<T: Decode>(&self, call: &Call<T>) -> Result<T> {
+        self.shared.call(call)
+    }
+
+    /// Sends `call` to the server, which may address the procedure either by
+    /// name or by id, and returns its encoded result.
+    pub fn invoke_call(&self, call: &ProcedureCall) -> Result<Bytes> {
+        self.shared.invoke_call(call)
+    }
+
+    /// Starts a batch of calls to send to the server in a single request.
+    pub fn batch(&self) -> Batch<'_> {
+        Batch::new(self)
+    }
+
+    /// Streams the result of `call`. The server starts sending updates
+    /// straight away.
+    pub fn add_stream<T>(&self, call: &Call<T>) -> Result<Stream<T>>
+    where
+        T: Decode + Clone + Send + Sync + 'static,
+    {
+        Stream::add(&self.shared, call, true)
+    }
+
+    /// Like `add_stream`, but the server does not send updates until
+    /// `Stream::start` is called.
+    pub fn add_stream_paused<T>(&self, call: &Call<T>) -> Result<Stream<T>>
+    where
+        T: Decode + Clone + Send + Sync + 'static,
+    {
+        Stream::add(&self.shared, call, false)
+    }
+
+    /// Calls a procedure that returns an event, such as `KRPC.AddEvent`.
+    pub fn event(&self, call: &Call<schema::Event>) -> Result<Event> {
+        self.shared.streams()?;
+        let event = self.call(call)?;
+        let stream = event
+            .stream
+            .ok_or_else(|| Error::InvalidResponse("event has no stream".to_string()))?;
+        Ok(Event::new(Stream::from_id(&self.shared, stream.id)?))
+    }
+
+    /// Creates an event that occurs whenever `condition`, which must be a
+    /// boolean expression, becomes true.
+    pub fn add_event(&self, condition: &Expression) -> Result<Event> {
+        self.event(&Call::new("KRPC", "AddEvent").arg(condition))
+    }
+
+    /// Blocks until the next `StreamUpdate` is received, whichever streams
+    /// it touches. Returns false if `timeout` elapses first, and fails if the
+    /// client is not, or stops being, connected to the stream server.
+    pub fn wait_for_stream_update(&self, timeout: Option<Duration>) -> Result<bool> {
+        self.shared.streams()?.signal.wait(timeout)
+    }
+
+    /// Adds a callback that is run after each `StreamUpdate` has been
+    /// processed, once the callbacks of the streams it updated have run.
+    /// Fails if the client is not connected to the stream server.
+    ///
+    /// These callbacks follow the same rules as those added with
+    /// `Stream::add_callback`.
+    pub fn add_stream_update_callback<F>(&self, callback: F) -> Result<CallbackId>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let callback: Arc<UpdateCallback> = Arc::new(Mutex::new(callback));
+        Ok(self.shared.streams()?.callbacks.add(callback))
+    }
+
+    /// Removes a callback added with `add_stream_update_callback`. Returns
+    /// false if it had already been removed.
+    pub fn remove_stream_update_callback(&self, id: CallbackId) -> bool {
+        match self.shared.streams() {
+            Ok(streams) => streams.callbacks.remove(id),
+            Err(_) => false,
+        }
+    }
+
+    /// Sends a request and waits for its response. Fails if the server
+    /// rejected the request as a whole.
+    pub(crate) fn send_request(&self, request: &Request) -> Result<Response> {
+        self.shared.send_request(request)
+    }
+
+    /// Closes the connections to the RPC and stream servers. The connections
+    /// are shared by every clone of the client, so this closes all of them:
+    /// their calls fail with `Error::NotConnected`, and the streams added
+    /// through any of them stop receiving updates.
+    pub fn close(&self) -> Result<()> {
+        self.shared.close()
+    }
+}
+
+impl Shared {
+    pub fn call<T: Decode>(&self, call: &Call<T>) -> Result<T> {
+        Ok(T::decode(&self.invoke_call(call.message())?)?)
+    }
+
+    pub fn invoke_call(&self, call: &ProcedureCall) -> Result<Bytes> {
+        let request = Request {
+            calls: vec![call.clone()],
+        };
+        single_result(self.send_request(&request)?)
+    }
+
+    pub fn send_request(&self, request: &Request) -> Result<Response> {
+        send_request(self.rpc.lock().unwrap().as_mut(), request)
+    }
+
+    /// The manager for the client's streams, or an error if the client is
+    /// not connected to the stream server.
+    pub fn streams(&self) -> Result<&Arc<StreamManager>> {
+        self.streams.as_ref().ok_or(Error::NoStreamConnection)
+    }
+
+    fn close(&self) -> Result<()> {
+        if let Some(mut stream_connection) = self.stream_connection.lock().unwrap().take() {
+            stream_connection.close();
+        }
+        self.rpc.lock().unwrap().close()
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // Nothing can be done about an error here, and the sockets are closed
+        // as they are dropped either way.
+        let _ = self.close();
+    }
+}
+
+/// The result of the only call in a request, or the error it failed with.
+pub(crate) fn single_result(response: Response) -> Result<Bytes> {
+    let mut results = response.results;
+    if results.len() != 1 {
+        return Err(Error::InvalidResponse(format!(
+            "expected 1 result, got {}",
+            results.len()
+        )));
+    }
+    let result = results.remove(0);
+    match result.error {
+        Some(error) => Err(Error::from_procedure_error(error)),
+        None => Ok(result.value),
+    }
+}
+
+/// Sends a request over `rpc` and waits for its response. Fails if the
+/// server rejected the request as a whole.
+fn send_request(rpc: &mut dyn Transport, request: &Request) -> Result<Response> {
+    rpc.send_message(request)?;
+    let response: Response = rpc.receive_message()?;
+    match response.error {
+        Some(error) => Err(Error::from_request_error(error)),
+        None => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::schema::{self, connection_request, ProcedureResult, StreamUpdate};
+    use crate::test_server::{TestServer, IDENTIFIER};
+    use claim::{assert_matches, assert_ok};
+
+    #[test]
+    fn connect_and_close() {
+        let server = TestServer::start(|_| Response::default());
+        let client = assert_ok!(Client::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            Some(server.stream_port)
+        ));
+        assert_eq!(client.client_identifier(), &IDENTIFIER);
+        // Closing a clone closes the connections they share.
+        assert_ok!(client.clone().close());
+        assert_matches!(
+            client.call(&Call::<()>::new("KRPC", "GetStatus")),
+            Err(Error::NotConnected)
+        );
+
+        let request = server.join();
+        assert_eq!(request.r#type(), connection_request::Type::Rpc);
+        assert_eq!(request.client_name, "Jeb");
+    }
+
+    #[test]
+    fn connect_without_stream_server() {
+        let server = TestServer::start(|_| Response::default());
+        let client = assert_ok!(Client::connect("Jeb", "127.0.0.1", server.rpc_port, None));
+        assert_matches!(
+            client.add_stream(&Call::<u32>::new("KRPC", "Add")),
+            Err(Error::NoStreamConnection)
+        );
+        assert_matches!(
+            client.wait_for_stream_update(None),
+            Err(Error::NoStreamConnection)
+        );
+        drop(client);
+        server.join();
+    }
+
+    #[test]
+    fn wait_for_stream_update() {
+        let server = TestServer::start(|_| Response::default());
+        let client = assert_ok!(Client::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            Some(server.stream_port)
+        ));
+        let timeout = Some(Duration::from_millis(10));
+        assert!(!assert_ok!(client.wait_for_stream_update(timeout)));
+
+        // Updates wake waiters even if they touch no stream the client knows.
+        let signal = &client.shared.streams().unwrap().signal;
+        let seen = signal.count();
+        server.send_update(StreamUpdate::default());
+        assert!(assert_ok!(signal.wait_since(seen, None)));
+    }
+
+    #[test]
+    fn stream_update_callbacks() {
+        let server = TestServer::start(|_| Response::default());
+        let client = assert_ok!(Client::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            Some(server.stream_port)
+        ));
+        let (updates, received) = std::sync::mpsc::channel();
+        let id = assert_ok!(client.add_stream_update_callback(move || {
+            updates.send(()).unwrap();
+        }));
+        server.send_update(StreamUpdate::default());
+        assert_ok!(received.recv_timeout(Duration::from_secs(5)));
+        assert!(client.remove_stream_update_callback(id));
+        assert!(!client.remove_stream_update_callback(id));
+    }
+
+    fn ok(value: impl Into<Bytes>) -> ProcedureResult {
+        ProcedureResult {
+            value: value.into(),
+            ..Default::default()
+        }
+    }
+
+    fn error(name: &str) -> schema::Error {
+        schema::Error {
+            service: "KRPC".to_string(),
+            name: name.to_string(),
+            description: "Oops".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Answers `KRPC.Add(a, b)`, both addressed by name and as procedure 2 of
+    /// service 1, and fails anything else.
+    fn handle(request: Request) -> Response {
+        let call = &request.calls[0];
+        let add = (call.service == "KRPC" && call.procedure == "Add")
+            || (call.service_id == 1 && call.procedure_id == 2);
+        if !add {
+            return Response {
+                results: vec![ProcedureResult {
+                    error: Some(error("ArgumentException")),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+        }
+        let a: u32 = codec::decode(&call.arguments[0].value).unwrap();
+        let b: u32 = codec::decode(&call.arguments[1].value).unwrap();
+        Response {
+            results: vec![ok(codec::encode(&(a + b)))],
+            ..Default::default()
+        }
+    }
+
+    fn connect(server: &TestServer) -> Client {
+        assert_ok!(Client::connect("Jeb", "127.0.0.1", server.rpc_port, None))
+    }
+
+    #[test]
+    fn invoke() {
+        let server = TestServer::start(handle);
+        let client = connect(&server);
+        let result = assert_ok!(client.invoke("KRPC", "Add", &[&1u32, &2u32]));
+        assert_eq!(result.as_ref(), b"\x03");
+        assert_eq!(
+            assert_ok!(client.invoke_typed::<u32>("KRPC", "Add", &[&40u32, &2u32])),
+            42
+        );
+    }
+
+    #[test]
+    fn invoke_by_id() {
+        let server = TestServer::start(handle);
+        let client = connect(&server);
+        let call = Call::<u32>::by_id(1, 2).arg(&3u32).arg(&4u32);
+        assert_eq!(assert_ok!(client.call(&call)), 7);
+    }
+
+    #[test]
+    fn procedure_error() {
+        let server = TestServer::start(handle);
+        let client = connect(&server);
+        assert_matches!(
+            client.invoke("KRPC", "Missing", &[]),
+            Err(Error::Argument(e)) if e.description == "Oops"
+        );
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Client>();
+        assert_send_sync::<Stream<f64>>();
+        assert_send_sync::<Event>();
+
+        let server = TestServer::start(handle);
+        let client = connect(&server);
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let client = client.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        assert_ok!(client.invoke("KRPC", "Add", &[&1u32, &1u32]));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn calls_do_not_interleave() {
+        let server = TestServer::start(handle);
+        let client = connect(&server);
+        std::thread::scope(|scope| {
+            for thread in 0..10u32 {
+                let client = &client;
+                scope.spawn(move || {
+                    for i in 0..10u32 {
+                        let sum = client.invoke_typed::<u32>("KRPC", "Add", &[&thread, &i]);
+                        assert_eq!(assert_ok!(sum), thread + i);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn request_error() {
+        let server = TestServer::start(|_| Response {
+            error: Some(schema::Error {
+                description: "Malformed request".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let client = connect(&server);
+        assert_matches!(
+            client.invoke("KRPC", "Add", &[]),
+            Err(Error::Request(e)) if e.description == "Malformed request"
+        );
+    }
+}
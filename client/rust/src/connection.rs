@@ -1,41 +1,132 @@
-use std::net::{Shutdown, TcpListener, TcpStream};
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use krpc_core::framing;
+use krpc_core::io::FromStd;
+use prost::Message;
+
+pub use krpc_core::connection::ClientIdentifier;
+pub(crate) use krpc_core::connection::{rpc_connection_request, stream_connection_request};
+pub use krpc_core::framing::DEFAULT_MAX_MESSAGE_SIZE;
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+pub(crate) use krpc_core::framing::MAX_VARINT_LENGTH;
+
+use crate::error::{Error, Result};
+use crate::schema::{ConnectionRequest, ConnectionResponse};
+use crate::transport::Transport;
+
+/// The port the kRPC server listens on for RPC connections by default.
+pub const DEFAULT_RPC_PORT: u16 = 50000;
+
+/// The port the kRPC server listens on for stream connections by default.
+pub const DEFAULT_STREAM_PORT: u16 = 50001;
 
 pub struct Connection {
     address: String,
-    port: String,
+    port: u16,
+    timeout: Option<Duration>,
     stream: Option<TcpStream>,
     used_up: bool,
+    client_identifier: Option<ClientIdentifier>,
+    max_message_size: usize,
+    read_buffer: Vec<u8>,
+    write_buffer: Vec<u8>,
 }
 
 impl Connection {
-    pub fn new(address: String, port: String) -> Self {
+    pub fn new(address: String, port: u16) -> Self {
         Connection {
             address,
             port,
+            timeout: None,
             stream: None,
             used_up: false,
+            client_identifier: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            read_buffer: Vec::new(),
+            write_buffer: Vec::new(),
         }
     }
 
-    pub fn connect(&mut self) -> std::io::Result<()> {
+    /// Sets the timeout used when opening the socket. `None` (the default)
+    /// waits for as long as the operating system allows.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Opens a TCP connection to the server. `address` may be a hostname,
+    /// an IPv4 address or an IPv6 address; every address it resolves to is
+    /// tried in turn until one accepts the connection.
+    pub fn connect(&mut self) -> Result<()> {
         if self.used_up {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Cannot reuse a `Connection`.",
-            ));
+            return Err(Error::Reused);
         }
 
-        let listener = TcpListener::bind(format!("{}:{}", self.address, self.port))?;
-        if self.port == "0" {
-            self.port = listener.local_addr().unwrap().port().to_string();
+        let addrs = resolve(&self.address, self.port)?;
+        let mut last_error = None;
+        for addr in addrs {
+            let result = match self.timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+            match result {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    self.stream = Some(stream);
+                    return Ok(());
+                }
+                Err(e) => last_error = Some(e),
+            }
         }
+        Err(Error::Io(last_error.unwrap()))
+    }
+
+    /// Sets the largest message, in bytes, that `receive_message` accepts.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+
+    /// Sends a message, prefixed with its length encoded as a varint.
+    pub fn send_message<M: Message>(&mut self, message: &M) -> Result<()> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        write_message(stream, message, &mut self.write_buffer)
+    }
+
+    /// Receives a varint length-prefixed message. Blocks until the whole
+    /// message has arrived.
+    pub fn receive_message<M: Message + Default>(&mut self) -> Result<M> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        read_message(stream, &mut self.read_buffer, self.max_message_size)
+    }
+
+    /// Identifies this client to the RPC server by name, as shown in the
+    /// in-game client list, and returns the identifier the server assigned.
+    pub fn handshake_rpc(&mut self, client_name: &str) -> Result<ClientIdentifier> {
+        self.handshake(&rpc_connection_request(client_name))
+    }
+
+    /// Attaches this connection to the stream server on behalf of the client
+    /// that was assigned `client_identifier` by the RPC server.
+    pub fn handshake_stream(&mut self, client_identifier: &ClientIdentifier) -> Result<()> {
+        self.handshake(&stream_connection_request(client_identifier))
+            .map(|_| ())
+    }
 
-        let (stream, _) = listener.accept()?;
-        self.stream = Some(stream);
-        Ok(())
+    fn handshake(&mut self, request: &ConnectionRequest) -> Result<ClientIdentifier> {
+        self.send_message(request)?;
+        let identifier = check_connection_response(self.receive_message()?)?;
+        self.client_identifier = Some(identifier);
+        Ok(identifier)
     }
 
-    pub fn close(&mut self) -> std::io::Result<()> {
+    /// The identifier the server assigned to this client during the
+    /// handshake, if it has completed.
+    pub fn client_identifier(&self) -> Option<&ClientIdentifier> {
+        self.client_identifier.as_ref()
+    }
+
+    pub fn close(&mut self) -> Result<()> {
         match &self.stream {
             Some(stream) => match stream.shutdown(Shutdown::Both) {
                 Ok(()) => {
@@ -43,11 +134,114 @@ impl Connection {
                     self.stream = None;
                     Ok(())
                 }
-                Err(e) => Err(e),
+                Err(e) => Err(e.into()),
             },
             None => Ok(()),
         }
     }
+
+    /// Returns a second handle to the underlying socket, which can be used
+    /// to shut the connection down from another thread.
+    pub(crate) fn try_clone_socket(&self) -> Result<TcpStream> {
+        let stream = self.stream.as_ref().ok_or(Error::NotConnected)?;
+        Ok(stream.try_clone()?)
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+}
+
+/// Resolves `address` and `port` to the socket addresses to try connecting
+/// to. Fails with `Error::AddressResolution` if the lookup fails or finds no
+/// addresses.
+pub(crate) fn resolve(address: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let resolution_error = || Error::AddressResolution {
+        address: address.to_string(),
+        port,
+    };
+    let addrs: Vec<SocketAddr> = (address, port)
+        .to_socket_addrs()
+        .map_err(|_| resolution_error())?
+        .collect();
+    if addrs.is_empty() {
+        return Err(resolution_error());
+    }
+    Ok(addrs)
+}
+
+/// Encodes `message` into `buffer` with its length prefix and writes it out.
+pub(crate) fn write_message<W: Write, M: Message>(
+    writer: &mut W,
+    message: &M,
+    buffer: &mut Vec<u8>,
+) -> Result<()> {
+    Ok(framing::write_message(
+        &mut FromStd(writer),
+        message,
+        buffer,
+    )?)
+}
+
+/// Maps an unsuccessful handshake to its error, and returns the client
+/// identifier of a successful one.
+pub(crate) fn check_connection_response(response: ConnectionResponse) -> Result<ClientIdentifier> {
+    Ok(krpc_core::connection::check_connection_response(response)?)
+}
+
+/// Reads a length-prefixed message, using `buffer` to hold its body.
+pub(crate) fn read_message<R: Read, M: Message + Default>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    max_message_size: usize,
+) -> Result<M> {
+    Ok(framing::read_message(
+        &mut FromStd(reader),
+        buffer,
+        max_message_size,
+    )?)
+}
+
+/// Reads the body of a length-prefixed message into `buffer`.
+pub(crate) fn read_frame<R: Read>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    max_message_size: usize,
+) -> Result<()> {
+    Ok(framing::read_frame(
+        &mut FromStd(reader),
+        buffer,
+        max_message_size,
+    )?)
+}
+
+impl Transport for Connection {
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        Ok(framing::write_frame(&mut FromStd(stream), message)?)
+    }
+
+    fn receive(&mut self) -> Result<&[u8]> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        read_frame(stream, &mut self.read_buffer, self.max_message_size)?;
+        Ok(&self.read_buffer)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        Connection::close(self)
+    }
+
+    fn send_buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.write_buffer
+    }
 }
 
 #[allow(unused_must_use)] // TODO: handle possible error
@@ -56,3 +250,257 @@ impl Drop for Connection {
         self.close();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{self, connection_request, connection_response};
+    use claim::{assert_matches, assert_ok};
+    use std::io::Cursor;
+    use std::net::TcpListener;
+
+    /// A reader that hands out at most one byte per call.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn procedure_call() -> schema::ProcedureCall {
+        schema::ProcedureCall {
+            service: "ServiceName".to_string(),
+            procedure: "ProcedureName".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn connects_to_listening_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut connection = Connection::new("127.0.0.1".to_string(), port);
+        assert_ok!(connection.connect());
+        assert!(connection.is_connected());
+        assert_ok!(listener.accept());
+    }
+
+    #[test]
+    fn connects_over_ipv6() {
+        let listener = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener,
+            Err(_) => return, // IPv6 not available on this host
+        };
+        let port = listener.local_addr().unwrap().port();
+        let mut connection = Connection::new("::1".to_string(), port);
+        assert_ok!(connection.connect());
+    }
+
+    #[test]
+    fn unresolvable_address() {
+        let mut connection = Connection::new("no such host.invalid".to_string(), 50000);
+        assert_matches!(connection.connect(), Err(Error::AddressResolution { .. }));
+    }
+
+    #[test]
+    fn cannot_reuse_closed_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut connection = Connection::new("127.0.0.1".to_string(), port);
+        assert_ok!(connection.connect());
+        assert_ok!(connection.close());
+        assert_matches!(connection.connect(), Err(Error::Reused));
+    }
+
+    #[test]
+    fn write_message_with_size() {
+        let mut data = Vec::new();
+        let mut buffer = Vec::new();
+        assert_ok!(write_message(&mut data, &procedure_call(), &mut buffer));
+        let mut expected = vec![0x1c];
+        expected.extend_from_slice(b"\x0a\x0bServiceName\x12\x0dProcedureName");
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn read_message_from_partial_reads() {
+        let mut data = Vec::new();
+        assert_ok!(write_message(&mut data, &procedure_call(), &mut Vec::new()));
+        assert_ok!(write_message(&mut data, &procedure_call(), &mut Vec::new()));
+        let mut reader = Trickle(Cursor::new(data));
+        let mut buffer = Vec::new();
+        for _ in 0..2 {
+            let call: schema::ProcedureCall = assert_ok!(read_message(
+                &mut reader,
+                &mut buffer,
+                DEFAULT_MAX_MESSAGE_SIZE
+            ));
+            assert_eq!(call, procedure_call());
+        }
+    }
+
+    #[test]
+    fn read_message_too_large() {
+        let mut reader = Cursor::new(vec![0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_matches!(
+            read_message::<_, schema::ProcedureCall>(&mut reader, &mut Vec::new(), 1024),
+            Err(Error::MessageTooLarge {
+                size: 0xffffffff,
+                max: 1024
+            })
+        );
+    }
+
+    #[test]
+    fn read_message_malformed_length() {
+        let mut reader = Cursor::new(vec![0xff; 11]);
+        assert_matches!(
+            read_message::<_, schema::ProcedureCall>(&mut reader, &mut Vec::new(), 1024),
+            Err(Error::MalformedLength)
+        );
+    }
+
+    #[test]
+    fn send_and_receive_over_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut connection = Connection::new("127.0.0.1".to_string(), port);
+        assert_ok!(connection.connect());
+        let (mut server, _) = listener.accept().unwrap();
+
+        assert_ok!(connection.send_message(&procedure_call()));
+        let call: schema::ProcedureCall = assert_ok!(read_message(
+            &mut server,
+            &mut Vec::new(),
+            DEFAULT_MAX_MESSAGE_SIZE
+        ));
+        assert_eq!(call, procedure_call());
+
+        assert_ok!(write_message(&mut server, &call, &mut Vec::new()));
+        let call: schema::ProcedureCall = assert_ok!(connection.receive_message());
+        assert_eq!(call, procedure_call());
+    }
+
+    #[test]
+    fn send_and_receive_as_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut connection = Connection::new("127.0.0.1".to_string(), port);
+        assert_ok!(connection.connect());
+        let (mut server, _) = listener.accept().unwrap();
+        let transport: &mut dyn Transport = &mut connection;
+
+        for _ in 0..2 {
+            assert_ok!(transport.send_message(&procedure_call()));
+            let call: schema::ProcedureCall = assert_ok!(read_message(
+                &mut server,
+                &mut Vec::new(),
+                DEFAULT_MAX_MESSAGE_SIZE
+            ));
+            assert_eq!(call, procedure_call());
+        }
+        // The encoded message is kept, so the next one reuses its space.
+        assert_eq!(
+            transport.send_buffer().len(),
+            procedure_call().encoded_len()
+        );
+
+        assert_ok!(write_message(
+            &mut server,
+            &procedure_call(),
+            &mut Vec::new()
+        ));
+        let call: schema::ProcedureCall = assert_ok!(transport.receive_message());
+        assert_eq!(call, procedure_call());
+    }
+
+    #[test]
+    fn send_before_connect() {
+        let mut connection = Connection::new("127.0.0.1".to_string(), DEFAULT_RPC_PORT);
+        assert_matches!(
+            connection.send_message(&procedure_call()),
+            Err(Error::NotConnected)
+        );
+    }
+
+    /// Accepts a connection on `listener`, reads the connection request and
+    /// replies with `response`.
+    fn serve_handshake(
+        listener: TcpListener,
+        response: ConnectionResponse,
+    ) -> std::thread::JoinHandle<ConnectionRequest> {
+        std::thread::spawn(move || {
+            let (mut server, _) = listener.accept().unwrap();
+            let request = read_message(&mut server, &mut Vec::new(), DEFAULT_MAX_MESSAGE_SIZE);
+            write_message(&mut server, &response, &mut Vec::new()).unwrap();
+            request.unwrap()
+        })
+    }
+
+    fn handshake_rpc_with(
+        response: ConnectionResponse,
+    ) -> (Connection, Result<ClientIdentifier>, ConnectionRequest) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = serve_handshake(listener, response);
+        let mut connection = Connection::new("127.0.0.1".to_string(), port);
+        assert_ok!(connection.connect());
+        let result = connection.handshake_rpc("Jeb");
+        (connection, result, server.join().unwrap())
+    }
+
+    #[test]
+    fn rpc_handshake() {
+        let identifier: ClientIdentifier = *b"0123456789abcdef";
+        let (connection, result, request) = handshake_rpc_with(ConnectionResponse {
+            status: connection_response::Status::Ok as i32,
+            client_identifier: identifier.to_vec().into(),
+            ..Default::default()
+        });
+        assert_eq!(request.r#type(), connection_request::Type::Rpc);
+        assert_eq!(request.client_name, "Jeb");
+        assert!(request.client_identifier.is_empty());
+        assert_eq!(assert_ok!(result), identifier);
+        assert_eq!(connection.client_identifier(), Some(&identifier));
+    }
+
+    #[test]
+    fn rpc_handshake_errors() {
+        let cases = [
+            connection_response::Status::MalformedMessage,
+            connection_response::Status::Timeout,
+            connection_response::Status::WrongType,
+        ];
+        for status in cases {
+            let (connection, result, _) = handshake_rpc_with(ConnectionResponse {
+                status: status as i32,
+                message: "denied".to_string(),
+                ..Default::default()
+            });
+            match (status, result) {
+                (
+                    connection_response::Status::MalformedMessage,
+                    Err(Error::MalformedConnectionRequest(m)),
+                )
+                | (connection_response::Status::Timeout, Err(Error::ConnectionTimeout(m)))
+                | (connection_response::Status::WrongType, Err(Error::WrongConnectionType(m))) => {
+                    assert_eq!(m, "denied")
+                }
+                (status, result) => panic!("{:?} mapped to {:?}", status, result),
+            }
+            assert_eq!(connection.client_identifier(), None);
+        }
+    }
+
+    #[test]
+    fn rpc_handshake_bad_identifier() {
+        let (_, result, _) = handshake_rpc_with(ConnectionResponse {
+            status: connection_response::Status::Ok as i32,
+            client_identifier: vec![1, 2, 3].into(),
+            ..Default::default()
+        });
+        assert_matches!(result, Err(Error::InvalidConnectionResponse(_)));
+    }
+}
@@ -0,0 +1,852 @@
+//! Calling procedures by name, for services that have no generated
+//! bindings.
+//!
+//! A [`DynamicClient`] fetches the definitions of the server's services with
+//! `KRPC.GetServices` when it connects. Procedures are then looked up by the
+//! names they have on the server, and their arguments are checked against
+//! the parameter types from the definitions and encoded at runtime. Results
+//! are decoded into a [`Value`] according to the procedure's return type.
+//!
+//! ```no_run
+//! use krpc::dynamic::{DynamicClient, Value};
+//!
+//! # fn main() -> krpc::Result<()> {
+//! let client = DynamicClient::connect("", "127.0.0.1", 50000, None)?;
+//! let space_center = client.service("SpaceCenter")?;
+//! let vessel = space_center.property("ActiveVessel")?;
+//! let vessel_class = space_center.class("Vessel")?;
+//! if let Value::String(name) = vessel_class.property(&vessel, "Name")? {
+//!     println!("{}", name);
+//! }
+//! vessel_class.set_property(&vessel, "Name", "My Vessel".into())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use prost::Message;
+
+use crate::client::Client;
+use crate::codec::{self, Decode};
+use crate::error::{Error, Result};
+use crate::schema::{self, r#type::TypeCode, Argument, ProcedureCall, Type};
+
+/// A value passed to or returned from a procedure called through a
+/// [`DynamicClient`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// The result of a procedure that returns nothing, or a null object.
+    None,
+    Double(f64),
+    Float(f32),
+    SInt32(i32),
+    SInt64(i64),
+    UInt32(u32),
+    UInt64(u64),
+    Bool(bool),
+    String(String),
+    Bytes(Bytes),
+    /// An instance of the class `class` defined by `service`.
+    Object {
+        service: String,
+        class: String,
+        id: u64,
+    },
+    /// A value of the enumeration `enumeration` defined by `service`.
+    Enum {
+        service: String,
+        enumeration: String,
+        value: i32,
+    },
+    Event(schema::Event),
+    ProcedureCall(schema::ProcedureCall),
+    Stream(schema::Stream),
+    Status(schema::Status),
+    Services(schema::Services),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Set(Vec<Value>),
+    /// The entries of a dictionary, in the order the server sent them.
+    Dictionary(Vec<(Value, Value)>),
+}
+
+macro_rules! value_from {
+    ($($type:ty => $variant:ident),+) => {
+        $(
+            impl From<$type> for Value {
+                fn from(value: $type) -> Self {
+                    Value::$variant(value)
+                }
+            }
+        )+
+    };
+}
+
+value_from!(
+    f64 => Double,
+    f32 => Float,
+    i32 => SInt32,
+    i64 => SInt64,
+    u32 => UInt32,
+    u64 => UInt64,
+    bool => Bool,
+    String => String,
+    Bytes => Bytes
+);
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+/// A client that calls procedures by name, checking and encoding their
+/// arguments at runtime from the server's service definitions.
+///
+/// Cloning a `DynamicClient` is cheap; the clones share the connection and
+/// the definitions.
+#[derive(Clone)]
+pub struct DynamicClient {
+    client: Client,
+    services: Arc<schema::Services>,
+}
+
+impl DynamicClient {
+    /// Connects to the server as `Client::connect` does, then fetches the
+    /// definitions of its services.
+    pub fn connect(
+        name: &str,
+        address: &str,
+        rpc_port: u16,
+        stream_port: Option<u16>,
+    ) -> Result<Self> {
+        Self::new(Client::connect(name, address, rpc_port, stream_port)?)
+    }
+
+    /// Fetches the definitions of the services of the server that `client`
+    /// is connected to.
+    pub fn new(client: Client) -> Result<Self> {
+        let services = client.invoke_typed("KRPC", "GetServices", &[])?;
+        Ok(Self::with_services(client, services))
+    }
+
+    /// Uses `services` as the definitions of the server's services, instead
+    /// of fetching them.
+    pub fn with_services(client: Client, services: schema::Services) -> Self {
+        DynamicClient {
+            client,
+            services: Arc::new(services),
+        }
+    }
+
+    /// The client the procedures are called through.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The definitions of the server's services.
+    pub fn services(&self) -> &schema::Services {
+        &self.services
+    }
+
+    /// The service named `name`.
+    pub fn service(&self, name: &str) -> Result<DynamicService<'_>> {
+        let definition = self
+            .services
+            .services
+            .iter()
+            .find(|service| service.name == name)
+            .ok_or_else(|| Error::NotFound(format!("service {}", name)))?;
+        Ok(DynamicService {
+            client: self,
+            definition,
+        })
+    }
+
+    /// Checks and encodes `args` for the procedure `procedure` of `service`
+    /// and calls it. Parameters with default values may be left off the end
+    /// of `args`.
+    fn invoke(
+        &self,
+        service: &str,
+        procedure: &schema::Procedure,
+        args: &[&Value],
+    ) -> Result<Value> {
+        let call = procedure_call(service, procedure, args)?;
+        let value = self.client.invoke_call(&call)?;
+        match &procedure.return_type {
+            Some(typ) => decode(&value, typ),
+            None => Ok(Value::None),
+        }
+    }
+}
+
+/// A service of the server, whose procedures are called by name.
+#[derive(Clone, Copy)]
+pub struct DynamicService<'a> {
+    client: &'a DynamicClient,
+    definition: &'a schema::Service,
+}
+
+impl<'a> DynamicService<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.definition.name
+    }
+
+    /// The definition of the service.
+    pub fn definition(&self) -> &'a schema::Service {
+        self.definition
+    }
+
+    /// The class named `name`.
+    pub fn class(&self, name: &str) -> Result<DynamicClass<'a>> {
+        let definition = self
+            .definition
+            .classes
+            .iter()
+            .find(|class| class.name == name)
+            .ok_or_else(|| Error::NotFound(format!("class {}.{}", self.name(), name)))?;
+        Ok(DynamicClass {
+            service: *self,
+            definition,
+        })
+    }
+
+    /// The definition of the enumeration named `name`.
+    pub fn enumeration(&self, name: &str) -> Result<&'a schema::Enumeration> {
+        self.definition
+            .enumerations
+            .iter()
+            .find(|enumeration| enumeration.name == name)
+            .ok_or_else(|| Error::NotFound(format!("enumeration {}.{}", self.name(), name)))
+    }
+
+    /// The value named `name` of the enumeration `enumeration`, to pass as
+    /// an argument.
+    pub fn enum_value(&self, enumeration: &str, name: &str) -> Result<Value> {
+        let definition = self.enumeration(enumeration)?;
+        let value = definition
+            .values
+            .iter()
+            .find(|value| value.name == name)
+            .ok_or_else(|| {
+                Error::NotFound(format!("value {}.{}.{}", self.name(), enumeration, name))
+            })?;
+        Ok(Value::Enum {
+            service: self.name().to_string(),
+            enumeration: enumeration.to_string(),
+            value: value.value,
+        })
+    }
+
+    /// The definition of the procedure named `name`, which for members of
+    /// classes includes the class, as in `Vessel_get_Name`.
+    pub fn procedure(&self, name: &str) -> Result<&'a schema::Procedure> {
+        self.definition
+            .procedures
+            .iter()
+            .find(|procedure| procedure.name == name)
+            .ok_or_else(|| Error::NotFound(format!("procedure {}.{}", self.name(), name)))
+    }
+
+    /// Calls the procedure named `name` with `args`.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value> {
+        let args: Vec<&Value> = args.iter().collect();
+        self.invoke(name, &args)
+    }
+
+    /// Gets the value of the service's property `name`.
+    pub fn property(&self, name: &str) -> Result<Value> {
+        self.invoke(&format!("get_{}", name), &[])
+    }
+
+    /// Sets the value of the service's property `name`.
+    pub fn set_property(&self, name: &str, value: Value) -> Result<()> {
+        self.invoke(&format!("set_{}", name), &[&value])?;
+        Ok(())
+    }
+
+    fn invoke(&self, name: &str, args: &[&Value]) -> Result<Value> {
+        self.client.invoke(self.name(), self.procedure(name)?, args)
+    }
+}
+
+impl fmt::Debug for DynamicService<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicService").field(&self.name()).finish()
+    }
+}
+
+/// A class defined by a service, whose methods and properties are called by
+/// name on objects returned from other calls.
+#[derive(Clone, Copy)]
+pub struct DynamicClass<'a> {
+    service: DynamicService<'a>,
+    definition: &'a schema::Class,
+}
+
+impl<'a> DynamicClass<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.definition.name
+    }
+
+    /// The definition of the class.
+    pub fn definition(&self) -> &'a schema::Class {
+        self.definition
+    }
+
+    /// The service that defines the class.
+    pub fn service(&self) -> DynamicService<'a> {
+        self.service
+    }
+
+    /// Calls the method `name` of `object` with `args`.
+    pub fn call(&self, object: &Value, name: &str, args: &[Value]) -> Result<Value> {
+        let args: Vec<&Value> = std::iter::once(object).chain(args).collect();
+        self.invoke(&format!("{}_{}", self.name(), name), &args)
+    }
+
+    /// Calls the static method `name` with `args`.
+    pub fn call_static(&self, name: &str, args: &[Value]) -> Result<Value> {
+        let args: Vec<&Value> = args.iter().collect();
+        self.invoke(&format!("{}_static_{}", self.name(), name), &args)
+    }
+
+    /// Gets the value of the property `name` of `object`.
+    pub fn property(&self, object: &Value, name: &str) -> Result<Value> {
+        self.invoke(&format!("{}_get_{}", self.name(), name), &[object])
+    }
+
+    /// Sets the value of the property `name` of `object`.
+    pub fn set_property(&self, object: &Value, name: &str, value: Value) -> Result<()> {
+        self.invoke(&format!("{}_set_{}", self.name(), name), &[object, &value])?;
+        Ok(())
+    }
+
+    fn invoke(&self, name: &str, args: &[&Value]) -> Result<Value> {
+        self.service.invoke(name, args)
+    }
+}
+
+impl fmt::Debug for DynamicClass<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicClass")
+            .field(&self.service.name())
+            .field(&self.name())
+            .finish()
+    }
+}
+
+/// Builds the call to `procedure`, checking that `args` match its
+/// parameters.
+fn procedure_call(
+    service: &str,
+    procedure: &schema::Procedure,
+    args: &[&Value],
+) -> Result<ProcedureCall> {
+    let parameters = &procedure.parameters;
+    if args.len() > parameters.len() {
+        return Err(Error::InvalidArguments(format!(
+            "{}.{} takes at most {} arguments, got {}",
+            service,
+            procedure.name,
+            parameters.len(),
+            args.len()
+        )));
+    }
+    if let Some(missing) = parameters[args.len()..]
+        .iter()
+        .find(|parameter| parameter.default_value.is_empty())
+    {
+        return Err(Error::InvalidArguments(format!(
+            "{}.{} is missing an argument for {}",
+            service, procedure.name, missing.name
+        )));
+    }
+    let arguments = parameters
+        .iter()
+        .zip(args)
+        .enumerate()
+        .map(|(position, (parameter, value))| {
+            let typ = parameter.r#type.as_ref().ok_or_else(|| {
+                Error::InvalidArguments(format!("{} has no type", parameter.name))
+            })?;
+            let value = encode(value, typ).map_err(|e| {
+                Error::InvalidArguments(format!(
+                    "{}.{} argument {}: {}",
+                    service, procedure.name, parameter.name, e
+                ))
+            })?;
+            Ok(Argument {
+                position: position as u32,
+                value,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(ProcedureCall {
+        service: service.to_string(),
+        procedure: procedure.name.clone(),
+        arguments,
+        ..Default::default()
+    })
+}
+
+/// Describes a type for error messages, as it is written in the service
+/// definitions.
+fn describe(typ: &Type) -> String {
+    match typ.code() {
+        TypeCode::Class | TypeCode::Enumeration => format!("{}.{}", typ.service, typ.name),
+        code if typ.types.is_empty() => code.as_str_name().to_string(),
+        code => format!(
+            "{}({})",
+            code.as_str_name(),
+            typ.types
+                .iter()
+                .map(describe)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn element(typ: &Type, index: usize) -> Result<&Type> {
+    typ.types
+        .get(index)
+        .ok_or_else(|| Error::Encoding(format!("{} is missing an element type", describe(typ))))
+}
+
+fn mismatch(value: &Value, typ: &Type) -> Error {
+    Error::Encoding(format!("expected {}, got {:?}", describe(typ), value))
+}
+
+/// Encodes `value`, which must be of type `typ`.
+pub(crate) fn encode(value: &Value, typ: &Type) -> Result<Bytes> {
+    Ok(match (typ.code(), value) {
+        (TypeCode::Double, Value::Double(x)) => codec::encode(x),
+        (TypeCode::Float, Value::Float(x)) => codec::encode(x),
+        (TypeCode::Sint32, Value::SInt32(x)) => codec::encode(x),
+        (TypeCode::Sint64, Value::SInt64(x)) => codec::encode(x),
+        (TypeCode::Uint32, Value::UInt32(x)) => codec::encode(x),
+        (TypeCode::Uint64, Value::UInt64(x)) => codec::encode(x),
+        (TypeCode::Bool, Value::Bool(x)) => codec::encode(x),
+        (TypeCode::String, Value::String(x)) => codec::encode(x),
+        (TypeCode::Bytes, Value::Bytes(x)) => codec::encode(x),
+        (TypeCode::Class, Value::None) => codec::encode(&0u64),
+        (TypeCode::Class, Value::Object { service, class, id })
+            if *service == typ.service && *class == typ.name =>
+        {
+            codec::encode(id)
+        }
+        (
+            TypeCode::Enumeration,
+            Value::Enum {
+                service,
+                enumeration,
+                value,
+            },
+        ) if *service == typ.service && *enumeration == typ.name => codec::encode(value),
+        (TypeCode::Event, Value::Event(x)) => codec::encode(x),
+        (TypeCode::ProcedureCall, Value::ProcedureCall(x)) => codec::encode(x),
+        (TypeCode::Stream, Value::Stream(x)) => codec::encode(x),
+        (TypeCode::Status, Value::Status(x)) => codec::encode(x),
+        (TypeCode::Services, Value::Services(x)) => codec::encode(x),
+        (TypeCode::Tuple, Value::Tuple(items)) => {
+            if items.len() != typ.types.len() {
+                return Err(Error::Encoding(format!(
+                    "expected {} elements for {}, got {}",
+                    typ.types.len(),
+                    describe(typ),
+                    items.len()
+                )));
+            }
+            let items = items
+                .iter()
+                .zip(&typ.types)
+                .map(|(item, typ)| encode(item, typ))
+                .collect::<Result<_>>()?;
+            schema::Tuple { items }.encode_to_vec().into()
+        }
+        (TypeCode::List, Value::List(items)) => {
+            let items = encode_items(items, element(typ, 0)?)?;
+            schema::List { items }.encode_to_vec().into()
+        }
+        (TypeCode::Set, Value::Set(items)) => {
+            // Sorted as the codec sorts sets, so that equal sets encode to
+            // the same bytes.
+            let mut items = encode_items(items, element(typ, 0)?)?;
+            items.sort();
+            schema::Set { items }.encode_to_vec().into()
+        }
+        (TypeCode::Dictionary, Value::Dictionary(entries)) => {
+            let (key_type, value_type) = (element(typ, 0)?, element(typ, 1)?);
+            let entries = entries
+                .iter()
+                .map(|(key, value)| {
+                    Ok(schema::DictionaryEntry {
+                        key: encode(key, key_type)?,
+                        value: encode(value, value_type)?,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            schema::Dictionary { entries }.encode_to_vec().into()
+        }
+        _ => return Err(mismatch(value, typ)),
+    })
+}
+
+fn encode_items(items: &[Value], typ: &Type) -> Result<Vec<Bytes>> {
+    items.iter().map(|item| encode(item, typ)).collect()
+}
+
+/// Decodes a value of type `typ` from `data`.
+pub(crate) fn decode(data: &[u8], typ: &Type) -> Result<Value> {
+    Ok(match typ.code() {
+        TypeCode::None => Value::None,
+        TypeCode::Double => Value::Double(codec::decode(data)?),
+        TypeCode::Float => Value::Float(codec::decode(data)?),
+        TypeCode::Sint32 => Value::SInt32(codec::decode(data)?),
+        TypeCode::Sint64 => Value::SInt64(codec::decode(data)?),
+        TypeCode::Uint32 => Value::UInt32(codec::decode(data)?),
+        TypeCode::Uint64 => Value::UInt64(codec::decode(data)?),
+        TypeCode::Bool => Value::Bool(codec::decode(data)?),
+        TypeCode::String => Value::String(codec::decode(data)?),
+        TypeCode::Bytes => Value::Bytes(codec::decode(data)?),
+        TypeCode::Class => match <u64 as Decode>::decode(data)? {
+            0 => Value::None,
+            id => Value::Object {
+                service: typ.service.clone(),
+                class: typ.name.clone(),
+                id,
+            },
+        },
+        TypeCode::Enumeration => Value::Enum {
+            service: typ.service.clone(),
+            enumeration: typ.name.clone(),
+            value: codec::decode(data)?,
+        },
+        TypeCode::Event => Value::Event(codec::decode(data)?),
+        TypeCode::ProcedureCall => Value::ProcedureCall(codec::decode(data)?),
+        TypeCode::Stream => Value::Stream(codec::decode(data)?),
+        TypeCode::Status => Value::Status(codec::decode(data)?),
+        TypeCode::Services => Value::Services(codec::decode(data)?),
+        TypeCode::Tuple => {
+            let items = schema::Tuple::decode(data)?.items;
+            if items.len() != typ.types.len() {
+                return Err(Error::Encoding(format!(
+                    "expected {} elements for {}, got {}",
+                    typ.types.len(),
+                    describe(typ),
+                    items.len()
+                )));
+            }
+            let items = items
+                .iter()
+                .zip(&typ.types)
+                .map(|(item, typ)| decode(item, typ))
+                .collect::<Result<_>>()?;
+            Value::Tuple(items)
+        }
+        TypeCode::List => Value::List(decode_items(
+            schema::List::decode(data)?.items,
+            element(typ, 0)?,
+        )?),
+        TypeCode::Set => Value::Set(decode_items(
+            schema::Set::decode(data)?.items,
+            element(typ, 0)?,
+        )?),
+        TypeCode::Dictionary => {
+            let (key_type, value_type) = (element(typ, 0)?, element(typ, 1)?);
+            let entries = schema::Dictionary::decode(data)?
+                .entries
+                .iter()
+                .map(|entry| {
+                    Ok((
+                        decode(&entry.key, key_type)?,
+                        decode(&entry.value, value_type)?,
+                    ))
+                })
+                .collect::<Result<_>>()?;
+            Value::Dictionary(entries)
+        }
+    })
+}
+
+fn decode_items(items: Vec<Bytes>, typ: &Type) -> Result<Vec<Value>> {
+    items.iter().map(|item| decode(item, typ)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{ProcedureResult, Request, Response};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+
+    fn typ(code: TypeCode) -> Type {
+        Type {
+            code: code as i32,
+            ..Default::default()
+        }
+    }
+
+    fn class(name: &str) -> Type {
+        Type {
+            code: TypeCode::Class as i32,
+            service: "SpaceCenter".to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn collection(code: TypeCode, types: Vec<Type>) -> Type {
+        Type {
+            code: code as i32,
+            types,
+            ..Default::default()
+        }
+    }
+
+    fn parameter(name: &str, typ: Type) -> schema::Parameter {
+        schema::Parameter {
+            name: name.to_string(),
+            r#type: Some(typ),
+            ..Default::default()
+        }
+    }
+
+    fn procedure(
+        name: &str,
+        parameters: Vec<schema::Parameter>,
+        return_type: Option<Type>,
+    ) -> schema::Procedure {
+        schema::Procedure {
+            name: name.to_string(),
+            parameters,
+            return_type,
+            ..Default::default()
+        }
+    }
+
+    fn vessel(id: u64) -> Value {
+        Value::Object {
+            service: "SpaceCenter".to_string(),
+            class: "Vessel".to_string(),
+            id,
+        }
+    }
+
+    fn services() -> schema::Services {
+        let mut flight = procedure(
+            "Vessel_Flight",
+            vec![
+                parameter("this", class("Vessel")),
+                parameter("reference_frame", class("ReferenceFrame")),
+            ],
+            Some(class("Flight")),
+        );
+        flight.parameters[1].default_value = codec::encode(&0u64);
+        schema::Services {
+            services: vec![schema::Service {
+                name: "SpaceCenter".to_string(),
+                procedures: vec![
+                    procedure("get_ActiveVessel", vec![], Some(class("Vessel"))),
+                    procedure(
+                        "Vessel_get_Name",
+                        vec![parameter("this", class("Vessel"))],
+                        Some(typ(TypeCode::String)),
+                    ),
+                    procedure(
+                        "Vessel_set_Name",
+                        vec![
+                            parameter("this", class("Vessel")),
+                            parameter("value", typ(TypeCode::String)),
+                        ],
+                        None,
+                    ),
+                    flight,
+                ],
+                classes: ["Vessel", "Flight", "ReferenceFrame"]
+                    .iter()
+                    .map(|name| schema::Class {
+                        name: name.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                enumerations: vec![schema::Enumeration {
+                    name: "VesselType".to_string(),
+                    values: vec![schema::EnumerationValue {
+                        name: "Probe".to_string(),
+                        value: 2,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    /// Answers `GetServices` with `services()`, and every other call with
+    /// the result of `handler`.
+    fn start<F>(handler: F) -> TestServer
+    where
+        F: Fn(&ProcedureCall) -> Bytes + Send + 'static,
+    {
+        TestServer::start(move |request: Request| {
+            let call = &request.calls[0];
+            let value = if call.procedure == "GetServices" {
+                codec::encode(&services())
+            } else {
+                handler(call)
+            };
+            Response {
+                results: vec![ProcedureResult {
+                    value,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        })
+    }
+
+    #[test]
+    fn calls_by_name() {
+        let server = start(|call| match call.procedure.as_str() {
+            "get_ActiveVessel" => codec::encode(&42u64),
+            "Vessel_get_Name" => {
+                assert_eq!(call.arguments[0].value, codec::encode(&42u64));
+                codec::encode("Jeb")
+            }
+            "Vessel_set_Name" => {
+                assert_eq!(call.arguments[1].value, codec::encode("Bob"));
+                Bytes::new()
+            }
+            "Vessel_Flight" => {
+                assert_eq!(call.arguments.len(), 1);
+                codec::encode(&7u64)
+            }
+            name => panic!("unexpected call to {}", name),
+        });
+        let client = assert_ok!(DynamicClient::connect(
+            "",
+            "127.0.0.1",
+            server.rpc_port,
+            None
+        ));
+        let space_center = assert_ok!(client.service("SpaceCenter"));
+        let vessel_class = assert_ok!(space_center.class("Vessel"));
+
+        let vessel = assert_ok!(space_center.property("ActiveVessel"));
+        assert_eq!(vessel, self::vessel(42));
+        assert_eq!(
+            assert_ok!(vessel_class.property(&vessel, "Name")),
+            Value::String("Jeb".to_string())
+        );
+        assert_ok!(vessel_class.set_property(&vessel, "Name", "Bob".into()));
+        assert_matches!(
+            assert_ok!(vessel_class.call(&vessel, "Flight", &[])),
+            Value::Object { class, id: 7, .. } if class == "Flight"
+        );
+    }
+
+    #[test]
+    fn checks_arguments() {
+        let server = start(|call| panic!("unexpected call to {}", call.procedure));
+        let client = assert_ok!(DynamicClient::connect(
+            "",
+            "127.0.0.1",
+            server.rpc_port,
+            None
+        ));
+        assert_matches!(client.service("Foo"), Err(Error::NotFound(_)));
+        let space_center = assert_ok!(client.service("SpaceCenter"));
+        assert_matches!(space_center.class("Foo"), Err(Error::NotFound(_)));
+        assert_matches!(space_center.call("Foo", &[]), Err(Error::NotFound(_)));
+
+        let vessel_class = assert_ok!(space_center.class("Vessel"));
+        let vessel = vessel(42);
+        assert_matches!(
+            vessel_class.set_property(&vessel, "Name", 1u32.into()),
+            Err(Error::InvalidArguments(_))
+        );
+        assert_matches!(
+            space_center.call("Vessel_get_Name", &[]),
+            Err(Error::InvalidArguments(_))
+        );
+        assert_matches!(
+            vessel_class.call(&vessel, "Flight", &[vessel.clone(), vessel.clone()]),
+            Err(Error::InvalidArguments(_))
+        );
+        // A Vessel is not a ReferenceFrame.
+        assert_matches!(
+            vessel_class.call(&vessel, "Flight", std::slice::from_ref(&vessel)),
+            Err(Error::InvalidArguments(_))
+        );
+    }
+
+    #[test]
+    fn enum_values() {
+        let server = start(|call| panic!("unexpected call to {}", call.procedure));
+        let client = assert_ok!(Client::connect("", "127.0.0.1", server.rpc_port, None));
+        let client = DynamicClient::with_services(client, services());
+        let space_center = assert_ok!(client.service("SpaceCenter"));
+        let probe = assert_ok!(space_center.enum_value("VesselType", "Probe"));
+        assert_matches!(probe, Value::Enum { value: 2, .. });
+        assert_matches!(
+            space_center.enum_value("VesselType", "Ship"),
+            Err(Error::NotFound(_))
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let cases = vec![
+            (Value::Double(1.5), typ(TypeCode::Double)),
+            (Value::SInt32(-3), typ(TypeCode::Sint32)),
+            (Value::Bool(true), typ(TypeCode::Bool)),
+            (Value::String("jeb".to_string()), typ(TypeCode::String)),
+            (Value::None, class("Vessel")),
+            (vessel(3), class("Vessel")),
+            (
+                Value::Tuple(vec![Value::UInt32(1), Value::String("a".to_string())]),
+                collection(
+                    TypeCode::Tuple,
+                    vec![typ(TypeCode::Uint32), typ(TypeCode::String)],
+                ),
+            ),
+            (
+                Value::List(vec![vessel(1), Value::None]),
+                collection(TypeCode::List, vec![class("Vessel")]),
+            ),
+            (
+                Value::Dictionary(vec![(Value::String("a".to_string()), Value::SInt64(-1))]),
+                collection(
+                    TypeCode::Dictionary,
+                    vec![typ(TypeCode::String), typ(TypeCode::Sint64)],
+                ),
+            ),
+        ];
+        for (value, typ) in cases {
+            let data = assert_ok!(encode(&value, &typ));
+            assert_eq!(assert_ok!(decode(&data, &typ)), value);
+        }
+    }
+
+    #[test]
+    fn matches_codec() {
+        let typ = collection(TypeCode::Set, vec![typ(TypeCode::Uint32)]);
+        let value = Value::Set(vec![Value::UInt32(300), Value::UInt32(1)]);
+        let set: std::collections::HashSet<u32> = [1, 300].into_iter().collect();
+        assert_eq!(assert_ok!(encode(&value, &typ)), codec::encode(&set));
+        assert_matches!(
+            encode(
+                &Value::Tuple(vec![]),
+                &collection(TypeCode::Tuple, vec![typ.clone()])
+            ),
+            Err(Error::Encoding(_))
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+
+use crate::schema;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An error returned by the server, either for a whole request or for a
+/// single procedure call within it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RpcError {
+    /// The service that defines the exception, or empty for errors that are
+    /// not associated with an exception type.
+    pub service: String,
+    /// The name of the exception type, or empty.
+    pub name: String,
+    pub description: String,
+    pub stack_trace: String,
+}
+
+impl From<schema::Error> for RpcError {
+    fn from(error: schema::Error) -> Self {
+        RpcError {
+            service: error.service,
+            name: error.name,
+            description: error.description,
+            stack_trace: error.stack_trace,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.service.is_empty() && !self.name.is_empty() {
+            write!(f, "{}.{}: ", self.service, self.name)?;
+        }
+        write!(f, "{}", self.description)?;
+        if !self.stack_trace.is_empty() {
+            write!(f, "\nServer stack trace:\n{}", self.stack_trace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors that can occur while talking to a kRPC server.
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error occurred on the underlying transport.
+    Io(std::io::Error),
+    /// The address could not be resolved to any socket address.
+    AddressResolution { address: String, port: u16 },
+    /// The connection was closed and cannot be reused.
+    Reused,
+    /// The connection has not been established, or has been closed.
+    NotConnected,
+    /// The WebSocket handshake failed, or the server broke the WebSocket
+    /// protocol.
+    WebSocket(String),
+
+    /// The length prefix of a received message was not a valid varint.
+    MalformedLength,
+    /// The length prefix of a received message exceeded the maximum size.
+    MessageTooLarge { size: u64, max: usize },
+    /// A received message could not be decoded.
+    Decode(prost::DecodeError),
+    /// A value could not be encoded or decoded.
+    Encoding(String),
+
+    /// The server could not decode the connection request.
+    MalformedConnectionRequest(String),
+    /// The server did not receive the connection request in time.
+    ConnectionTimeout(String),
+    /// The connection request was for a different kind of server, for
+    /// example an RPC request sent to the stream port.
+    WrongConnectionType(String),
+    /// The server's connection response did not make sense.
+    InvalidConnectionResponse(String),
+
+    /// The server's response to a request did not make sense.
+    InvalidResponse(String),
+    /// The server rejected a request as a whole (`Response.error`).
+    Request(RpcError),
+    /// A procedure threw `KRPC.InvalidOperationException`.
+    InvalidOperation(RpcError),
+    /// A procedure threw `KRPC.ArgumentException`.
+    Argument(RpcError),
+    /// A procedure threw `KRPC.ArgumentOutOfRangeException`.
+    ArgumentOutOfRange(RpcError),
+    /// A procedure threw `KRPC.ArgumentNullException`.
+    ArgumentNull(RpcError),
+    /// A procedure failed with any other error (`ProcedureResult.error`).
+    Rpc(RpcError),
+
+    /// The client is not connected to the stream server.
+    NoStreamConnection,
+    /// The stream has not received a value from the server yet.
+    NoStreamValue,
+
+    /// A service, or one of its members, is not in the service definitions.
+    NotFound(String),
+    /// The arguments to a procedure do not match its parameters.
+    InvalidArguments(String),
+}
+
+impl Error {
+    /// Builds the error for a failed procedure call, mapping the exceptions
+    /// defined by the `KRPC` service to their own variants.
+    pub fn from_procedure_error(error: schema::Error) -> Self {
+        let error = RpcError::from(error);
+        if error.service != "KRPC" {
+            return Error::Rpc(error);
+        }
+        match error.name.as_str() {
+            "InvalidOperationException" => Error::InvalidOperation(error),
+            "ArgumentException" => Error::Argument(error),
+            "ArgumentOutOfRangeException" => Error::ArgumentOutOfRange(error),
+            "ArgumentNullException" => Error::ArgumentNull(error),
+            _ => Error::Rpc(error),
+        }
+    }
+
+    /// Builds the error for a request the server rejected as a whole.
+    pub fn from_request_error(error: schema::Error) -> Self {
+        Error::Request(error.into())
+    }
+
+    /// The error reported by the server, if this error came from the server.
+    pub fn rpc_error(&self) -> Option<&RpcError> {
+        match self {
+            Error::Request(e)
+            | Error::InvalidOperation(e)
+            | Error::Argument(e)
+            | Error::ArgumentOutOfRange(e)
+            | Error::ArgumentNull(e)
+            | Error::Rpc(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::AddressResolution { address, port } => {
+                write!(f, "could not resolve address {}:{}", address, port)
+            }
+            Error::Reused => write!(f, "cannot reuse a `Connection`"),
+            Error::NotConnected => write!(f, "not connected"),
+            Error::WebSocket(message) => write!(f, "websocket error: {}", message),
+            Error::MalformedLength => write!(f, "malformed message length prefix"),
+            Error::MessageTooLarge { size, max } => write!(
+                f,
+                "message of {} bytes exceeds the maximum of {} bytes",
+                size, max
+            ),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+            Error::Encoding(message) => write!(f, "encoding error: {}", message),
+            Error::MalformedConnectionRequest(message) => {
+                write!(f, "malformed connection request: {}", message)
+            }
+            Error::ConnectionTimeout(message) => write!(f, "connection timed out: {}", message),
+            Error::WrongConnectionType(message) => {
+                write!(f, "wrong connection type: {}", message)
+            }
+            Error::InvalidConnectionResponse(message) => {
+                write!(f, "invalid connection response: {}", message)
+            }
+            Error::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+            Error::Request(e) => write!(f, "request failed: {}", e),
+            Error::InvalidOperation(e)
+            | Error::Argument(e)
+            | Error::ArgumentOutOfRange(e)
+            | Error::ArgumentNull(e)
+            | Error::Rpc(e) => write!(f, "{}", e),
+            Error::NoStreamConnection => write!(f, "not connected to the stream server"),
+            Error::NoStreamValue => write!(f, "stream has no value"),
+            Error::NotFound(message) => write!(f, "not found: {}", message),
+            Error::InvalidArguments(message) => write!(f, "invalid arguments: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<krpc_core::Error> for Error {
+    fn from(e: krpc_core::Error) -> Self {
+        match e {
+            krpc_core::Error::MalformedLength => Error::MalformedLength,
+            krpc_core::Error::MessageTooLarge { size, max } => Error::MessageTooLarge { size, max },
+            krpc_core::Error::Decode(e) => Error::Decode(e),
+            krpc_core::Error::Encoding(message) => Error::Encoding(message),
+            krpc_core::Error::MalformedConnectionRequest(message) => {
+                Error::MalformedConnectionRequest(message)
+            }
+            krpc_core::Error::ConnectionTimeout(message) => Error::ConnectionTimeout(message),
+            krpc_core::Error::WrongConnectionType(message) => Error::WrongConnectionType(message),
+            krpc_core::Error::InvalidConnectionResponse(message) => {
+                Error::InvalidConnectionResponse(message)
+            }
+            krpc_core::Error::InvalidResponse(message) => Error::InvalidResponse(message),
+        }
+    }
+}
+
+impl From<krpc_core::io::Error<std::io::Error>> for Error {
+    fn from(e: krpc_core::io::Error<std::io::Error>) -> Self {
+        match e {
+            krpc_core::io::Error::Io(e) => Error::Io(e),
+            krpc_core::io::Error::Protocol(e) => e.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::assert_matches;
+
+    fn error(service: &str, name: &str) -> schema::Error {
+        schema::Error {
+            service: service.to_string(),
+            name: name.to_string(),
+            description: "Something went wrong".to_string(),
+            stack_trace: "at Foo.Bar()".to_string(),
+        }
+    }
+
+    #[test]
+    fn maps_krpc_exceptions() {
+        assert_matches!(
+            Error::from_procedure_error(error("KRPC", "InvalidOperationException")),
+            Error::InvalidOperation(_)
+        );
+        assert_matches!(
+            Error::from_procedure_error(error("KRPC", "ArgumentException")),
+            Error::Argument(_)
+        );
+        assert_matches!(
+            Error::from_procedure_error(error("KRPC", "ArgumentOutOfRangeException")),
+            Error::ArgumentOutOfRange(_)
+        );
+        assert_matches!(
+            Error::from_procedure_error(error("KRPC", "ArgumentNullException")),
+            Error::ArgumentNull(_)
+        );
+        assert_matches!(
+            Error::from_procedure_error(error("SpaceCenter", "ArgumentException")),
+            Error::Rpc(_)
+        );
+        assert_matches!(Error::from_procedure_error(error("", "")), Error::Rpc(_));
+    }
+
+    #[test]
+    fn keeps_server_details() {
+        let e = Error::from_procedure_error(error("KRPC", "ArgumentException"));
+        let rpc_error = e.rpc_error().unwrap();
+        assert_eq!(rpc_error.service, "KRPC");
+        assert_eq!(rpc_error.name, "ArgumentException");
+        assert_eq!(rpc_error.description, "Something went wrong");
+        assert_eq!(rpc_error.stack_trace, "at Foo.Bar()");
+        assert_eq!(
+            e.to_string(),
+            "KRPC.ArgumentException: Something went wrong\nServer stack trace:\nat Foo.Bar()"
+        );
+    }
+}
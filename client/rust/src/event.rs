@@ -0,0 +1,238 @@
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::stream::{CallbackId, Stream};
+
+/// An event on the server, which occurs whenever the value of its condition
+/// becomes true.
+///
+/// Each event is backed by a stream of booleans that the server updates
+/// with the value of the condition, so waiting for an event costs no round
+/// trips to the server. Events are created with `Client::event` or
+/// `Client::add_event`, and removed from the server when dropped.
+///
+/// ```no_run
+/// # fn main() -> krpc::Result<()> {
+/// # let client = krpc::Client::connect("", "127.0.0.1", 50000, Some(50001))?;
+/// use krpc::expression::Expr;
+///
+/// let altitude = Expr::call(&krpc::Call::<f64>::new("SpaceCenter", "Flight_get_MeanAltitude"));
+/// let event = client.add_event(&altitude.gt(10_000.0).build(&client)?)?;
+/// event.wait(None)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Event {
+    stream: Stream<bool>,
+}
+
+impl Event {
+    pub(crate) fn new(stream: Stream<bool>) -> Self {
+        Event { stream }
+    }
+
+    /// Asks the server to start evaluating the event. Waiting for the event
+    /// starts it too.
+    pub fn start(&self) -> Result<()> {
+        self.stream.start()
+    }
+
+    /// Blocks until the event occurs, starting it first if needed. Returns
+    /// false if `timeout` elapses first, and fails if the client is
+    /// disconnected from the stream server while waiting.
+    ///
+    /// Only updates received after the call count. Starting a paused event
+    /// makes the server send the current value of its condition, so waiting
+    /// on an event that has not been started returns promptly if the
+    /// condition is already true. Once started, the server only sends the
+    /// condition when it changes, so a later `wait` returns true once the
+    /// condition becomes true again.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let signal = self.stream.signal();
+        let mut seen = signal.count();
+        self.start()?;
+        loop {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if !signal.wait_since(seen, remaining)? {
+                return Ok(false);
+            }
+            // Values are stored before the count is incremented, so the value
+            // read here is at least as recent as update number `seen`.
+            seen = signal.count();
+            if let Ok(true) = self.stream.get() {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Adds a callback that is run each time the event occurs. Callbacks
+    /// follow the same rules as those added with `Stream::add_callback`.
+    pub fn add_callback<F>(&self, mut callback: F) -> CallbackId
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.stream.add_callback(move |value| {
+            if let Ok(true) = value {
+                callback();
+            }
+        })
+    }
+
+    /// Removes a callback added with `add_callback`. Returns false if it had
+    /// already been removed.
+    pub fn remove_callback(&self, id: CallbackId) -> bool {
+        self.stream.remove_callback(id)
+    }
+
+    /// The stream of the event's condition.
+    pub fn stream(&self) -> &Stream<bool> {
+        &self.stream
+    }
+
+    /// Removes the event from the server, as dropping it does, but reports
+    /// any error in doing so.
+    pub fn remove(self) -> Result<()> {
+        self.stream.remove()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::codec::{self, RemoteObject};
+    use crate::error::Error;
+    use crate::expression::Expression;
+    use crate::schema::{self, ProcedureResult, Request, Response, StreamResult, StreamUpdate};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+    use std::sync::{Arc, Mutex};
+
+    /// Answers `KRPC.AddEvent` with an event on stream 7, and records the
+    /// name of each procedure called.
+    fn start_server() -> (TestServer, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&calls);
+        let server = TestServer::start(move |request: Request| {
+            let call = &request.calls[0];
+            log.lock().unwrap().push(call.procedure.clone());
+            let value = match call.procedure.as_str() {
+                "AddEvent" => codec::encode(&schema::Event {
+                    stream: Some(schema::Stream { id: 7 }),
+                }),
+                "BrokenEvent" => codec::encode(&schema::Event::default()),
+                _ => Default::default(),
+            };
+            Response {
+                results: vec![ProcedureResult {
+                    value,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        });
+        (server, calls)
+    }
+
+    fn add_event(server: &TestServer) -> (Client, Event) {
+        let client = assert_ok!(Client::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            Some(server.stream_port)
+        ));
+        let event = assert_ok!(client.add_event(&Expression::from_id(1)));
+        (client, event)
+    }
+
+    fn update(value: bool) -> StreamUpdate {
+        StreamUpdate {
+            results: vec![StreamResult {
+                id: 7,
+                result: Some(ProcedureResult {
+                    value: codec::encode(&value),
+                    ..Default::default()
+                }),
+            }],
+        }
+    }
+
+    #[test]
+    fn wait_until_condition_true() {
+        let (server, calls) = start_server();
+        let (_client, event) = add_event(&server);
+        assert!(!event.stream().started());
+        assert!(!assert_ok!(event.wait(Some(Duration::from_millis(10)))));
+        assert!(event.stream().started());
+
+        let waiter = std::thread::spawn(move || (event.wait(None), event));
+        // Both updates are sent after the waiter starts waiting, as it
+        // ignores values received before it was called.
+        while calls.lock().unwrap().len() < 2 {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        server.send_update(update(false));
+        server.send_update(update(true));
+        let (result, event) = waiter.join().unwrap();
+        assert!(assert_ok!(result));
+        assert_ok!(event.stream().get());
+
+        assert_ok!(event.remove());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["AddEvent", "StartStream", "RemoveStream"]
+        );
+    }
+
+    #[test]
+    fn wait_when_already_true() {
+        let (server, calls) = start_server();
+        let (_client, event) = add_event(&server);
+        let waiter = std::thread::spawn(move || event.wait(Some(Duration::from_secs(5))));
+        // The server answers starting the stream with the condition's
+        // current value.
+        while !calls
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|call| call == "StartStream")
+        {
+            std::thread::yield_now();
+        }
+        server.send_update(update(true));
+        assert!(assert_ok!(waiter.join().unwrap()));
+    }
+
+    #[test]
+    fn callbacks_fire_when_true() {
+        let (server, _) = start_server();
+        let (client, event) = add_event(&server);
+        assert_ok!(event.start());
+        let (fired, received) = std::sync::mpsc::channel();
+        let id = event.add_callback(move || fired.send(()).unwrap());
+        let (updated, updates) = std::sync::mpsc::channel();
+        assert_ok!(client.add_stream_update_callback(move || updated.send(()).unwrap()));
+        server.send_update(update(false));
+        server.send_update(update(true));
+        server.send_update(update(false));
+        for _ in 0..3 {
+            assert_ok!(updates.recv_timeout(Duration::from_secs(5)));
+        }
+        assert_eq!(received.try_iter().count(), 1);
+        assert!(event.remove_callback(id));
+    }
+
+    #[test]
+    fn event_without_stream() {
+        let (server, _) = start_server();
+        let (client, _) = add_event(&server);
+        assert_matches!(
+            client.event(&crate::Call::new("KRPC", "BrokenEvent")),
+            Err(Error::InvalidResponse(_))
+        );
+    }
+}
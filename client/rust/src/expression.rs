@@ -0,0 +1,998 @@
+//! Expressions evaluated by the server, from the `KRPC.Expression` class.
+//!
+//! An [`Expr<T>`] describes an expression whose value has type `T`. It is
+//! built locally, with operators and methods that only accept operands of
+//! matching types, and sent to the server with [`Expr::build`], which makes
+//! one call to a static method of `KRPC.Expression` for each node of the
+//! tree.
+//!
+//! ```no_run
+//! use krpc::expression::Expr;
+//! use krpc::Call;
+//!
+//! # fn main() -> krpc::Result<()> {
+//! # let client = krpc::Client::connect("", "127.0.0.1", 50000, Some(50001))?;
+//! # let flight = 1u64;
+//! let altitude = Expr::call(&Call::<f64>::new("SpaceCenter", "Flight_get_MeanAltitude").arg(&flight));
+//! let fuel = Expr::call(&Call::<f32>::new("SpaceCenter", "Resources_Amount").arg(&2u64).arg("LiquidFuel"));
+//! let condition = altitude.gt(10_000.0) & fuel.lt(5.0);
+//! let event = client.add_event(&condition.build(&client)?)?;
+//! event.wait(None)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Operands of different types must be converted with [`Expr::cast`]
+//! first:
+//!
+//! ```compile_fail
+//! # use krpc::expression::Expr;
+//! # use krpc::Call;
+//! let altitude = Expr::call(&Call::<f64>::new("SpaceCenter", "Flight_get_MeanAltitude"));
+//! let stage = Expr::call(&Call::<i32>::new("SpaceCenter", "Control_get_CurrentStage"));
+//! altitude.gt(stage);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+#[cfg(feature = "async")]
+use crate::async_client::AsyncClient;
+use crate::call::Call;
+use crate::client::Client;
+use crate::codec::{self, Encode, RemoteObject};
+use crate::error::{Error, Result};
+use node::{Constant, Node};
+
+crate::remote_object! {
+    /// An expression tree built on the server with the static methods of
+    /// the `KRPC.Expression` class, for example as the condition of an
+    /// event added with `Client::add_event`.
+    pub struct Expression;
+}
+
+crate::remote_object! {
+    /// A type on the server, from the `KRPC.Type` class, used to cast values
+    /// and declare function parameters.
+    pub struct Type;
+}
+
+/// An expression whose value has type `T`.
+///
+/// Expressions are cheap to clone, and a clone shares its nodes with the
+/// original, so that they are only built once on the server.
+pub struct Expr<T> {
+    node: Arc<Node>,
+    value: PhantomData<fn() -> T>,
+}
+
+/// The value type of a function taking arguments `Args`, a tuple, and
+/// returning `R`.
+pub struct Func<Args, R> {
+    _value: PhantomData<fn(Args) -> R>,
+}
+
+/// The value type of a sequence of `T`s produced by a query such as
+/// `Expr::select`. Convert it with `to_list` or `to_set` to count or index
+/// its items.
+pub struct Seq<T> {
+    _value: PhantomData<fn() -> T>,
+}
+
+/// The nodes of expression trees, public only within this module.
+mod node {
+    use std::sync::Arc;
+
+    use crate::schema::ProcedureCall;
+
+    pub enum Constant {
+        Double(f64),
+        Float(f32),
+        Int(i32),
+        Bool(bool),
+        String(String),
+    }
+
+    pub enum Node {
+        Constant(Constant),
+        Call(ProcedureCall),
+        /// A static method of `Expression` whose arguments are all expressions.
+        Apply(&'static str, Vec<Arc<Node>>),
+        /// A static method of `Expression` taking a list of expressions.
+        Collection(&'static str, Vec<Arc<Node>>),
+        Dictionary(Vec<Arc<Node>>, Vec<Arc<Node>>),
+        Cast(Arc<Node>, &'static str),
+        Parameter(String, &'static str),
+        Function(Vec<Arc<Node>>, Arc<Node>),
+        Invoke(Arc<Node>, Vec<Arc<Node>>),
+    }
+}
+
+impl<T> Expr<T> {
+    fn new(node: Node) -> Self {
+        Expr {
+            node: Arc::new(node),
+            value: PhantomData,
+        }
+    }
+
+    fn apply<U>(method: &'static str, args: &[&Arc<Node>]) -> Expr<U> {
+        Expr::new(Node::Apply(
+            method,
+            args.iter().map(|arg| Arc::clone(arg)).collect(),
+        ))
+    }
+
+    /// The value of `value`.
+    pub fn constant(value: T) -> Self
+    where
+        T: Scalar,
+    {
+        Self::new(Node::Constant(sealed::ScalarValue::into_constant(value)))
+    }
+
+    /// The result of `call`, evaluated each time the expression is.
+    pub fn call(call: &Call<T>) -> Self {
+        Self::new(Node::Call(call.message().clone()))
+    }
+
+    /// Creates a tuple of the values of `items`, a tuple of up to four
+    /// expressions.
+    pub fn tuple<E: Tuple<Value = T>>(items: E) -> Self {
+        Self::new(Node::Collection("CreateTuple", items.nodes()))
+    }
+
+    /// Builds the expression on the server. Fails with
+    /// `Error::InvalidArguments` if it invokes a function that was not
+    /// declared with `function` or `function2`.
+    pub fn build(&self, client: &Client) -> Result<Expression> {
+        let mut built = Vec::new();
+        for step in Plan::steps(&self.node)? {
+            built.push(client.call(&step.call(&built))?);
+        }
+        Ok(Expression::from_id(
+            *built.last().expect("a plan has a step"),
+        ))
+    }
+
+    /// Builds the expression on the server, through an async client.
+    #[cfg(feature = "async")]
+    pub async fn build_async(&self, client: &AsyncClient) -> Result<Expression> {
+        let mut built = Vec::new();
+        for step in Plan::steps(&self.node)? {
+            built.push(client.call(&step.call(&built)).await?);
+        }
+        Ok(Expression::from_id(
+            *built.last().expect("a plan has a step"),
+        ))
+    }
+
+    pub fn equal(&self, other: impl Into<Expr<T>>) -> Expr<bool> {
+        Self::apply("Equal", &[&self.node, &other.into().node])
+    }
+
+    pub fn not_equal(&self, other: impl Into<Expr<T>>) -> Expr<bool> {
+        Self::apply("NotEqual", &[&self.node, &other.into().node])
+    }
+
+    /// Converts the value to another scalar type.
+    pub fn cast<U: Scalar>(&self) -> Expr<U>
+    where
+        T: Scalar,
+    {
+        Expr::new(Node::Cast(
+            Arc::clone(&self.node),
+            <U as sealed::ScalarValue>::TYPE,
+        ))
+    }
+}
+
+impl<T: Numeric> Expr<T> {
+    pub fn gt(&self, other: impl Into<Expr<T>>) -> Expr<bool> {
+        Self::apply("GreaterThan", &[&self.node, &other.into().node])
+    }
+
+    pub fn ge(&self, other: impl Into<Expr<T>>) -> Expr<bool> {
+        Self::apply("GreaterThanOrEqual", &[&self.node, &other.into().node])
+    }
+
+    pub fn lt(&self, other: impl Into<Expr<T>>) -> Expr<bool> {
+        Self::apply("LessThan", &[&self.node, &other.into().node])
+    }
+
+    pub fn le(&self, other: impl Into<Expr<T>>) -> Expr<bool> {
+        Self::apply("LessThanOrEqual", &[&self.node, &other.into().node])
+    }
+
+    /// The value raised to the power `exponent`.
+    pub fn pow(&self, exponent: impl Into<Expr<T>>) -> Expr<T> {
+        Self::apply("Power", &[&self.node, &exponent.into().node])
+    }
+}
+
+impl<T> Clone for Expr<T> {
+    fn clone(&self) -> Self {
+        Expr {
+            node: Arc::clone(&self.node),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Expr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Expr").field(&self.node).finish()
+    }
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Constant(Constant::Double(value)) => write!(f, "{:?}", value),
+            Node::Constant(Constant::Float(value)) => write!(f, "{:?}f", value),
+            Node::Constant(Constant::Int(value)) => write!(f, "{:?}", value),
+            Node::Constant(Constant::Bool(value)) => write!(f, "{:?}", value),
+            Node::Constant(Constant::String(value)) => write!(f, "{:?}", value),
+            Node::Call(call) => write!(f, "{}.{}(..)", call.service, call.procedure),
+            Node::Apply(method, args) | Node::Collection(method, args) => {
+                f.debug_tuple(method).field(args).finish()
+            }
+            Node::Dictionary(keys, values) => f
+                .debug_tuple("CreateDictionary")
+                .field(keys)
+                .field(values)
+                .finish(),
+            Node::Cast(arg, ty) => f.debug_tuple("Cast").field(arg).field(ty).finish(),
+            Node::Parameter(name, _) => write!(f, "{}", name),
+            Node::Function(parameters, body) => f
+                .debug_tuple("Function")
+                .field(parameters)
+                .field(body)
+                .finish(),
+            Node::Invoke(function, args) => {
+                f.debug_tuple("Invoke").field(function).field(args).finish()
+            }
+        }
+    }
+}
+
+impl<T> From<&Expr<T>> for Expr<T> {
+    fn from(expr: &Expr<T>) -> Self {
+        expr.clone()
+    }
+}
+
+impl<T: Scalar> From<T> for Expr<T> {
+    fn from(value: T) -> Self {
+        Expr::constant(value)
+    }
+}
+
+impl From<&str> for Expr<String> {
+    fn from(value: &str) -> Self {
+        Expr::constant(value.to_string())
+    }
+}
+
+mod sealed {
+    use super::{Arc, Constant, Node};
+
+    pub trait Sealed {}
+
+    pub trait ScalarValue {
+        /// The name of the static method of `KRPC.Type` for the type.
+        const TYPE: &'static str;
+
+        fn into_constant(self) -> Constant;
+    }
+
+    pub trait TupleNodes {
+        fn nodes(self) -> Vec<Arc<Node>>;
+    }
+}
+
+/// A type with a `KRPC.Type`, which can be a constant, be cast to, and be
+/// the type of a function parameter.
+pub trait Scalar: sealed::Sealed + sealed::ScalarValue + Sized {}
+
+/// A number type, which can be compared and used in arithmetic.
+pub trait Numeric: sealed::Sealed {
+    /// The type of the average of a collection of the numbers.
+    type Average;
+}
+
+/// A type that supports `&`, `|`, `^` and `!`: logical operators for
+/// booleans and bitwise operators for integers.
+pub trait Logical: sealed::Sealed {}
+
+macro_rules! scalar {
+    ($ty:ty, $name:literal, $variant:ident) => {
+        impl sealed::Sealed for $ty {}
+
+        impl Scalar for $ty {}
+
+        impl sealed::ScalarValue for $ty {
+            const TYPE: &'static str = $name;
+
+            fn into_constant(self) -> Constant {
+                Constant::$variant(self)
+            }
+        }
+    };
+}
+
+scalar!(f64, "Double", Double);
+scalar!(f32, "Float", Float);
+scalar!(i32, "Int", Int);
+scalar!(bool, "Bool", Bool);
+scalar!(String, "String", String);
+
+macro_rules! numeric {
+    ($($ty:ty => $average:ty),*) => {
+        $(
+            impl Numeric for $ty {
+                type Average = $average;
+            }
+        )*
+    };
+}
+
+numeric!(f64 => f64, f32 => f32, i32 => f64);
+
+impl Logical for bool {}
+impl Logical for i32 {}
+
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $bound:ident, $name:literal) => {
+        impl<T: $bound, R: Into<Expr<T>>> ops::$trait<R> for Expr<T> {
+            type Output = Expr<T>;
+
+            fn $method(self, other: R) -> Expr<T> {
+                Self::apply($name, &[&self.node, &other.into().node])
+            }
+        }
+    };
+}
+
+binary_operator!(Add, add, Numeric, "Add");
+binary_operator!(Sub, sub, Numeric, "Subtract");
+binary_operator!(Mul, mul, Numeric, "Multiply");
+binary_operator!(Div, div, Numeric, "Divide");
+binary_operator!(Rem, rem, Numeric, "Modulo");
+binary_operator!(BitAnd, bitand, Logical, "And");
+binary_operator!(BitOr, bitor, Logical, "Or");
+binary_operator!(BitXor, bitxor, Logical, "ExclusiveOr");
+
+impl<T: Logical> ops::Not for Expr<T> {
+    type Output = Expr<T>;
+
+    fn not(self) -> Expr<T> {
+        Self::apply("Not", &[&self.node])
+    }
+}
+
+impl<R: Into<Expr<i32>>> ops::Shl<R> for Expr<i32> {
+    type Output = Expr<i32>;
+
+    fn shl(self, other: R) -> Expr<i32> {
+        Self::apply("LeftShift", &[&self.node, &other.into().node])
+    }
+}
+
+impl<R: Into<Expr<i32>>> ops::Shr<R> for Expr<i32> {
+    type Output = Expr<i32>;
+
+    fn shr(self, other: R) -> Expr<i32> {
+        Self::apply("RightShift", &[&self.node, &other.into().node])
+    }
+}
+
+/// Declares a function of one parameter named `name`, whose body is built by
+/// `body` from an expression for the parameter.
+pub fn function<A: Scalar, R>(
+    name: &str,
+    body: impl FnOnce(Expr<A>) -> Expr<R>,
+) -> Expr<Func<(A,), R>> {
+    let a = Expr::<A>::parameter(name);
+    let parameters = vec![Arc::clone(&a.node)];
+    Expr::new(Node::Function(parameters, body(a).node))
+}
+
+/// Declares a function of two parameters, as `function` does.
+pub fn function2<A: Scalar, B: Scalar, R>(
+    names: [&str; 2],
+    body: impl FnOnce(Expr<A>, Expr<B>) -> Expr<R>,
+) -> Expr<Func<(A, B), R>> {
+    let a = Expr::<A>::parameter(names[0]);
+    let b = Expr::<B>::parameter(names[1]);
+    let parameters = vec![Arc::clone(&a.node), Arc::clone(&b.node)];
+    Expr::new(Node::Function(parameters, body(a, b).node))
+}
+
+impl<T: Scalar> Expr<T> {
+    fn parameter(name: &str) -> Self {
+        let ty = <T as sealed::ScalarValue>::TYPE;
+        Self::new(Node::Parameter(name.to_string(), ty))
+    }
+}
+
+impl<A, R> Expr<Func<(A,), R>> {
+    /// Calls the function.
+    pub fn invoke(&self, a: impl Into<Expr<A>>) -> Expr<R> {
+        Expr::new(Node::Invoke(Arc::clone(&self.node), vec![a.into().node]))
+    }
+}
+
+impl<A, B, R> Expr<Func<(A, B), R>> {
+    /// Calls the function.
+    pub fn invoke(&self, a: impl Into<Expr<A>>, b: impl Into<Expr<B>>) -> Expr<R> {
+        Expr::new(Node::Invoke(
+            Arc::clone(&self.node),
+            vec![a.into().node, b.into().node],
+        ))
+    }
+}
+
+/// A tuple of expressions, from which `Expr::tuple` creates an expression
+/// for a tuple of their values.
+pub trait Tuple: sealed::TupleNodes {
+    type Value;
+}
+
+macro_rules! tuple {
+    ($(($ty:ident, $field:tt, $item:ident)),+) => {
+        impl<$($ty),+> Tuple for ($(Expr<$ty>,)+) {
+            type Value = ($($ty,)+);
+        }
+
+        impl<$($ty),+> sealed::TupleNodes for ($(Expr<$ty>,)+) {
+            fn nodes(self) -> Vec<Arc<Node>> {
+                vec![$(self.$field.node),+]
+            }
+        }
+
+        impl<$($ty),+> Expr<($($ty,)+)> {
+            $(
+                /// An item of the tuple.
+                pub fn $item(&self) -> Expr<$ty> {
+                    let index = Expr::constant($field);
+                    Self::apply("Get", &[&self.node, &index.node])
+                }
+            )+
+        }
+    };
+}
+
+tuple!((A, 0, item0));
+tuple!((A, 0, item0), (B, 1, item1));
+tuple!((A, 0, item0), (B, 1, item1), (C, 2, item2));
+tuple!((A, 0, item0), (B, 1, item1), (C, 2, item2), (D, 3, item3));
+
+/// A type whose values are sequences of `Item`s, which can be queried.
+pub trait Enumerable: sealed::Sealed {
+    type Item;
+}
+
+impl<T> sealed::Sealed for Vec<T> {}
+impl<T> sealed::Sealed for HashSet<T> {}
+impl<T> sealed::Sealed for Seq<T> {}
+
+impl<T> Enumerable for Vec<T> {
+    type Item = T;
+}
+
+impl<T> Enumerable for HashSet<T> {
+    type Item = T;
+}
+
+impl<T> Enumerable for Seq<T> {
+    type Item = T;
+}
+
+impl<T> Expr<Vec<T>> {
+    /// Creates a list of the values of `items`, which must not be empty.
+    pub fn list(items: impl IntoIterator<Item = Expr<T>>) -> Self {
+        Self::new(Node::Collection(
+            "CreateList",
+            items.into_iter().map(|item| item.node).collect(),
+        ))
+    }
+
+    /// The item at `index`.
+    pub fn get(&self, index: impl Into<Expr<i32>>) -> Expr<T> {
+        Self::apply("Get", &[&self.node, &index.into().node])
+    }
+
+    /// The number of items in the list.
+    pub fn count(&self) -> Expr<i32> {
+        Self::apply("Count", &[&self.node])
+    }
+}
+
+impl<T> Expr<HashSet<T>> {
+    /// Creates a set of the values of `items`, which must not be empty.
+    pub fn set(items: impl IntoIterator<Item = Expr<T>>) -> Self {
+        Self::new(Node::Collection(
+            "CreateSet",
+            items.into_iter().map(|item| item.node).collect(),
+        ))
+    }
+
+    /// The number of items in the set.
+    pub fn count(&self) -> Expr<i32> {
+        Self::apply("Count", &[&self.node])
+    }
+}
+
+impl<K, V> Expr<HashMap<K, V>> {
+    /// Creates a dictionary mapping each of `entries`' keys to its value.
+    /// There must be at least one entry.
+    pub fn dictionary(entries: impl IntoIterator<Item = (Expr<K>, Expr<V>)>) -> Self {
+        let (keys, values): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .map(|(key, value)| (key.node, value.node))
+            .unzip();
+        Self::new(Node::Dictionary(keys, values))
+    }
+
+    /// The value for `key`.
+    pub fn get(&self, key: impl Into<Expr<K>>) -> Expr<V> {
+        Self::apply("Get", &[&self.node, &key.into().node])
+    }
+
+    /// The number of entries in the dictionary.
+    pub fn count(&self) -> Expr<i32> {
+        Self::apply("Count", &[&self.node])
+    }
+}
+
+impl<C: Enumerable> Expr<C> {
+    /// Collects the items into a list.
+    pub fn to_list(&self) -> Expr<Vec<C::Item>> {
+        Self::apply("ToList", &[&self.node])
+    }
+
+    /// Collects the items into a set.
+    pub fn to_set(&self) -> Expr<HashSet<C::Item>> {
+        Self::apply("ToSet", &[&self.node])
+    }
+
+    /// Whether `value` is one of the items.
+    pub fn contains(&self, value: impl Into<Expr<C::Item>>) -> Expr<bool> {
+        Self::apply("Contains", &[&self.node, &value.into().node])
+    }
+
+    /// The result of `func` for each item.
+    pub fn select<R>(&self, func: &Expr<Func<(C::Item,), R>>) -> Expr<Seq<R>> {
+        Self::apply("Select", &[&self.node, &func.node])
+    }
+
+    /// The items for which `predicate` is true, from `KRPC.Expression.Where`.
+    pub fn filter(&self, predicate: &Expr<Func<(C::Item,), bool>>) -> Expr<Seq<C::Item>> {
+        Self::apply("Where", &[&self.node, &predicate.node])
+    }
+
+    /// Combines the items in turn with `func`, starting from the first.
+    #[allow(clippy::type_complexity)]
+    pub fn aggregate(&self, func: &Expr<Func<(C::Item, C::Item), C::Item>>) -> Expr<C::Item> {
+        Self::apply("Aggregate", &[&self.node, &func.node])
+    }
+
+    /// Combines the items in turn with `func`, starting from `seed`.
+    pub fn aggregate_with_seed<A>(
+        &self,
+        seed: impl Into<Expr<A>>,
+        func: &Expr<Func<(A, C::Item), A>>,
+    ) -> Expr<A> {
+        Self::apply(
+            "AggregateWithSeed",
+            &[&self.node, &seed.into().node, &func.node],
+        )
+    }
+
+    /// The items followed by those of `other`.
+    pub fn concat<D>(&self, other: &Expr<D>) -> Expr<Seq<C::Item>>
+    where
+        D: Enumerable<Item = C::Item>,
+    {
+        Self::apply("Concat", &[&self.node, &other.node])
+    }
+
+    /// The items, sorted by the result of `key` for each.
+    pub fn order_by<K>(&self, key: &Expr<Func<(C::Item,), K>>) -> Expr<Seq<C::Item>> {
+        Self::apply("OrderBy", &[&self.node, &key.node])
+    }
+
+    /// Whether `predicate` is true for every item.
+    pub fn all(&self, predicate: &Expr<Func<(C::Item,), bool>>) -> Expr<bool> {
+        Self::apply("All", &[&self.node, &predicate.node])
+    }
+
+    /// Whether `predicate` is true for any item.
+    pub fn any(&self, predicate: &Expr<Func<(C::Item,), bool>>) -> Expr<bool> {
+        Self::apply("Any", &[&self.node, &predicate.node])
+    }
+}
+
+impl<C> Expr<C>
+where
+    C: Enumerable,
+    C::Item: Numeric,
+{
+    pub fn sum(&self) -> Expr<C::Item> {
+        Self::apply("Sum", &[&self.node])
+    }
+
+    pub fn max(&self) -> Expr<C::Item> {
+        Self::apply("Max", &[&self.node])
+    }
+
+    pub fn min(&self) -> Expr<C::Item> {
+        Self::apply("Min", &[&self.node])
+    }
+
+    pub fn average(&self) -> Expr<<C::Item as Numeric>::Average> {
+        Self::apply("Average", &[&self.node])
+    }
+}
+
+/// One call to a static method of `Expression` or `Type` that builds a node
+/// on the server. Its arguments may refer to the results of earlier steps.
+struct Step {
+    procedure: String,
+    args: Vec<Arg>,
+}
+
+enum Arg {
+    Value(Raw),
+    Built(usize),
+    List(Vec<usize>),
+    Set(Vec<usize>),
+    Named(Vec<(String, usize)>),
+}
+
+/// An already encoded argument.
+struct Raw(Bytes);
+
+impl Encode for Raw {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0);
+    }
+}
+
+impl Step {
+    fn expression(method: &str, args: Vec<Arg>) -> Self {
+        Step {
+            procedure: format!("Expression_static_{}", method),
+            args,
+        }
+    }
+
+    /// The call for this step, given the ids of the objects built by the
+    /// steps before it.
+    fn call(&self, built: &[u64]) -> Call<u64> {
+        let ids = |steps: &[usize]| steps.iter().map(|&step| built[step]).collect::<Vec<_>>();
+        self.args
+            .iter()
+            .fold(Call::new("KRPC", &self.procedure), |call, arg| match arg {
+                Arg::Value(value) => call.arg(value),
+                Arg::Built(step) => call.arg(&built[*step]),
+                Arg::List(steps) => call.arg(&ids(steps)),
+                Arg::Set(steps) => call.arg(&ids(steps).into_iter().collect::<HashSet<_>>()),
+                Arg::Named(args) => {
+                    let args: HashMap<&str, u64> = args
+                        .iter()
+                        .map(|(name, step)| (name.as_str(), built[*step]))
+                        .collect();
+                    call.arg(&args)
+                }
+            })
+    }
+}
+
+/// Orders the nodes of an expression into the steps that build them, each
+/// after the nodes it refers to. Shared nodes, and types, are built once.
+#[derive(Default)]
+struct Plan {
+    steps: Vec<Step>,
+    nodes: HashMap<*const Node, usize>,
+    types: HashMap<&'static str, usize>,
+}
+
+impl Plan {
+    fn steps(root: &Arc<Node>) -> Result<Vec<Step>> {
+        let mut plan = Plan::default();
+        plan.add(root)?;
+        Ok(plan.steps)
+    }
+
+    fn add(&mut self, node: &Arc<Node>) -> Result<usize> {
+        let key = Arc::as_ptr(node);
+        if let Some(&step) = self.nodes.get(&key) {
+            return Ok(step);
+        }
+        let value = |value: &dyn Encode| vec![Arg::Value(Raw(codec::encode(value)))];
+        let step = match &**node {
+            Node::Constant(Constant::Double(v)) => Step::expression("ConstantDouble", value(v)),
+            Node::Constant(Constant::Float(v)) => Step::expression("ConstantFloat", value(v)),
+            Node::Constant(Constant::Int(v)) => Step::expression("ConstantInt", value(v)),
+            Node::Constant(Constant::Bool(v)) => Step::expression("ConstantBool", value(v)),
+            Node::Constant(Constant::String(v)) => Step::expression("ConstantString", value(v)),
+            Node::Call(call) => Step::expression("Call", value(call)),
+            Node::Apply(method, args) => {
+                let args = self.add_all(args)?.into_iter().map(Arg::Built).collect();
+                Step::expression(method, args)
+            }
+            Node::Collection(method, items) => {
+                let items = self.add_all(items)?;
+                match *method {
+                    "CreateSet" => Step::expression(method, vec![Arg::Set(items)]),
+                    _ => Step::expression(method, vec![Arg::List(items)]),
+                }
+            }
+            Node::Dictionary(keys, values) => {
+                let keys = Arg::List(self.add_all(keys)?);
+                let values = Arg::List(self.add_all(values)?);
+                Step::expression("CreateDictionary", vec![keys, values])
+            }
+            Node::Cast(arg, ty) => {
+                let arg = Arg::Built(self.add(arg)?);
+                let ty = Arg::Built(self.add_type(ty));
+                Step::expression("Cast", vec![arg, ty])
+            }
+            Node::Parameter(name, ty) => {
+                let mut args = value(name);
+                args.push(Arg::Built(self.add_type(ty)));
+                Step::expression("Parameter", args)
+            }
+            Node::Function(parameters, body) => {
+                let parameters = Arg::List(self.add_all(parameters)?);
+                let body = Arg::Built(self.add(body)?);
+                Step::expression("Function", vec![parameters, body])
+            }
+            Node::Invoke(function, args) => {
+                // The arguments are passed by the names of the parameters, so
+                // only functions declared here, whose parameters are known,
+                // can be invoked.
+                let Node::Function(parameters, _) = &**function else {
+                    return Err(Error::InvalidArguments(format!(
+                        "cannot invoke {:?}, only functions declared with `function` or \
+                         `function2` can be invoked",
+                        function
+                    )));
+                };
+                let built = Arg::Built(self.add(function)?);
+                let mut named = Vec::new();
+                for (parameter, arg) in parameters.iter().zip(args) {
+                    let Node::Parameter(name, _) = &**parameter else {
+                        return Err(Error::InvalidArguments(format!(
+                            "function parameter {:?} is not a parameter",
+                            parameter
+                        )));
+                    };
+                    named.push((name.clone(), self.add(arg)?));
+                }
+                Step::expression("Invoke", vec![built, Arg::Named(named)])
+            }
+        };
+        self.steps.push(step);
+        self.nodes.insert(key, self.steps.len() - 1);
+        Ok(self.steps.len() - 1)
+    }
+
+    fn add_all(&mut self, nodes: &[Arc<Node>]) -> Result<Vec<usize>> {
+        nodes.iter().map(|node| self.add(node)).collect()
+    }
+
+    fn add_type(&mut self, name: &'static str) -> usize {
+        if let Some(&step) = self.types.get(name) {
+            return step;
+        }
+        self.steps.push(Step {
+            procedure: format!("Type_static_{}", name),
+            args: Vec::new(),
+        });
+        self.types.insert(name, self.steps.len() - 1);
+        self.steps.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{self, Decode};
+    use crate::schema::{ProcedureCall, ProcedureResult, Request, Response};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+    use std::sync::Mutex;
+
+    /// A call made by `Builder`: the procedure name and its arguments.
+    type Log = Arc<Mutex<Vec<(String, Vec<Vec<u8>>)>>>;
+
+    /// Answers each call with a new object id, starting from 1, and records
+    /// the calls made.
+    fn start_server() -> (TestServer, Client, Log) {
+        let log = Log::default();
+        let calls = Arc::clone(&log);
+        let server = TestServer::start(move |request: Request| {
+            let call = &request.calls[0];
+            let mut calls = calls.lock().unwrap();
+            let args = call.arguments.iter().map(|arg| arg.value.to_vec());
+            calls.push((call.procedure.clone(), args.collect()));
+            Response {
+                results: vec![ProcedureResult {
+                    value: codec::encode(&(calls.len() as u64)),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        });
+        let client = assert_ok!(Client::connect("", "127.0.0.1", server.rpc_port, None));
+        (server, client, log)
+    }
+
+    fn procedures(log: &Log) -> Vec<String> {
+        let log = log.lock().unwrap();
+        let names = log.iter().map(|(procedure, _)| {
+            procedure
+                .trim_start_matches("Expression_static_")
+                .to_string()
+        });
+        names.collect()
+    }
+
+    fn args<T: Decode>(log: &Log, index: usize) -> Vec<T> {
+        let log = log.lock().unwrap();
+        let args = log[index].1.iter().map(|arg| T::decode(arg).unwrap());
+        args.collect()
+    }
+
+    #[test]
+    fn operators() {
+        let (_server, client, log) = start_server();
+        let altitude = Expr::call(&Call::<f64>::new("SpaceCenter", "get_Altitude"));
+        let fuel = Expr::call(&Call::<f32>::new("SpaceCenter", "get_Fuel"));
+        let condition = altitude.gt(10_000.0) & fuel.lt(5.0);
+        assert_eq!(assert_ok!(condition.build(&client)), Expression { id: 7 });
+
+        assert_eq!(
+            procedures(&log),
+            [
+                "Call",
+                "ConstantDouble",
+                "GreaterThan",
+                "Call",
+                "ConstantFloat",
+                "LessThan",
+                "And"
+            ]
+        );
+        assert_eq!(args::<f64>(&log, 1), [10_000.0]);
+        assert_eq!(args::<u64>(&log, 2), [1, 2]);
+        assert_eq!(args::<u64>(&log, 6), [3, 6]);
+        let call: ProcedureCall = codec::decode(&log.lock().unwrap()[0].1[0]).unwrap();
+        assert_eq!(call.procedure, "get_Altitude");
+    }
+
+    #[test]
+    fn arithmetic_and_casts() {
+        let (_server, client, log) = start_server();
+        let stage = Expr::constant(3);
+        let expr = !(((stage.clone() + 1) * 2 % stage) << 1)
+            .cast::<f64>()
+            .pow(2.0)
+            .le(1.5);
+        assert_ok!(expr.build(&client));
+        assert_eq!(
+            procedures(&log),
+            [
+                "ConstantInt",
+                "ConstantInt",
+                "Add",
+                "ConstantInt",
+                "Multiply",
+                "Modulo",
+                "ConstantInt",
+                "LeftShift",
+                "Type_static_Double",
+                "Cast",
+                "ConstantDouble",
+                "Power",
+                "ConstantDouble",
+                "LessThanOrEqual",
+                "Not"
+            ]
+        );
+        // The shared constant is only built once.
+        assert_eq!(args::<u64>(&log, 5), [5, 1]);
+    }
+
+    #[test]
+    fn functions() {
+        let (_server, client, log) = start_server();
+        let square = function("x", |x: Expr<f64>| x.clone() * x);
+        assert_ok!(square.invoke(3.0).build(&client));
+        assert_eq!(
+            procedures(&log),
+            [
+                "Type_static_Double",
+                "Parameter",
+                "Multiply",
+                "Function",
+                "ConstantDouble",
+                "Invoke"
+            ]
+        );
+        // The parameter is built once, and used in the body.
+        assert_eq!(args::<u64>(&log, 2), [2, 2]);
+        let (name, ty): (String, u64) = {
+            let log = log.lock().unwrap();
+            let args = &log[1].1;
+            (
+                codec::decode(&args[0]).unwrap(),
+                codec::decode(&args[1]).unwrap(),
+            )
+        };
+        assert_eq!((name.as_str(), ty), ("x", 1));
+        let invoke = &log.lock().unwrap()[5].1;
+        let named: HashMap<String, u64> = codec::decode(&invoke[1]).unwrap();
+        assert_eq!(named, HashMap::from([("x".to_string(), 5)]));
+    }
+
+    #[test]
+    fn invoking_other_functions_fails() {
+        let (_server, client, log) = start_server();
+        let call = Call::<Func<(f64,), f64>>::new("Foo", "GetFunction");
+        let result = Expr::call(&call).invoke(3.0).build(&client);
+        assert_matches!(result, Err(Error::InvalidArguments(_)));
+        let functions = Expr::<Vec<Func<(i32,), i32>>>::call(&Call::new("Foo", "GetFunctions"));
+        let result = functions.get(0).invoke(1).build(&client);
+        assert_matches!(result, Err(Error::InvalidArguments(_)));
+        assert!(procedures(&log).is_empty());
+    }
+
+    #[test]
+    fn collections() {
+        let (_server, client, log) = start_server();
+        let list = Expr::list([Expr::constant(1), Expr::constant(2)]);
+        let even = function("x", |x: Expr<i32>| (x % 2).equal(0));
+        let sum = function2(["a", "b"], |a: Expr<i32>, b: Expr<i32>| a + b);
+        let expr = list.filter(&even).to_list().aggregate(&sum);
+        assert_ok!(expr.build(&client));
+        let names = procedures(&log);
+        assert_eq!(names[..3], ["ConstantInt", "ConstantInt", "CreateList"]);
+        assert_eq!(args::<Vec<u64>>(&log, 2), [vec![1, 2]]);
+        assert_eq!(names[names.len() - 3..], ["Add", "Function", "Aggregate"]);
+
+        log.lock().unwrap().clear();
+        let tuple = Expr::tuple((Expr::constant(true), Expr::from("jeb")));
+        let dictionary = Expr::dictionary([(tuple.item1(), Expr::constant(1.5))]);
+        let set = Expr::set([Expr::from("jeb")]);
+        assert_ok!(set.contains("jeb").build(&client));
+        assert_ok!(dictionary.get("jeb").build(&client));
+        assert_eq!(
+            procedures(&log),
+            [
+                "ConstantString",
+                "CreateSet",
+                "ConstantString",
+                "Contains",
+                "ConstantBool",
+                "ConstantString",
+                "CreateTuple",
+                "ConstantInt",
+                "Get",
+                "ConstantDouble",
+                "CreateDictionary",
+                "ConstantString",
+                "Get"
+            ]
+        );
+        assert_eq!(args::<Vec<u64>>(&log, 10), [vec![9], vec![10]]);
+    }
+}
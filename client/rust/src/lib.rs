@@ -1 +1,71 @@
+// The generated service bindings name the crate by its path, `::krpc`.
+#[cfg(feature = "services")]
+extern crate self as krpc;
+
+// The host's WebSockets are the async client's only transport on wasm32.
+#[cfg(all(feature = "async", target_arch = "wasm32", not(feature = "websocket")))]
+compile_error!("the `async` feature needs the `websocket` feature on wasm32 targets");
+
+#[cfg(feature = "async")]
+pub mod async_batch;
+#[cfg(feature = "async")]
+pub mod async_client;
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+mod async_connection;
+#[cfg(feature = "async")]
+pub mod async_event;
+#[cfg(feature = "async")]
+pub mod async_stream;
+#[cfg(all(feature = "async", feature = "websocket", target_arch = "wasm32"))]
+mod async_websocket;
+pub mod batch;
+pub mod call;
+pub mod client;
 pub mod connection;
+pub mod dynamic;
+pub mod error;
+pub mod event;
+pub mod expression;
+#[cfg(feature = "async")]
+mod pipeline;
+pub mod pool;
+#[cfg(feature = "async")]
+mod runtime;
+mod serial;
+// The bindings are generated by the build, so are not there to format.
+#[cfg(feature = "services")]
+#[rustfmt::skip]
+pub mod services;
+pub mod stream;
+mod stream_manager;
+#[cfg(test)]
+mod test_server;
+mod transport;
+// On wasm32 targets the sockets are only used by the async client.
+#[cfg(all(
+    feature = "websocket",
+    any(not(target_arch = "wasm32"), feature = "async")
+))]
+mod websocket;
+
+#[cfg(feature = "async")]
+pub use async_batch::AsyncBatch;
+#[cfg(feature = "async")]
+pub use async_client::AsyncClient;
+#[cfg(feature = "async")]
+pub use async_event::AsyncEvent;
+#[cfg(feature = "async")]
+pub use async_stream::AsyncStream;
+pub use batch::{Batch, BatchCall, BatchResults};
+pub use bytes::Bytes;
+pub use call::Call;
+pub use client::Client;
+pub use connection::{DEFAULT_RPC_PORT, DEFAULT_STREAM_PORT};
+pub use dynamic::DynamicClient;
+pub use error::{Error, Result, RpcError};
+pub use event::Event;
+pub use expression::Expression;
+pub use krpc_core::{codec, remote_enum, remote_object, schema};
+pub use krpc_core::{Decode, Encode};
+pub use pool::ClientPool;
+pub use stream::{CallbackId, Stream};
@@ -0,0 +1,224 @@
+//! Request pipelining for the async client.
+//!
+//! The server handles the requests on a connection one at a time, in the
+//! order they arrive, so a client can send further requests before the
+//! responses to earlier ones come back and match each response to the oldest
+//! request still waiting. A writer task sends requests and queues a reply
+//! slot for each before it goes out, and a reader task fills the slots in
+//! order as responses arrive. Callers only wait for their own response, and
+//! one that gives up waiting cannot leave a request half written.
+
+use tokio::sync::{mpsc, oneshot};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::async_connection::{AsyncReader, AsyncWriter};
+#[cfg(target_arch = "wasm32")]
+use crate::async_websocket::{AsyncReader, AsyncWriter};
+use crate::error::{Error, Result};
+use crate::runtime::{self, Task};
+use crate::schema::{Request, Response};
+
+/// The most requests written to the socket at once.
+const MAX_BATCH: usize = 64;
+
+enum Command {
+    Send(Request, oneshot::Sender<Result<Response>>),
+    Close(oneshot::Sender<Result<()>>),
+}
+
+/// An RPC connection shared by any number of concurrent callers. Its tasks
+/// stop when it is dropped.
+pub(crate) struct Pipeline {
+    commands: mpsc::UnboundedSender<Command>,
+    _writer: Task,
+    _reader: Task,
+}
+
+impl Pipeline {
+    /// Spawns the tasks that drive the connection.
+    pub fn new(reader: AsyncReader, writer: AsyncWriter) -> Self {
+        let (commands, queued) = mpsc::unbounded_channel();
+        let (replies, pending) = mpsc::unbounded_channel();
+        Pipeline {
+            commands,
+            _writer: runtime::spawn(send_requests(writer, queued, replies)),
+            _reader: runtime::spawn(receive_responses(reader, pending)),
+        }
+    }
+
+    /// Sends `request` and waits for its response. Fails with
+    /// `Error::NotConnected` once the connection is closed or broken.
+    pub async fn send_request(&self, request: Request) -> Result<Response> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(Command::Send(request, reply))
+            .map_err(|_| Error::NotConnected)?;
+        response.await.map_err(|_| Error::NotConnected)?
+    }
+
+    /// Sends the requests already made, then shuts the connection down.
+    /// Their responses are still delivered.
+    pub async fn close(&self) -> Result<()> {
+        let (reply, closed) = oneshot::channel();
+        match self.commands.send(Command::Close(reply)) {
+            Ok(()) => closed.await.unwrap_or(Ok(())),
+            // The writer task has already exited.
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+async fn send_requests(
+    mut writer: AsyncWriter,
+    mut queued: mpsc::UnboundedReceiver<Command>,
+    replies: mpsc::UnboundedSender<oneshot::Sender<Result<Response>>>,
+) {
+    let mut commands = Vec::with_capacity(MAX_BATCH);
+    while queued.recv_many(&mut commands, MAX_BATCH).await > 0 {
+        let mut close = None;
+        for command in commands.drain(..) {
+            match command {
+                // The reply slot is queued before the request is sent, so the
+                // reader always has a slot for the response.
+                Command::Send(request, reply) if close.is_none() => {
+                    if replies.send(reply).is_err() {
+                        return;
+                    }
+                    writer.queue_message(&request);
+                }
+                // Dropping the reply fails the request as not connected.
+                Command::Send(..) => {}
+                Command::Close(reply) => close = Some(reply),
+            }
+        }
+        let sent = writer.flush().await;
+        if let Some(reply) = close {
+            let _ = reply.send(sent.and(writer.shutdown().await));
+            return;
+        }
+        if sent.is_err() {
+            // The reader fails when the broken socket is read, failing the
+            // requests waiting for a response.
+            return;
+        }
+    }
+}
+
+async fn receive_responses(
+    mut reader: AsyncReader,
+    mut pending: mpsc::UnboundedReceiver<oneshot::Sender<Result<Response>>>,
+) {
+    loop {
+        let response = reader.receive_message::<Response>().await;
+        let Some(reply) = pending.recv().await else {
+            return;
+        };
+        let failed = response.is_err();
+        // The caller may have stopped waiting, which leaves the order of
+        // the remaining responses unchanged.
+        let _ = reply.send(response);
+        if failed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_connection::AsyncConnection;
+    use crate::connection::{read_message, write_message, DEFAULT_MAX_MESSAGE_SIZE};
+    use crate::schema::{Argument, ProcedureCall, ProcedureResult};
+    use claim::{assert_matches, assert_ok};
+    use std::net::TcpListener;
+
+    fn request(value: u8) -> Request {
+        Request {
+            calls: vec![ProcedureCall {
+                arguments: vec![Argument {
+                    value: vec![value].into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn value(response: &Response) -> u8 {
+        response.results[0].value[0]
+    }
+
+    /// Starts a server that reads `batch` requests before answering any of
+    /// them, in order, with the value of their first argument, and repeats
+    /// until the client disconnects.
+    async fn connect(batch: usize) -> Pipeline {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut buffer = Vec::new();
+            loop {
+                let mut requests: Vec<Request> = Vec::new();
+                while requests.len() < batch {
+                    match read_message(&mut socket, &mut buffer, DEFAULT_MAX_MESSAGE_SIZE) {
+                        Ok(request) => requests.push(request),
+                        Err(_) => return,
+                    }
+                }
+                for request in requests {
+                    let response = Response {
+                        results: vec![ProcedureResult {
+                            value: request.calls[0].arguments[0].value.clone(),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    };
+                    write_message(&mut socket, &response, &mut Vec::new()).unwrap();
+                }
+            }
+        });
+        let (reader, writer) =
+            assert_ok!(AsyncConnection::connect("127.0.0.1", port, None).await).split();
+        Pipeline::new(reader, writer)
+    }
+
+    #[tokio::test]
+    async fn matches_responses_in_order() {
+        // Every request must be sent before the first response arrives.
+        let pipeline = connect(3).await;
+        let (first, second, third) = tokio::join!(
+            pipeline.send_request(request(1)),
+            pipeline.send_request(request(2)),
+            pipeline.send_request(request(3)),
+        );
+        assert_eq!(value(&assert_ok!(first)), 1);
+        assert_eq!(value(&assert_ok!(second)), 2);
+        assert_eq!(value(&assert_ok!(third)), 3);
+    }
+
+    #[tokio::test]
+    async fn survives_cancelled_requests() {
+        let pipeline = connect(2).await;
+        let timeout = std::time::Duration::from_millis(10);
+        // Sent, but abandoned before the server answers it.
+        assert!(
+            tokio::time::timeout(timeout, pipeline.send_request(request(1)))
+                .await
+                .is_err()
+        );
+        let response = assert_ok!(pipeline.send_request(request(2)).await);
+        assert_eq!(value(&response), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_once_closed() {
+        let pipeline = connect(1).await;
+        assert_ok!(pipeline.send_request(request(1)).await);
+        assert_ok!(pipeline.close().await);
+        assert_matches!(
+            pipeline.send_request(request(2)).await,
+            Err(Error::NotConnected)
+        );
+        assert_ok!(pipeline.close().await);
+    }
+}
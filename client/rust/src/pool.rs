@@ -0,0 +1,307 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::Bytes;
+
+use crate::call::Call;
+use crate::client::Client;
+use crate::codec::{Decode, Encode};
+use crate::connection::ClientIdentifier;
+use crate::error::{Error, Result};
+use crate::schema::ProcedureCall;
+
+/// A set of RPC connections to the same server, used as one client.
+///
+/// The server runs the requests of each connection one at a time, so a
+/// single `Client` shared by many threads makes them queue for it. A pool
+/// spreads their calls across several connections, each to the one with the
+/// fewest calls in progress, so that the server runs them side by side.
+///
+/// Each connection is a client of its own, connected with the same name, so
+/// the server's list of clients shows one entry per connection.
+///
+/// Remote objects are identified by ids that are the same on every
+/// connection, so an object returned by a call on one connection can be
+/// passed to a call on another.
+///
+/// Only the first connection, the primary, is connected to the stream
+/// server. Streams, events and stream update callbacks are added through
+/// `primary`.
+///
+/// ```no_run
+/// # fn main() -> krpc::Result<()> {
+/// let pool = krpc::ClientPool::connect("", "127.0.0.1", 50000, Some(50001), 4)?;
+/// std::thread::scope(|scope| {
+///     for _ in 0..8 {
+///         scope.spawn(|| pool.call(&krpc::Call::<f64>::new("SpaceCenter", "get_UT")));
+///     }
+/// });
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientPool {
+    clients: Vec<Client>,
+    in_progress: Vec<AtomicUsize>,
+}
+
+/// Counts a call in progress on one of the pool's connections.
+struct Lease<'a> {
+    client: &'a Client,
+    in_progress: &'a AtomicUsize,
+}
+
+impl ClientPool {
+    /// Opens `connections` connections to the RPC server at `address` and
+    /// `rpc_port`, each identifying itself as `name`, and connects the
+    /// primary to the stream server on `stream_port` if given. Fails with
+    /// `Error::InvalidArguments` if `connections` is zero.
+    pub fn connect(
+        name: &str,
+        address: &str,
+        rpc_port: u16,
+        stream_port: Option<u16>,
+        connections: usize,
+    ) -> Result<Self> {
+        if connections == 0 {
+            return Err(Error::InvalidArguments(
+                "a pool needs at least one connection".to_string(),
+            ));
+        }
+        let mut clients = vec![Client::connect(name, address, rpc_port, stream_port)?];
+        for _ in 1..connections {
+            clients.push(Client::connect(name, address, rpc_port, None)?);
+        }
+        Ok(ClientPool {
+            in_progress: clients.iter().map(|_| AtomicUsize::new(0)).collect(),
+            clients,
+        })
+    }
+
+    /// The number of connections in the pool.
+    pub fn connections(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// The primary connection, which is also connected to the stream server.
+    pub fn primary(&self) -> &Client {
+        &self.clients[0]
+    }
+
+    /// The identifier the server assigned to the primary connection, which
+    /// identifies the pool to the stream server.
+    pub fn client_identifier(&self) -> &ClientIdentifier {
+        self.primary().client_identifier()
+    }
+
+    /// Runs `f` with the connection that has the fewest calls in progress,
+    /// counting it as busy until `f` returns. Use this to send a `Batch`.
+    pub fn with_client<R>(&self, f: impl FnOnce(&Client) -> R) -> R {
+        f(self.lease().client)
+    }
+
+    /// Makes a typed call on the least busy connection and decodes its
+    /// result.
+    pub fn call<T: Decode>(&self, call: &Call<T>) -> Result<T> {
+        self.with_client(|client| client.call(call))
+    }
+
+    /// Invokes a procedure on the least busy connection, as
+    /// `Client::invoke` does.
+    pub fn invoke(&self, service: &str, procedure: &str, args: &[&dyn Encode]) -> Result<Bytes> {
+        self.with_client(|client| client.invoke(service, procedure, args))
+    }
+
+    /// Like `invoke`, but decodes the result as a `T`.
+    pub fn invoke_typed<T: Decode>(
+        &self,
+        service: &str,
+        procedure: &str,
+        args: &[&dyn Encode],
+    ) -> Result<T> {
+        self.with_client(|client| client.invoke_typed(service, procedure, args))
+    }
+
+    /// Sends `call` on the least busy connection and returns its encoded
+    /// result.
+    pub fn invoke_call(&self, call: &ProcedureCall) -> Result<Bytes> {
+        self.with_client(|client| client.invoke_call(call))
+    }
+
+    /// Closes every connection in the pool, returning the first error.
+    pub fn close(&mut self) -> Result<()> {
+        self.clients
+            .iter()
+            .map(Client::close)
+            .fold(Ok(()), Result::and)
+    }
+
+    fn lease(&self) -> Lease<'_> {
+        loop {
+            let (index, count) = self
+                .in_progress
+                .iter()
+                .map(|in_progress| in_progress.load(Ordering::Relaxed))
+                .enumerate()
+                .min_by_key(|&(_, count)| count)
+                .expect("a pool has at least one connection");
+            // The connection is only claimed if no other caller claimed it
+            // since its count was read, so that callers arriving together
+            // spread out rather than all picking the same connection.
+            let in_progress = &self.in_progress[index];
+            if in_progress
+                .compare_exchange(count, count + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Lease {
+                    client: &self.clients[index],
+                    in_progress,
+                };
+            }
+        }
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        self.in_progress.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{self, RemoteObject};
+    use crate::schema::{ProcedureResult, Request, Response};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+    use std::sync::{Arc, Barrier, Mutex};
+
+    crate::remote_object! {
+        struct Vessel;
+    }
+
+    /// Answers each call with the index of the connection it arrived on.
+    fn connection_index(index: usize, _: Request) -> Response {
+        Response {
+            results: vec![ProcedureResult {
+                value: codec::encode(&(index as u32)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn runs_calls_in_parallel() {
+        // Each call waits for the other two, so they only finish if they run
+        // on separate connections at the same time.
+        let barrier = Arc::new(Barrier::new(3));
+        let server = TestServer::start_pool(3, move |index, request| {
+            barrier.wait();
+            connection_index(index, request)
+        });
+        let pool = assert_ok!(ClientPool::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            Some(server.stream_port),
+            3
+        ));
+        assert_eq!(pool.connections(), 3);
+        let call = Call::<u32>::new("KRPC", "GetStatus");
+        let mut indices: Vec<u32> = std::thread::scope(|scope| {
+            let calls: Vec<_> = (0..3)
+                .map(|_| scope.spawn(|| assert_ok!(pool.call(&call))))
+                .collect();
+            calls.into_iter().map(|call| call.join().unwrap()).collect()
+        });
+        indices.sort();
+        assert_eq!(indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn spreads_concurrent_callers() {
+        let server = TestServer::start_pool(2, connection_index);
+        let pool = assert_ok!(ClientPool::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            None,
+            2
+        ));
+        // Every lease is held until all six have been taken.
+        let barrier = Barrier::new(6);
+        let mut primary = std::thread::scope(|scope| {
+            let leases: Vec<_> = (0..6)
+                .map(|_| {
+                    scope.spawn(|| {
+                        pool.with_client(|client| {
+                            barrier.wait();
+                            std::ptr::eq(client, pool.primary())
+                        })
+                    })
+                })
+                .collect();
+            leases
+                .into_iter()
+                .map(|lease| lease.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        primary.sort();
+        assert_eq!(primary, [false, false, false, true, true, true]);
+    }
+
+    #[test]
+    fn needs_a_connection() {
+        assert_matches!(
+            ClientPool::connect("Jeb", "127.0.0.1", 0, None, 0).err(),
+            Some(Error::InvalidArguments(_))
+        );
+    }
+
+    #[test]
+    fn shares_objects_across_connections() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        let server = TestServer::start_pool(2, move |index, request: Request| {
+            let call = &request.calls[0];
+            log.lock().unwrap().push((index, call.procedure.clone()));
+            let value = match call.procedure.as_str() {
+                "get_ActiveVessel" => codec::encode(&Vessel::from_id(42)),
+                _ => {
+                    let vessel = assert_ok!(codec::decode::<Vessel>(&call.arguments[0].value));
+                    assert_eq!(vessel, Vessel::from_id(42));
+                    Bytes::new()
+                }
+            };
+            Response {
+                results: vec![ProcedureResult {
+                    value,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        });
+        let mut pool = assert_ok!(ClientPool::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            None,
+            2
+        ));
+        pool.with_client(|first| {
+            // The first connection is in use, so this call goes to the second.
+            let vessel =
+                assert_ok!(pool.call(&Call::<Vessel>::new("SpaceCenter", "get_ActiveVessel")));
+            let recover = Call::<()>::new("SpaceCenter", "Vessel_Recover").arg(&vessel);
+            assert_ok!(first.call(&recover));
+        });
+        assert_eq!(
+            *received.lock().unwrap(),
+            [
+                (1, "get_ActiveVessel".to_string()),
+                (0, "Vessel_Recover".to_string())
+            ]
+        );
+        assert_ok!(pool.close());
+    }
+}
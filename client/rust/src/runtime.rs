@@ -0,0 +1,133 @@
+//! The runtime that the async client's tasks and timers run on: tokio, or on
+//! wasm32 targets the host's event loop, whose tasks are not `Send`.
+
+use std::future::Future;
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+use std::pin::pin;
+#[cfg(target_arch = "wasm32")]
+use std::task::Poll;
+
+#[cfg(target_arch = "wasm32")]
+use tokio::sync::oneshot;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::closure::Closure;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsCast;
+
+/// A task running in the background, which is stopped when this is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Task(tokio::task::JoinHandle<()>);
+
+/// A task running in the background, which is stopped when this is dropped.
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct Task {
+    /// Dropping this wakes the task and ends it.
+    _stop: oneshot::Sender<()>,
+}
+
+/// Starts running `future` on the current tokio runtime.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn<F>(future: F) -> Task
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    Task(tokio::spawn(future))
+}
+
+/// Starts running `future` on the host's event loop.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn<F>(future: F) -> Task
+where
+    F: Future<Output = ()> + 'static,
+{
+    let (stop, stopped) = oneshot::channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        until(future, stopped).await;
+    });
+    Task { _stop: stop }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Runs `future` to completion in the background, if there is a runtime to
+/// run it on.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn detach<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        runtime.spawn(future);
+    }
+}
+
+/// Runs `future` to completion in the background, on the host's event loop.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn detach<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// Runs `future`, giving up once `duration` has elapsed, in which case it
+/// resolves to `None`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    tokio::time::timeout(duration, future).await.ok()
+}
+
+/// Runs `future`, giving up once `duration` has elapsed, in which case it
+/// resolves to `None`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    until(future, sleep(duration)).await
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, milliseconds: i32);
+}
+
+/// Resolves once `duration` has elapsed, using the host's `setTimeout`,
+/// which browsers and workers both have.
+#[cfg(target_arch = "wasm32")]
+fn sleep(duration: Duration) -> impl Future<Output = ()> + Send + Sync {
+    let (elapsed, sleep) = oneshot::channel();
+    let handler = Closure::once_into_js(move || {
+        // The sleep may have been given up on.
+        let _ = elapsed.send(());
+    });
+    // Longer delays than this overflow and fire straight away.
+    let milliseconds = duration.as_millis().min(i32::MAX as u128) as i32;
+    set_timeout(handler.unchecked_ref(), milliseconds);
+    async move {
+        let _ = sleep.await;
+    }
+}
+
+/// Runs `future` until it completes, resolving to its output, or until
+/// `stop` does, resolving to `None`.
+#[cfg(target_arch = "wasm32")]
+async fn until<F: Future, S: Future>(future: F, stop: S) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut stop = pin!(stop);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        stop.as_mut().poll(cx).map(|_| None)
+    })
+    .await
+}
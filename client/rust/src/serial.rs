@@ -0,0 +1,311 @@
+//! The SerialIO transport, for talking to the server over a serial port or
+//! any other byte pipe.
+//!
+//! The protocol itself is implemented by `krpc_core::serial`, which this
+//! drives over a `std::io` port.
+//!
+//! The SerialIO server has no stream server, so streams and events are not
+//! available over it.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use krpc_core::io::FromStd;
+use krpc_core::serial;
+
+use crate::connection::{ClientIdentifier, DEFAULT_MAX_MESSAGE_SIZE};
+use crate::error::{Error, Result};
+use crate::transport::Transport;
+
+/// A connection to the SerialIO server over `port`.
+pub(crate) struct SerialConnection<S> {
+    port: Option<Patient<S>>,
+    buffer: Vec<u8>,
+    send_buffer: Vec<u8>,
+}
+
+/// Retries reads that time out, as serial ports do when no byte arrives
+/// within their configured timeout, so that a message is not cut short by a
+/// slow server. Once `timeout` has passed since the message was waited for,
+/// the read fails with `ErrorKind::TimedOut` instead.
+struct Patient<S> {
+    port: S,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl<S> Patient<S> {
+    /// Starts the wait for the next message.
+    fn start(&mut self) {
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+    }
+}
+
+impl<S: Read> Read for Patient<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            match self.port.read(buf) {
+                Err(e) if e.kind() == ErrorKind::TimedOut => {
+                    if self
+                        .deadline
+                        .is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        return Err(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<S: Write> Write for Patient<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl<S: Read + Write> SerialConnection<S> {
+    /// Opens the connection, identifying the client as `name`, and returns
+    /// it with the identifier the server assigned. The handshake, and each
+    /// response after it, fails with `ErrorKind::TimedOut` if it takes longer
+    /// than `timeout` to arrive.
+    pub fn connect(
+        port: S,
+        name: &str,
+        timeout: Option<Duration>,
+    ) -> Result<(Self, ClientIdentifier)> {
+        let mut port = Patient {
+            port,
+            timeout,
+            deadline: None,
+        };
+        port.start();
+        let mut buffer = Vec::new();
+        let client_identifier = serial::connect(&mut FromStd(&mut port), &mut buffer, name)?;
+        let connection = SerialConnection {
+            port: Some(port),
+            buffer,
+            send_buffer: Vec::new(),
+        };
+        Ok((connection, client_identifier))
+    }
+}
+
+impl<S: Read + Write + Send> Transport for SerialConnection<S> {
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        let port = self.port.as_mut().ok_or(Error::NotConnected)?;
+        Ok(serial::write_request(&mut FromStd(port), message)?)
+    }
+
+    fn receive(&mut self) -> Result<&[u8]> {
+        let port = self.port.as_mut().ok_or(Error::NotConnected)?;
+        port.start();
+        match serial::read_encoded_response(
+            &mut FromStd(port),
+            &mut self.buffer,
+            DEFAULT_MAX_MESSAGE_SIZE,
+        ) {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                // The rest of a response that timed out may still arrive, and
+                // would be mistaken for the next, so the port is let go of.
+                let e = Error::from(e);
+                if matches!(&e, Error::Io(e) if e.kind() == ErrorKind::TimedOut) {
+                    self.port = None;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        // A byte pipe has no shutdown, so closing just lets go of it.
+        self.port = None;
+        Ok(())
+    }
+
+    fn send_buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.send_buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::codec::RemoteObject;
+    use crate::connection::{read_message, write_message, DEFAULT_MAX_MESSAGE_SIZE};
+    use crate::error::Error;
+    use crate::expression::Expression;
+    use crate::schema::{
+        connection_request, connection_response, ConnectionResponse, MultiplexedRequest,
+        MultiplexedResponse, ProcedureResult, Response,
+    };
+    use crate::test_server::IDENTIFIER;
+    use claim::{assert_matches, assert_ok};
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    /// A socket whose reads time out as a serial port's do, with
+    /// `ErrorKind::TimedOut`.
+    struct SerialPort(TcpStream);
+
+    impl SerialPort {
+        fn new(socket: TcpStream) -> Self {
+            socket
+                .set_read_timeout(Some(Duration::from_millis(1)))
+                .unwrap();
+            SerialPort(socket)
+        }
+    }
+
+    impl Read for SerialPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.read(buf) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => Err(ErrorKind::TimedOut.into()),
+                result => result,
+            }
+        }
+    }
+
+    impl Write for SerialPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    /// Serves the SerialIO protocol on one end of a byte pipe, answering the
+    /// connection request with `status` and each call with its first
+    /// argument. Returns the client's end and the name it connected with.
+    fn start_server(status: connection_response::Status) -> (SerialPort, JoinHandle<String>) {
+        start_slow_server(status, Some(Duration::ZERO))
+    }
+
+    /// Like `start_server`, but waits `delay` before answering each call, or
+    /// never answers calls if it is `None`.
+    fn start_slow_server(
+        status: connection_response::Status,
+        delay: Option<Duration>,
+    ) -> (SerialPort, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut port, _) = listener.accept().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let request: MultiplexedRequest =
+                read_message(&mut port, &mut buffer, DEFAULT_MAX_MESSAGE_SIZE).unwrap();
+            let connection_request = request.connection_request.unwrap();
+            assert_eq!(connection_request.r#type(), connection_request::Type::Rpc);
+            let response = ConnectionResponse {
+                status: status as i32,
+                client_identifier: IDENTIFIER.to_vec().into(),
+                ..Default::default()
+            };
+            write_message(&mut port, &response, &mut Vec::new()).unwrap();
+            while let Ok(request) = read_message::<_, MultiplexedRequest>(
+                &mut port,
+                &mut buffer,
+                DEFAULT_MAX_MESSAGE_SIZE,
+            ) {
+                let Some(delay) = delay else {
+                    continue;
+                };
+                std::thread::sleep(delay);
+                let call = &request.request.unwrap().calls[0];
+                let response = MultiplexedResponse {
+                    response: Some(Response {
+                        results: vec![ProcedureResult {
+                            value: call.arguments[0].value.clone(),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                };
+                write_message(&mut port, &response, &mut Vec::new()).unwrap();
+            }
+            connection_request.client_name
+        });
+        (SerialPort::new(client), server)
+    }
+
+    #[test]
+    fn connects_and_calls() {
+        let (port, server) = start_server(connection_response::Status::Ok);
+        let client = assert_ok!(Client::connect_serial("Jeb", port));
+        assert_eq!(client.client_identifier(), &IDENTIFIER);
+        for value in ["Kerbin", "Mun"] {
+            let echoed = assert_ok!(client.invoke_typed::<String>("KRPC", "Echo", &[&value]));
+            assert_eq!(echoed, value);
+        }
+        assert_matches!(
+            client.add_event(&Expression::from_id(1)),
+            Err(Error::NoStreamConnection)
+        );
+        assert_ok!(client.close());
+        assert_matches!(
+            client.invoke("KRPC", "Echo", &[&1u32]),
+            Err(Error::NotConnected)
+        );
+        drop(client);
+        assert_eq!(server.join().unwrap(), "Jeb");
+    }
+
+    #[test]
+    fn waits_for_slow_responses() {
+        // Each response takes many of the port's read timeouts to arrive.
+        let (port, _) = start_slow_server(
+            connection_response::Status::Ok,
+            Some(Duration::from_millis(50)),
+        );
+        let timeout = Some(Duration::from_secs(5));
+        let client = assert_ok!(Client::connect_serial_with_timeout("Jeb", port, timeout));
+        let echoed = assert_ok!(client.invoke_typed::<String>("KRPC", "Echo", &[&"Kerbin"]));
+        assert_eq!(echoed, "Kerbin");
+    }
+
+    #[test]
+    fn times_out_on_dead_link() {
+        let (port, _) = start_slow_server(connection_response::Status::Ok, None);
+        let timeout = Some(Duration::from_millis(50));
+        let client = assert_ok!(Client::connect_serial_with_timeout("Jeb", port, timeout));
+        assert_matches!(
+            client.invoke("KRPC", "Echo", &[&1u32]),
+            Err(Error::Io(e)) if e.kind() == ErrorKind::TimedOut
+        );
+        assert_matches!(
+            client.invoke("KRPC", "Echo", &[&1u32]),
+            Err(Error::NotConnected)
+        );
+    }
+
+    #[test]
+    fn handshake_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = SerialPort::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        let timeout = Some(Duration::from_millis(50));
+        assert_matches!(
+            Client::connect_serial_with_timeout("Jeb", port, timeout).err(),
+            Some(Error::Io(e)) if e.kind() == ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn connection_refused() {
+        let (port, _) = start_server(connection_response::Status::WrongType);
+        assert_matches!(
+            Client::connect_serial("Jeb", port).err(),
+            Some(Error::WrongConnectionType(_))
+        );
+    }
+}
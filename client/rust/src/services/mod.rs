@@ -0,0 +1,22 @@
+//! Bindings for the services that ship with kRPC, generated from their
+//! service definitions. Each service is a module of functions that build the
+//! calls to its procedures, along with its classes and enumerations.
+//!
+//! ```no_run
+//! # fn main() -> krpc::Result<()> {
+//! use krpc::services::space_center;
+//!
+//! let client = krpc::Client::connect("", "127.0.0.1", 50000, None)?;
+//! let vessel = client.call(&space_center::active_vessel())?;
+//! println!("{}", client.call(&vessel.name())?);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod drawing;
+pub mod infernal_robotics;
+pub mod kerbal_alarm_clock;
+pub mod krpc;
+pub mod remote_tech;
+pub mod space_center;
+pub mod ui;
@@ -0,0 +1,410 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::call::Call;
+use crate::client::Shared;
+use crate::codec::Decode;
+use crate::error::{Error, Result};
+use crate::schema;
+use crate::stream_manager::{decode_any, StreamCallback, StreamState, StreamValue, UpdateSignal};
+
+/// Identifies a callback added to a stream or client, for removing it later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+impl CallbackId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        CallbackId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A streamed procedure call, whose result the server sends to the client
+/// whenever it changes.
+///
+/// The most recently received value is cached, and `get` returns it without
+/// a round trip to the server. Streams are added with `Client::add_stream`,
+/// and removed from the server when the last handle to them is dropped.
+///
+/// The server gives a call that is already streamed the id of the existing
+/// stream, so streaming the same call twice returns two handles to the same
+/// stream. Such handles share their value, rate and started state.
+///
+/// ```no_run
+/// # fn main() -> krpc::Result<()> {
+/// # let client = krpc::Client::connect("", "127.0.0.1", 50000, Some(50001))?;
+/// let ut = client.add_stream(&krpc::Call::<f64>::new("SpaceCenter", "get_UT"))?;
+/// ut.set_rate(10.0)?;
+/// println!("{}", ut.get()?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Stream<T> {
+    shared: Arc<Shared>,
+    state: Arc<StreamState>,
+    removed: bool,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> Stream<T>
+where
+    T: Decode + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn add(shared: &Arc<Shared>, call: &Call<T>, start: bool) -> Result<Self> {
+        shared.streams()?;
+        // The stream is always added paused, and only started once it is
+        // registered, so that its first update cannot arrive before the
+        // manager knows how to decode it.
+        let add = Call::<schema::Stream>::new("KRPC", "AddStream")
+            .arg(call.message())
+            .arg(&false);
+        let stream = Self::from_id(shared, shared.call(&add)?.id)?;
+        if start {
+            stream.start()?;
+        }
+        Ok(stream)
+    }
+
+    /// Wraps a paused stream that already exists on the server, such as the
+    /// stream of an event.
+    pub(crate) fn from_id(shared: &Arc<Shared>, id: u64) -> Result<Self> {
+        Ok(Stream {
+            shared: Arc::clone(shared),
+            state: shared.streams()?.register(id, false, decode_any::<T>),
+            removed: false,
+            value: PhantomData,
+        })
+    }
+
+    /// The most recently received value of the stream, or the error the
+    /// procedure failed with. Fails with `Error::NoStreamValue` if no update
+    /// has been received yet.
+    pub fn get(&self) -> Result<T> {
+        match self.state.value() {
+            Some(value) => value.get(),
+            None => Err(Error::NoStreamValue),
+        }
+    }
+
+    /// Adds a callback that is run with each value received for the stream,
+    /// or with the error the procedure failed with.
+    ///
+    /// Callbacks run on the thread that receives stream updates, after the
+    /// value is stored and waiting threads are woken, in the order they were
+    /// added. Until a callback returns no further updates are processed, so
+    /// it must not block waiting for one. Callbacks added or removed while
+    /// an update is being processed, including by a callback removing
+    /// itself, take effect from the next update. A callback that panics is
+    /// removed, and does not stop the others from running.
+    ///
+    /// Callbacks belong to the stream rather than to this handle, so they
+    /// are shared by every handle to the same stream.
+    pub fn add_callback<F>(&self, mut callback: F) -> CallbackId
+    where
+        F: FnMut(Result<T>) + Send + 'static,
+    {
+        let callback: Arc<StreamCallback> =
+            Arc::new(Mutex::new(move |value: &StreamValue| callback(value.get())));
+        self.state.callbacks.add(callback)
+    }
+}
+
+impl<T> Stream<T> {
+    /// The id the server assigned to the stream.
+    pub fn id(&self) -> u64 {
+        self.state.id
+    }
+
+    /// Whether the server has been asked to send updates for the stream.
+    pub fn started(&self) -> bool {
+        self.state.started.load(Ordering::SeqCst)
+    }
+
+    /// Asks the server to start sending updates for a stream added with
+    /// `Client::add_stream_paused`. Does nothing if it was already started.
+    pub fn start(&self) -> Result<()> {
+        if self.started() {
+            return Ok(());
+        }
+        self.shared
+            .call(&Call::<()>::new("KRPC", "StartStream").arg(&self.id()))?;
+        self.state.started.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Blocks until the next update for the stream is received, starting the
+    /// stream first if it is paused. Returns false if `timeout` elapses
+    /// first, and fails if the client is disconnected from the stream
+    /// server while waiting.
+    ///
+    /// Each call waits for an update received after it was made, so a loop
+    /// calling `wait` then `get` sees every update at most once.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let seen = self.state.signal.count();
+        self.start()?;
+        self.state.signal.wait_since(seen, timeout)
+    }
+
+    pub(crate) fn signal(&self) -> &UpdateSignal {
+        &self.state.signal
+    }
+
+    /// Removes a callback added with `add_callback`. Returns false if it had
+    /// already been removed.
+    pub fn remove_callback(&self, id: CallbackId) -> bool {
+        self.state.callbacks.remove(id)
+    }
+
+    /// The update rate of the stream in Hertz, or zero if it is unlimited.
+    pub fn rate(&self) -> f32 {
+        *self.state.rate.lock().unwrap()
+    }
+
+    /// Sets the update rate of the stream in Hertz. Zero removes the limit.
+    pub fn set_rate(&self, rate: f32) -> Result<()> {
+        self.shared.call(
+            &Call::<()>::new("KRPC", "SetStreamRate")
+                .arg(&self.id())
+                .arg(&rate),
+        )?;
+        *self.state.rate.lock().unwrap() = rate;
+        Ok(())
+    }
+
+    /// Removes the stream from the server, as dropping it does, but reports
+    /// any error in doing so.
+    pub fn remove(mut self) -> Result<()> {
+        self.removed = true;
+        self.release()
+    }
+
+    /// Drops this handle, removing the stream from the server if it was the
+    /// last one.
+    fn release(&self) -> Result<()> {
+        let last = match self.shared.streams() {
+            Ok(streams) => streams.unregister(self.id()),
+            Err(_) => false,
+        };
+        if last {
+            self.shared
+                .call(&Call::<()>::new("KRPC", "RemoveStream").arg(&self.id()))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        if !self.removed {
+            // The client may already be closed, in which case the server has
+            // discarded the stream anyway.
+            let _ = self.release();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Stream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream")
+            .field("id", &self.id())
+            .field("started", &self.started())
+            .field("rate", &self.rate())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::codec;
+    use crate::schema::{ProcedureResult, Request, Response, StreamResult, StreamUpdate};
+    use crate::test_server::TestServer;
+    use claim::{assert_matches, assert_ok};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    /// Answers the `KRPC` stream procedures, streaming every call with id 7,
+    /// and records the name of each procedure called.
+    fn start_server() -> (TestServer, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&calls);
+        let server = TestServer::start(move |request: Request| {
+            let call = &request.calls[0];
+            log.lock().unwrap().push(call.procedure.clone());
+            let value = match call.procedure.as_str() {
+                "AddStream" => codec::encode(&schema::Stream { id: 7 }),
+                _ => Default::default(),
+            };
+            Response {
+                results: vec![ProcedureResult {
+                    value,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        });
+        (server, calls)
+    }
+
+    fn connect(server: &TestServer) -> Client {
+        assert_ok!(Client::connect(
+            "Jeb",
+            "127.0.0.1",
+            server.rpc_port,
+            Some(server.stream_port)
+        ))
+    }
+
+    fn update(value: f64) -> StreamUpdate {
+        StreamUpdate {
+            results: vec![StreamResult {
+                id: 7,
+                result: Some(ProcedureResult {
+                    value: codec::encode(&value),
+                    ..Default::default()
+                }),
+            }],
+        }
+    }
+
+    fn wait_for_value(stream: &Stream<f64>, expected: f64) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stream.get().ok() != Some(expected) {
+            assert!(Instant::now() < deadline, "no stream update received");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn caches_latest_value() {
+        let (server, calls) = start_server();
+        let client = connect(&server);
+        let call = Call::<f64>::new("SpaceCenter", "get_UT");
+        let stream = assert_ok!(client.add_stream(&call));
+        assert_eq!(stream.id(), 7);
+        assert!(stream.started());
+        assert_matches!(stream.get(), Err(Error::NoStreamValue));
+
+        server.send_update(update(1.5));
+        wait_for_value(&stream, 1.5);
+        server.send_update(update(2.5));
+        wait_for_value(&stream, 2.5);
+        assert_eq!(*calls.lock().unwrap(), ["AddStream", "StartStream"]);
+    }
+
+    #[test]
+    fn start_paused_and_set_rate() {
+        let (server, calls) = start_server();
+        let client = connect(&server);
+        let stream = assert_ok!(client.add_stream_paused(&Call::<f64>::new("KRPC", "get_UT")));
+        assert!(!stream.started());
+        assert_eq!(stream.rate(), 0.0);
+        assert_ok!(stream.start());
+        assert_ok!(stream.start());
+        assert!(stream.started());
+        assert_ok!(stream.set_rate(10.0));
+        assert_eq!(stream.rate(), 10.0);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["AddStream", "StartStream", "SetStreamRate"]
+        );
+    }
+
+    #[test]
+    fn removes_stream_when_last_handle_dropped() {
+        let (server, calls) = start_server();
+        let client = connect(&server);
+        let call = Call::<f64>::new("KRPC", "get_UT");
+        let first = assert_ok!(client.add_stream(&call));
+        let second = assert_ok!(client.add_stream(&call));
+        server.send_update(update(3.0));
+        wait_for_value(&first, 3.0);
+        assert_eq!(assert_ok!(second.get()), 3.0);
+
+        drop(first);
+        assert!(!calls.lock().unwrap().contains(&"RemoveStream".to_string()));
+        assert_ok!(second.remove());
+        assert_eq!(calls.lock().unwrap().last().unwrap(), "RemoveStream");
+    }
+
+    #[test]
+    fn callbacks() {
+        let (server, _) = start_server();
+        let client = connect(&server);
+        let stream = Arc::new(assert_ok!(
+            client.add_stream(&Call::<f64>::new("KRPC", "get_UT"))
+        ));
+        let (values, received) = std::sync::mpsc::channel();
+
+        let sender = values.clone();
+        stream.add_callback(move |value| sender.send(("all", value.unwrap())).unwrap());
+        // Removes itself after its first value.
+        let sender = values.clone();
+        let handle = Arc::downgrade(&stream);
+        let id = Arc::new(Mutex::new(None));
+        let own_id = Arc::clone(&id);
+        *id.lock().unwrap() = Some(stream.add_callback(move |value| {
+            sender.send(("once", value.unwrap())).unwrap();
+            let stream = handle.upgrade().unwrap();
+            assert!(stream.remove_callback(own_id.lock().unwrap().unwrap()));
+        }));
+        // Panics on its first value, and is removed.
+        stream.add_callback(|_| panic!("callback failed"));
+        let removed = stream.add_callback(|_| unreachable!());
+        assert!(stream.remove_callback(removed));
+        assert!(!stream.remove_callback(removed));
+
+        server.send_update(update(1.0));
+        server.send_update(update(2.0));
+        let expected = [("all", 1.0), ("once", 1.0), ("all", 2.0)];
+        for expected in expected {
+            assert_eq!(
+                received.recv_timeout(Duration::from_secs(5)).unwrap(),
+                expected
+            );
+        }
+        // The receiver thread survived the panic.
+        server.send_update(update(3.0));
+        assert_eq!(
+            received.recv_timeout(Duration::from_secs(5)).unwrap(),
+            ("all", 3.0)
+        );
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn wait_for_next_update() {
+        let (server, calls) = start_server();
+        let client = connect(&server);
+        let stream = assert_ok!(client.add_stream_paused(&Call::<f64>::new("KRPC", "get_UT")));
+        assert!(!assert_ok!(stream.wait(Some(Duration::from_millis(10)))));
+        assert!(stream.started());
+        assert_eq!(calls.lock().unwrap().last().unwrap(), "StartStream");
+
+        let (sent, wait_for_sent) = std::sync::mpsc::channel();
+        let updates = std::thread::spawn(move || {
+            for value in [1.0, 2.0, 3.0] {
+                wait_for_sent.recv().unwrap();
+                server.send_update(update(value));
+            }
+            server
+        });
+        for value in [1.0, 2.0, 3.0] {
+            let seen = stream.state.signal.count();
+            sent.send(()).unwrap();
+            assert!(assert_ok!(stream.state.signal.wait_since(seen, None)));
+            assert_eq!(assert_ok!(stream.get()), value);
+        }
+        let server = updates.join().unwrap();
+
+        let waiter = std::thread::spawn(move || stream.wait(None));
+        std::thread::sleep(Duration::from_millis(10));
+        assert_ok!(client.close());
+        assert_matches!(waiter.join().unwrap(), Err(Error::NoStreamConnection));
+        drop(server);
+    }
+}
@@ -0,0 +1,521 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::net::{Shutdown, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::codec::Decode;
+use crate::connection::{ClientIdentifier, Connection};
+use crate::error::{Error, Result};
+#[cfg(feature = "async")]
+use crate::runtime;
+use crate::schema::{self, ProcedureResult, StreamUpdate};
+use crate::stream::CallbackId;
+use crate::transport::Transport;
+
+/// A decoded stream value, type-erased so that streams of every type can be
+/// held by the same manager.
+pub(crate) type AnyValue = Arc<dyn Any + Send + Sync>;
+
+/// Decodes the value of a stream result into its Rust type.
+pub(crate) type DecodeFn = fn(&[u8]) -> Result<AnyValue>;
+
+/// Decodes a stream value of type `T`.
+pub(crate) fn decode_any<T: Decode + Send + Sync + 'static>(data: &[u8]) -> Result<AnyValue> {
+    Ok(Arc::new(T::decode(data)?))
+}
+
+/// The most recent result received for a stream.
+#[derive(Clone)]
+pub(crate) enum StreamValue {
+    Value(AnyValue),
+    /// The procedure failed on the server.
+    Error(schema::Error),
+    /// The result could not be decoded.
+    Invalid(String),
+}
+
+impl StreamValue {
+    fn new(result: ProcedureResult, decode: DecodeFn) -> Self {
+        match result.error {
+            Some(error) => StreamValue::Error(error),
+            None => match decode(&result.value) {
+                Ok(value) => StreamValue::Value(value),
+                Err(e) => StreamValue::Invalid(e.to_string()),
+            },
+        }
+    }
+
+    /// Converts the value back into a `T`, or the error it holds.
+    pub fn get<T: Clone + 'static>(&self) -> Result<T> {
+        match self {
+            StreamValue::Value(value) => value
+                .downcast_ref::<T>()
+                .cloned()
+                .ok_or_else(|| Error::Encoding("stream value has a different type".to_string())),
+            StreamValue::Error(error) => Err(Error::from_procedure_error(error.clone())),
+            StreamValue::Invalid(message) => Err(Error::Encoding(message.clone())),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Updates {
+    count: u64,
+    closed: bool,
+}
+
+/// Counts updates and wakes the threads, and with the `async` feature the
+/// tasks, waiting for the next one.
+pub(crate) struct UpdateSignal {
+    updates: Mutex<Updates>,
+    condition: Condvar,
+    #[cfg(feature = "async")]
+    changes: tokio::sync::watch::Sender<()>,
+}
+
+impl Default for UpdateSignal {
+    fn default() -> Self {
+        UpdateSignal {
+            updates: Mutex::default(),
+            condition: Condvar::new(),
+            #[cfg(feature = "async")]
+            changes: tokio::sync::watch::channel(()).0,
+        }
+    }
+}
+
+impl UpdateSignal {
+    /// The number of updates so far, to pass to `wait_since`.
+    pub fn count(&self) -> u64 {
+        self.updates.lock().unwrap().count
+    }
+
+    /// Blocks until the update after the first `seen` updates, returning
+    /// false if `timeout` elapses first. Returns straight away if that update
+    /// has already happened, and fails once the stream connection is closed.
+    pub fn wait_since(&self, seen: u64, timeout: Option<Duration>) -> Result<bool> {
+        let updates = self.updates.lock().unwrap();
+        let waiting = |updates: &mut Updates| updates.count == seen && !updates.closed;
+        let updates = match timeout {
+            Some(timeout) => {
+                self.condition
+                    .wait_timeout_while(updates, timeout, waiting)
+                    .unwrap()
+                    .0
+            }
+            None => self.condition.wait_while(updates, waiting).unwrap(),
+        };
+        if updates.count != seen {
+            Ok(true)
+        } else if updates.closed {
+            Err(Error::NoStreamConnection)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Blocks until the next update, as `wait_since`.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        self.wait_since(self.count(), timeout)
+    }
+
+    /// Resolves once the update after the first `seen` updates has happened,
+    /// as `wait_since` does, resolving to false if `timeout` elapses first.
+    #[cfg(feature = "async")]
+    pub async fn wait_since_async(&self, seen: u64, timeout: Option<Duration>) -> Result<bool> {
+        match timeout {
+            Some(timeout) => runtime::timeout(timeout, self.changed_since(seen))
+                .await
+                .unwrap_or(Ok(false)),
+            None => self.changed_since(seen).await,
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn changed_since(&self, seen: u64) -> Result<bool> {
+        loop {
+            // Subscribing before checking the count means no change between
+            // the two can be missed.
+            let mut changes = self.changes.subscribe();
+            {
+                let updates = self.updates.lock().unwrap();
+                if updates.count != seen {
+                    return Ok(true);
+                } else if updates.closed {
+                    return Err(Error::NoStreamConnection);
+                }
+            }
+            // Fails only once the sender, owned by `self`, is dropped.
+            let _ = changes.changed().await;
+        }
+    }
+
+    fn notify(&self) {
+        self.updates.lock().unwrap().count += 1;
+        self.condition.notify_all();
+        #[cfg(feature = "async")]
+        self.changes.send_replace(());
+    }
+
+    fn close(&self) {
+        self.updates.lock().unwrap().closed = true;
+        self.condition.notify_all();
+        #[cfg(feature = "async")]
+        self.changes.send_replace(());
+    }
+}
+
+/// A callback run with each value received for a stream.
+pub(crate) type StreamCallback = Mutex<dyn FnMut(&StreamValue) + Send>;
+
+/// A callback run after each `StreamUpdate`.
+pub(crate) type UpdateCallback = Mutex<dyn FnMut() + Send>;
+
+/// A list of callbacks that can be changed while it is being run.
+pub(crate) struct Callbacks<F: ?Sized> {
+    list: Mutex<Vec<(CallbackId, Arc<F>)>>,
+}
+
+impl<F: ?Sized> Default for Callbacks<F> {
+    fn default() -> Self {
+        Callbacks {
+            list: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<F: ?Sized> Callbacks<F> {
+    pub fn add(&self, callback: Arc<F>) -> CallbackId {
+        let id = CallbackId::next();
+        self.list.lock().unwrap().push((id, callback));
+        id
+    }
+
+    pub fn remove(&self, id: CallbackId) -> bool {
+        let mut list = self.list.lock().unwrap();
+        let len = list.len();
+        list.retain(|(other, _)| *other != id);
+        list.len() != len
+    }
+}
+
+impl<F: ?Sized> Callbacks<Mutex<F>> {
+    /// Runs `run` on each callback in the list as it was on entry, so changes
+    /// made by the callbacks themselves take effect from the next run. A
+    /// callback that panics is removed; the others still run.
+    fn run(&self, run: impl Fn(&mut F)) {
+        let list = self.list.lock().unwrap().clone();
+        for (id, callback) in list {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                if let Ok(mut callback) = callback.lock() {
+                    run(&mut callback);
+                }
+            }));
+            if result.is_err() {
+                self.remove(id);
+            }
+        }
+    }
+}
+
+/// The client-side state of a stream, shared between its `Stream` handles and
+/// the receiver thread.
+pub(crate) struct StreamState {
+    pub id: u64,
+    decode: DecodeFn,
+    value: Mutex<Option<StreamValue>>,
+    /// Signalled each time an update for the stream is received.
+    pub signal: UpdateSignal,
+    pub callbacks: Callbacks<StreamCallback>,
+    pub started: AtomicBool,
+    pub rate: Mutex<f32>,
+}
+
+impl StreamState {
+    pub fn value(&self) -> Option<StreamValue> {
+        self.value.lock().unwrap().clone()
+    }
+}
+
+struct Entry {
+    state: Arc<StreamState>,
+    handles: usize,
+}
+
+/// Keeps track of the streams the client has added and stores the most
+/// recent value received for each. Updates for streams that have not been
+/// added are ignored.
+#[derive(Default)]
+pub(crate) struct StreamManager {
+    streams: Mutex<HashMap<u64, Entry>>,
+    /// Signalled after each `StreamUpdate`, whichever streams it touches.
+    pub signal: UpdateSignal,
+    pub callbacks: Callbacks<UpdateCallback>,
+}
+
+impl StreamManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a handle to stream `id`, whose values are decoded with `decode`.
+    /// The server reuses the id of an existing stream when the same call is
+    /// streamed twice, in which case the existing state is shared.
+    pub fn register(&self, id: u64, started: bool, decode: DecodeFn) -> Arc<StreamState> {
+        let mut streams = self.streams.lock().unwrap();
+        let entry = streams.entry(id).or_insert_with(|| {
+            let state = StreamState {
+                id,
+                decode,
+                value: Mutex::new(None),
+                signal: UpdateSignal::default(),
+                callbacks: Callbacks::default(),
+                started: AtomicBool::new(started),
+                rate: Mutex::new(0.0),
+            };
+            if self.signal.updates.lock().unwrap().closed {
+                state.signal.close();
+            }
+            Entry {
+                state: Arc::new(state),
+                handles: 0,
+            }
+        });
+        entry.handles += 1;
+        Arc::clone(&entry.state)
+    }
+
+    /// Drops a handle to stream `id`. Returns true if it was the last one,
+    /// in which case the stream should be removed from the server.
+    pub fn unregister(&self, id: u64) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        match streams.get_mut(&id) {
+            Some(entry) if entry.handles > 1 => {
+                entry.handles -= 1;
+                false
+            }
+            Some(_) => {
+                streams.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Decodes each result in `update` and stores it with its stream, then
+    /// wakes the threads waiting for the update and runs the callbacks of
+    /// each updated stream followed by the client-wide callbacks.
+    ///
+    /// The callbacks run without the manager locked, so they may add and
+    /// remove streams and callbacks.
+    pub fn update(&self, update: StreamUpdate) {
+        let mut updated = Vec::new();
+        {
+            let streams = self.streams.lock().unwrap();
+            for result in update.results {
+                let Some(entry) = streams.get(&result.id) else {
+                    continue;
+                };
+                let state = Arc::clone(&entry.state);
+                let value = StreamValue::new(result.result.unwrap_or_default(), state.decode);
+                *state.value.lock().unwrap() = Some(value.clone());
+                state.signal.notify();
+                updated.push((state, value));
+            }
+        }
+        self.signal.notify();
+        for (state, value) in &updated {
+            state.callbacks.run(|callback| callback(value));
+        }
+        self.callbacks.run(|callback| callback());
+    }
+
+    /// Wakes every waiting thread once no more updates will be received.
+    pub fn close(&self) {
+        let streams = self.streams.lock().unwrap();
+        for entry in streams.values() {
+            entry.state.signal.close();
+        }
+        self.signal.close();
+    }
+}
+
+/// A connection to the stream server, with a background thread that decodes
+/// `StreamUpdate` messages and hands them to a `StreamManager`.
+pub(crate) struct StreamConnection {
+    manager: Arc<StreamManager>,
+    socket: TcpStream,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StreamConnection {
+    /// Connects to the stream server and starts receiving updates for the
+    /// client identified by `client_identifier`.
+    pub fn connect(
+        address: String,
+        port: u16,
+        client_identifier: &ClientIdentifier,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let mut connection = Connection::new(address, port);
+        connection.set_connect_timeout(timeout);
+        connection.connect()?;
+        connection.handshake_stream(client_identifier)?;
+        let socket = connection.try_clone_socket()?;
+        Self::start(Box::new(connection), socket)
+    }
+
+    /// Starts receiving updates over a connection to the stream server that
+    /// is already attached to the client. `socket` is a handle to the socket
+    /// underneath it, used to shut the connection down.
+    pub fn start(transport: Box<dyn Transport>, socket: TcpStream) -> Result<Self> {
+        let manager = Arc::new(StreamManager::new());
+        let thread_manager = Arc::clone(&manager);
+        let thread = std::thread::Builder::new()
+            .name("krpc-stream".to_string())
+            .spawn(move || receive_updates(transport, &thread_manager))?;
+
+        Ok(StreamConnection {
+            manager,
+            socket,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn manager(&self) -> &Arc<StreamManager> {
+        &self.manager
+    }
+
+    /// Shuts the connection down and waits for the receiver thread to exit.
+    pub fn close(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            // The receiver thread exits once its blocking read fails.
+            let _ = self.socket.shutdown(Shutdown::Both);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for StreamConnection {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn receive_updates(mut transport: Box<dyn Transport>, manager: &StreamManager) {
+    while let Ok(update) = transport.receive_message::<StreamUpdate>() {
+        manager.update(update);
+    }
+    manager.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{read_message, write_message, DEFAULT_MAX_MESSAGE_SIZE};
+    use crate::schema::{
+        connection_request, connection_response, ConnectionRequest, ConnectionResponse,
+        StreamResult,
+    };
+    use claim::{assert_matches, assert_ok};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    const IDENTIFIER: ClientIdentifier = *b"0123456789abcdef";
+
+    fn stream_result(id: u64, value: &[u8]) -> StreamResult {
+        StreamResult {
+            id,
+            result: Some(ProcedureResult {
+                value: value.to_vec().into(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn decodes_updates_for_registered_streams() {
+        let manager = StreamManager::new();
+        let state = manager.register(1, true, decode_any::<u32>);
+        manager.update(StreamUpdate {
+            results: vec![stream_result(1, b"\x2a"), stream_result(2, b"b")],
+        });
+        assert_eq!(assert_ok!(state.value().unwrap().get::<u32>()), 42);
+        manager.update(StreamUpdate {
+            results: vec![StreamResult {
+                id: 1,
+                result: Some(ProcedureResult {
+                    error: Some(schema::Error {
+                        service: "KRPC".to_string(),
+                        name: "InvalidOperationException".to_string(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+            }],
+        });
+        assert_matches!(
+            state.value().unwrap().get::<u32>(),
+            Err(Error::InvalidOperation(_))
+        );
+    }
+
+    #[test]
+    fn counts_handles() {
+        let manager = StreamManager::new();
+        let first = manager.register(1, true, decode_any::<u32>);
+        let second = manager.register(1, true, decode_any::<u32>);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!manager.unregister(1));
+        assert!(manager.unregister(1));
+        assert!(!manager.unregister(1));
+    }
+
+    #[test]
+    fn receives_updates_in_background() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (added, wait_for_added) = std::sync::mpsc::channel();
+        let server = std::thread::spawn(move || {
+            let (mut server, _) = listener.accept().unwrap();
+            let request: ConnectionRequest =
+                read_message(&mut server, &mut Vec::new(), DEFAULT_MAX_MESSAGE_SIZE).unwrap();
+            let response = ConnectionResponse {
+                status: connection_response::Status::Ok as i32,
+                client_identifier: IDENTIFIER.to_vec().into(),
+                ..Default::default()
+            };
+            write_message(&mut server, &response, &mut Vec::new()).unwrap();
+            wait_for_added.recv().unwrap();
+            let update = StreamUpdate {
+                results: vec![stream_result(42, b"\x2a")],
+            };
+            write_message(&mut server, &update, &mut Vec::new()).unwrap();
+            (request, server)
+        });
+
+        let mut connection = assert_ok!(StreamConnection::connect(
+            "127.0.0.1".to_string(),
+            port,
+            &IDENTIFIER,
+            None
+        ));
+        let state = connection.manager().register(42, true, decode_any::<u32>);
+        added.send(()).unwrap();
+        let (request, _server) = server.join().unwrap();
+        assert_eq!(request.r#type(), connection_request::Type::Stream);
+        assert_eq!(request.client_identifier.as_ref(), &IDENTIFIER);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while state.value().is_none() {
+            assert!(Instant::now() < deadline, "no stream update received");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(assert_ok!(state.value().unwrap().get::<u32>()), 42);
+        connection.close();
+    }
+}
@@ -0,0 +1,131 @@
+//! A minimal in-process stand-in for the kRPC server, used by unit tests.
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::connection::{read_message, write_message, ClientIdentifier, DEFAULT_MAX_MESSAGE_SIZE};
+use crate::schema::{
+    connection_response, ConnectionRequest, ConnectionResponse, Request, Response, StreamUpdate,
+};
+
+pub(crate) const IDENTIFIER: ClientIdentifier = *b"0123456789abcdef";
+
+pub(crate) struct TestServer {
+    pub rpc_port: u16,
+    pub stream_port: u16,
+    updates: Sender<StreamUpdate>,
+    rpc_thread: Option<JoinHandle<ConnectionRequest>>,
+}
+
+impl TestServer {
+    /// Starts a server that answers each `Request` with `handler` and
+    /// forwards updates passed to `send_update` to the stream connection.
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: FnMut(Request) -> Response + Send + 'static,
+    {
+        let rpc_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let rpc_port = rpc_listener.local_addr().unwrap().port();
+        let stream_port = stream_listener.local_addr().unwrap().port();
+        let (updates, receiver) = channel();
+        let rpc_thread = std::thread::spawn(move || serve_rpc(rpc_listener, handler));
+        std::thread::spawn(move || serve_stream(stream_listener, receiver));
+        TestServer {
+            rpc_port,
+            stream_port,
+            updates,
+            rpc_thread: Some(rpc_thread),
+        }
+    }
+
+    /// Starts a server that accepts `connections` RPC connections, and
+    /// answers each `Request` with `handler`, passed the index of the
+    /// connection it arrived on in the order they were accepted.
+    pub fn start_pool<F>(connections: usize, handler: F) -> Self
+    where
+        F: Fn(usize, Request) -> Response + Send + Sync + 'static,
+    {
+        let rpc_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let rpc_port = rpc_listener.local_addr().unwrap().port();
+        let stream_port = stream_listener.local_addr().unwrap().port();
+        let (updates, receiver) = channel();
+        let handler = Arc::new(handler);
+        let rpc_thread = std::thread::spawn(move || {
+            let threads: Vec<_> = (0..connections)
+                .map(|index| {
+                    let (socket, request) = accept(&rpc_listener);
+                    let handler = Arc::clone(&handler);
+                    std::thread::spawn(move || {
+                        serve_requests(socket, |request| handler(index, request));
+                        request
+                    })
+                })
+                .collect();
+            let requests: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+            requests.into_iter().next().unwrap()
+        });
+        std::thread::spawn(move || serve_stream(stream_listener, receiver));
+        TestServer {
+            rpc_port,
+            stream_port,
+            updates,
+            rpc_thread: Some(rpc_thread),
+        }
+    }
+
+    pub fn send_update(&self, update: StreamUpdate) {
+        self.updates.send(update).unwrap();
+    }
+
+    /// Waits for the RPC client to disconnect and returns its connection
+    /// request.
+    pub fn join(mut self) -> ConnectionRequest {
+        self.rpc_thread.take().unwrap().join().unwrap()
+    }
+}
+
+fn accept(listener: &TcpListener) -> (TcpStream, ConnectionRequest) {
+    let (mut socket, _) = listener.accept().unwrap();
+    let request = read_message(&mut socket, &mut Vec::new(), DEFAULT_MAX_MESSAGE_SIZE).unwrap();
+    let response = ConnectionResponse {
+        status: connection_response::Status::Ok as i32,
+        client_identifier: IDENTIFIER.to_vec().into(),
+        ..Default::default()
+    };
+    write_message(&mut socket, &response, &mut Vec::new()).unwrap();
+    (socket, request)
+}
+
+fn serve_rpc<F>(listener: TcpListener, handler: F) -> ConnectionRequest
+where
+    F: FnMut(Request) -> Response,
+{
+    let (socket, connection_request) = accept(&listener);
+    serve_requests(socket, handler);
+    connection_request
+}
+
+fn serve_requests<F>(mut socket: TcpStream, mut handler: F)
+where
+    F: FnMut(Request) -> Response,
+{
+    while let Ok(request) = read_message(&mut socket, &mut Vec::new(), DEFAULT_MAX_MESSAGE_SIZE) {
+        let response = handler(request);
+        if write_message(&mut socket, &response, &mut Vec::new()).is_err() {
+            break;
+        }
+    }
+}
+
+fn serve_stream(listener: TcpListener, updates: Receiver<StreamUpdate>) {
+    let (mut socket, _) = accept(&listener);
+    for update in updates {
+        if write_message(&mut socket, &update, &mut Vec::new()).is_err() {
+            break;
+        }
+    }
+}
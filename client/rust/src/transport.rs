@@ -0,0 +1,39 @@
+use prost::Message;
+
+use crate::error::Result;
+
+/// Carries whole protobuf messages to and from the server, however they are
+/// framed on the wire.
+pub(crate) trait Transport: Send {
+    /// Sends one encoded message.
+    fn send(&mut self, message: &[u8]) -> Result<()>;
+
+    /// Receives one encoded message, blocking until all of it has arrived.
+    fn receive(&mut self) -> Result<&[u8]>;
+
+    /// Closes the connection. Later sends and receives fail.
+    fn close(&mut self) -> Result<()>;
+
+    /// The buffer that messages are encoded into before they are sent, kept
+    /// by the transport so that sending does not allocate.
+    fn send_buffer(&mut self) -> &mut Vec<u8>;
+}
+
+impl dyn Transport + '_ {
+    pub fn send_message<M: Message>(&mut self, message: &M) -> Result<()> {
+        // The buffer is taken while the message is sent from it, and put back
+        // after so that the next message reuses its capacity.
+        let mut buffer = std::mem::take(self.send_buffer());
+        buffer.clear();
+        message
+            .encode(&mut buffer)
+            .expect("Vec<u8> grows to fit the message");
+        let result = self.send(&buffer);
+        *self.send_buffer() = buffer;
+        result
+    }
+
+    pub fn receive_message<M: Message + Default>(&mut self) -> Result<M> {
+        Ok(M::decode(self.receive()?)?)
+    }
+}
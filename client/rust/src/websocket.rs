@@ -0,0 +1,297 @@
+//! The WebSocket transport, enabled by the `websocket` feature.
+//!
+//! The server accepts WebSocket connections on its RPC and stream ports, as
+//! described in the server's WebSockets protocol documentation. Each message
+//! is sent as a single binary frame rather than with a length prefix, and
+//! there is no `ConnectionRequest` handshake: the client's name is passed in
+//! the `name` query parameter of the RPC URI, and the stream connection is
+//! tied to the client by its identifier, base64-encoded in the `id` query
+//! parameter of the stream URI.
+//!
+//! On wasm32 targets the sockets are the host's, and are the transport of
+//! [`AsyncClient`](crate::AsyncClient) instead.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::TcpStream;
+
+use base64::Engine;
+#[cfg(not(target_arch = "wasm32"))]
+use bytes::Bytes;
+#[cfg(not(target_arch = "wasm32"))]
+use tungstenite::handshake::HandshakeError;
+#[cfg(not(target_arch = "wasm32"))]
+use tungstenite::{Message, WebSocket};
+
+use crate::call::Call;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connection::resolve;
+use crate::connection::ClientIdentifier;
+use crate::error::{Error, Result};
+use crate::schema::Request;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transport::Transport;
+
+/// The URI of the RPC server, which identifies the client as `name`.
+pub(crate) fn rpc_uri(address: &str, port: u16, name: &str) -> String {
+    format!("{}/?name={}", base_uri(address, port), escape(name))
+}
+
+/// The URI of the stream server, for the client identified by
+/// `client_identifier`.
+pub(crate) fn stream_uri(address: &str, port: u16, client_identifier: &ClientIdentifier) -> String {
+    // The server does not unescape the id, but the base64 alphabet is valid
+    // in a query string as it is.
+    let id = base64::engine::general_purpose::STANDARD.encode(client_identifier);
+    format!("{}/?id={}", base_uri(address, port), id)
+}
+
+/// There is no handshake over WebSockets, so the identifier the stream
+/// server needs is asked for with `KRPC.GetClientID` instead.
+pub(crate) fn client_id_request() -> Request {
+    Request {
+        calls: vec![Call::<bytes::Bytes>::new("KRPC", "GetClientID").into_message()],
+    }
+}
+
+/// Decodes the result of `KRPC.GetClientID`.
+pub(crate) fn decode_client_identifier(value: &[u8]) -> Result<ClientIdentifier> {
+    let id: bytes::Bytes = crate::codec::decode(value)?;
+    ClientIdentifier::try_from(id.as_ref()).map_err(|_| {
+        Error::InvalidResponse(format!(
+            "client identifier is {} bytes, expected 16",
+            id.len()
+        ))
+    })
+}
+
+/// A WebSocket connection to the RPC or stream server.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct WebSocketConnection {
+    socket: WebSocket<TcpStream>,
+    received: Bytes,
+    send_buffer: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl WebSocketConnection {
+    /// Connects to the RPC server, identifying the client as `name`.
+    pub fn connect_rpc(address: &str, port: u16, name: &str) -> Result<Self> {
+        Self::connect(address, port, &rpc_uri(address, port, name))
+    }
+
+    /// Connects to the stream server on behalf of the client identified by
+    /// `client_identifier`.
+    pub fn connect_stream(
+        address: &str,
+        port: u16,
+        client_identifier: &ClientIdentifier,
+    ) -> Result<Self> {
+        Self::connect(address, port, &stream_uri(address, port, client_identifier))
+    }
+
+    fn connect(address: &str, port: u16, uri: &str) -> Result<Self> {
+        let stream = TcpStream::connect(&resolve(address, port)?[..])?;
+        stream.set_nodelay(true)?;
+        let (socket, _) = tungstenite::client(uri, stream).map_err(|e| match e {
+            HandshakeError::Failure(e) => from_websocket_error(e),
+            HandshakeError::Interrupted(_) => unreachable!("the socket is blocking"),
+        })?;
+        Ok(WebSocketConnection {
+            socket,
+            received: Bytes::new(),
+            send_buffer: Vec::new(),
+        })
+    }
+
+    /// Returns a second handle to the underlying socket, which can be used
+    /// to shut the connection down from another thread.
+    pub fn try_clone_socket(&self) -> Result<TcpStream> {
+        Ok(self.socket.get_ref().try_clone()?)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for WebSocketConnection {
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        self.socket
+            .send(Message::Binary(Bytes::copy_from_slice(message)))
+            .map_err(from_websocket_error)
+    }
+
+    fn receive(&mut self) -> Result<&[u8]> {
+        loop {
+            // Pings are answered by the socket as it reads.
+            match self.socket.read().map_err(from_websocket_error)? {
+                Message::Binary(data) => {
+                    self.received = data;
+                    return Ok(&self.received);
+                }
+                Message::Text(_) => {
+                    return Err(Error::WebSocket(
+                        "received a text message, expected binary".to_string(),
+                    ))
+                }
+                Message::Close(_) => return Err(Error::NotConnected),
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        // The server may already have gone, so failing to say goodbye is not
+        // an error.
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+        match self.socket.get_ref().shutdown(std::net::Shutdown::Both) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotConnected => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn send_buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.send_buffer
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn from_websocket_error(error: tungstenite::Error) -> Error {
+    match error {
+        tungstenite::Error::Io(e) => Error::Io(e),
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            Error::NotConnected
+        }
+        e => Error::WebSocket(e.to_string()),
+    }
+}
+
+fn base_uri(address: &str, port: u16) -> String {
+    if address.contains(':') {
+        format!("ws://[{}]:{}", address, port)
+    } else {
+        format!("ws://{}:{}", address, port)
+    }
+}
+
+/// Percent-encodes everything but the characters unreserved in URIs.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::codec;
+    use crate::schema::{ProcedureResult, Request, Response, StreamUpdate};
+    use crate::test_server::IDENTIFIER;
+    use claim::{assert_matches, assert_ok};
+    use prost::Message as _;
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::time::Duration;
+    use tungstenite::handshake::server;
+
+    /// Accepts a WebSocket connection, sending its request URI to `uris`.
+    #[allow(clippy::result_large_err)] // The callback's type is set by tungstenite.
+    fn accept(listener: &TcpListener, uris: &Sender<String>) -> WebSocket<TcpStream> {
+        let (stream, _) = listener.accept().unwrap();
+        let uris = uris.clone();
+        tungstenite::accept_hdr(stream, move |request: &server::Request, response| {
+            uris.send(request.uri().to_string()).unwrap();
+            Ok(response)
+        })
+        .unwrap()
+    }
+
+    /// Starts a server that answers `KRPC.GetClientID`, and anything else
+    /// with its first argument, and sends a stream update whenever one is
+    /// sent to the returned channel. Returns the ports and the URIs the
+    /// client connected with.
+    fn start_server() -> (u16, u16, Sender<StreamUpdate>, Receiver<String>) {
+        let rpc_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let rpc_port = rpc_listener.local_addr().unwrap().port();
+        let stream_port = stream_listener.local_addr().unwrap().port();
+        let (uris, received_uris) = channel();
+        let (updates, received_updates) = channel::<StreamUpdate>();
+        let stream_uris = uris.clone();
+        std::thread::spawn(move || {
+            let mut socket = accept(&rpc_listener, &uris);
+            while let Ok(Message::Binary(data)) = socket.read() {
+                let call = &Request::decode(data).unwrap().calls[0];
+                let value = match call.procedure.as_str() {
+                    "GetClientID" => codec::encode(&Bytes::from_static(&IDENTIFIER)),
+                    _ => call.arguments[0].value.clone(),
+                };
+                let response = Response {
+                    results: vec![ProcedureResult {
+                        value,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                };
+                let message = Message::Binary(response.encode_to_vec().into());
+                if socket.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        std::thread::spawn(move || {
+            let mut socket = accept(&stream_listener, &stream_uris);
+            for update in received_updates {
+                let message = Message::Binary(update.encode_to_vec().into());
+                if socket.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        (rpc_port, stream_port, updates, received_uris)
+    }
+
+    #[test]
+    fn connects_and_calls() {
+        let (rpc_port, stream_port, updates, uris) = start_server();
+        let client = assert_ok!(Client::connect_websocket(
+            "Jeb Kerman",
+            "127.0.0.1",
+            rpc_port,
+            Some(stream_port)
+        ));
+        assert_eq!(assert_ok!(uris.recv()), "/?name=Jeb%20Kerman");
+        assert_eq!(assert_ok!(uris.recv()), "/?id=MDEyMzQ1Njc4OWFiY2RlZg==");
+        assert_eq!(client.client_identifier(), &IDENTIFIER);
+
+        let value = assert_ok!(client.invoke_typed::<String>("KRPC", "Echo", &[&"Kerbin"]));
+        assert_eq!(value, "Kerbin");
+
+        let (received, receive) = channel();
+        assert_ok!(client.add_stream_update_callback(move || received.send(()).unwrap()));
+        updates.send(StreamUpdate::default()).unwrap();
+        assert_ok!(receive.recv_timeout(Duration::from_secs(5)));
+        assert_ok!(client.close());
+    }
+
+    #[test]
+    fn unresolvable_address() {
+        assert_matches!(
+            Client::connect_websocket("Jeb", "no-such-host.invalid", 50000, None).err(),
+            Some(Error::AddressResolution { .. })
+        );
+    }
+
+    #[test]
+    fn escapes_names() {
+        assert_eq!(escape("Jeb_1.-~"), "Jeb_1.-~");
+        assert_eq!(escape("a&b=c d/é"), "a%26b%3Dc%20d%2F%C3%A9");
+        assert_eq!(base_uri("::1", 50000), "ws://[::1]:50000");
+    }
+}
@@ -0,0 +1,126 @@
+//! Tests of the async client on wasm32, in a browser, against the server in
+//! tools/TestServer started with `--type=websockets`. Its ports are taken
+//! from `RPC_PORT` and `STREAM_PORT` when the tests are built:
+//!
+//! ```text
+//! RPC_PORT=50000 STREAM_PORT=50001 cargo test --target wasm32-unknown-unknown \
+//!     --features async,websocket --test wasm
+//! ```
+//!
+//! with `wasm-bindgen-test-runner` as the target's runner.
+
+#![cfg(all(target_arch = "wasm32", feature = "async", feature = "websocket"))]
+
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use claim::{assert_matches, assert_ok};
+use krpc::{AsyncClient, Call, Error};
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+fn port(value: Option<&str>, default: u16) -> u16 {
+    value.map_or(default, |port| port.parse().unwrap())
+}
+
+async fn connect(name: &str) -> AsyncClient {
+    let rpc_port = port(option_env!("RPC_PORT"), 50000);
+    let stream_port = port(option_env!("STREAM_PORT"), 50001);
+    assert_ok!(AsyncClient::connect(name, "127.0.0.1", rpc_port, Some(stream_port)).await)
+}
+
+#[wasm_bindgen_test]
+async fn connect_and_close() {
+    let mut client = connect("WasmClientName").await;
+    assert_ne!(client.client_identifier(), &[0; 16]);
+    let name = assert_ok!(
+        client
+            .call(&Call::<String>::new("KRPC", "GetClientName"))
+            .await
+    );
+    assert_eq!(name, "WasmClientName");
+    assert_ok!(client.close().await);
+    assert_matches!(
+        client
+            .call(&Call::<String>::new("KRPC", "GetClientName"))
+            .await,
+        Err(Error::NotConnected)
+    );
+}
+
+#[wasm_bindgen_test]
+async fn call_procedures() {
+    let client = connect("WasmCall").await;
+    let status = assert_ok!(
+        client
+            .call(&Call::<krpc::schema::Status>::new("KRPC", "GetStatus"))
+            .await
+    );
+    assert_ne!(status.version, "");
+
+    let float_to_string =
+        |value: f32| Call::<String>::new("TestService", "FloatToString").arg(&value);
+    let (first, second) = join(
+        client.call(&float_to_string(2.5)),
+        client.call(&float_to_string(-1.5)),
+    )
+    .await;
+    assert_eq!(assert_ok!(first), "2.5");
+    assert_eq!(assert_ok!(second), "-1.5");
+
+    let mut batch = client.batch();
+    let positive = batch.add(float_to_string(42.0));
+    let negative = batch.add(float_to_string(-42.0));
+    let results = assert_ok!(batch.send().await);
+    assert_eq!(assert_ok!(results.get(&positive)), "42");
+    assert_eq!(assert_ok!(results.get(&negative)), "-42");
+}
+
+#[wasm_bindgen_test]
+async fn stream_updates() {
+    let client = connect("WasmStream").await;
+    let counter = Call::<i32>::new("TestService", "Counter")
+        .arg(&"WasmStream.stream_updates")
+        .arg(&1);
+    let stream = assert_ok!(client.add_stream(&counter).await);
+    let mut count = -1;
+    for _ in 0..5 {
+        count = loop {
+            assert!(assert_ok!(stream.wait(Some(Duration::from_secs(5))).await));
+            match stream.get() {
+                Ok(value) if value > count => break value,
+                _ => {}
+            }
+        };
+    }
+    assert_ok!(stream.remove().await);
+}
+
+/// Runs two futures at once, as there is no runtime here to spawn them on.
+async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (pin!(a), pin!(b));
+    let (mut a_output, mut b_output) = (None, None);
+    poll_fn(|cx| {
+        if a_output.is_none() {
+            if let Poll::Ready(output) = a.as_mut().poll(cx) {
+                a_output = Some(output);
+            }
+        }
+        if b_output.is_none() {
+            if let Poll::Ready(output) = b.as_mut().poll(cx) {
+                b_output = Some(output);
+            }
+        }
+        match (a_output.take(), b_output.take()) {
+            (Some(a), Some(b)) => Poll::Ready((a, b)),
+            (a, b) => {
+                (a_output, b_output) = (a, b);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
@@ -48,3 +48,30 @@ def java_binary_multiple(name, srcs, deps):
             deps = deps
         )
     native.filegroup(name=name, srcs=names)
+
+def rust_binary_multiple(name, srcs, crate):
+    """ Builds each script as an example of the crate in the release archive.
+        A script's `mod services` is the crate's generated service bindings. """
+    names = []
+    for src in srcs:
+        subname = name + '/' + src
+        names.append(subname)
+        native.genrule(
+            name = subname,
+            srcs = [src, crate],
+            outs = [subname + '.bin'],
+            cmd = ' && '.join([
+                'CRATE=$$(mktemp -d)',
+                'unzip -q $(location %s) -d $$CRATE' % crate,
+                'CRATE=$$(echo $$CRATE/*)',
+                'mkdir -p $$CRATE/examples/script',
+                'cp $(location %s) $$CRATE/examples/script/main.rs' % src,
+                'echo "pub use krpc::services::*;" > $$CRATE/examples/script/services.rs',
+                'cargo build --quiet --manifest-path $$CRATE/Cargo.toml --features services --example script',
+                'cp $$CRATE/target/debug/examples/script $@',
+                'rm -rf $$(dirname $$CRATE)'
+            ]),
+            local = 1,
+            tags = ['requires-network']
+        )
+    native.filegroup(name=name, srcs=names)
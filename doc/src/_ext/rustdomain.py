@@ -0,0 +1,197 @@
+"""
+A Sphinx domain for the Rust client's API reference.
+
+Items are documented with the directives ``module``, ``currentmodule``,
+``struct``, ``enum``, ``variant``, ``error`` and ``function``, and referred to
+with the roles ``mod``, ``struct``, ``enum``, ``variant``, ``error`` and
+``fn``. Paths are separated by ``::`` and are looked up relative to the
+current module and item, then from the root.
+"""
+
+import re
+from docutils import nodes
+from sphinx import addnodes
+from sphinx.directives import ObjectDescription
+from sphinx.domains import Domain, ObjType
+from sphinx.roles import XRefRole
+from sphinx.util.docutils import SphinxDirective
+from sphinx.util.nodes import make_refnode
+
+_signature_re = re.compile(r'^\s*(?:fn\s+)?((?:r#)?\w+)(.*)$')
+
+
+def _anchor(fullname):
+    return 'rust.' + fullname.replace('::', '.')
+
+
+class RustObject(ObjectDescription):
+    """ An item named by the first identifier in its signature. Structs, enums
+        and errors contain the items documented in their content. """
+
+    keyword = None
+    container = False
+
+    def handle_signature(self, sig, signode):
+        match = _signature_re.match(sig)
+        if match is None:
+            raise ValueError
+        name, rest = match.groups()
+        if self.keyword:
+            signode += addnodes.desc_annotation(
+                self.keyword + ' ', self.keyword + ' ')
+        signode += addnodes.desc_name(name, name)
+        if rest:
+            signode += nodes.Text(rest)
+        path = [self.env.ref_context.get('rust:module')]
+        path.extend(self.env.ref_context.get('rust:parents', []))
+        path.append(name[2:] if name.startswith('r#') else name)
+        return '::'.join(x for x in path if x)
+
+    def add_target_and_index(self, name, sig, signode):
+        anchor = _anchor(name)
+        if anchor not in self.state.document.ids:
+            signode['names'].append(name)
+            signode['ids'].append(anchor)
+            signode['first'] = not self.names
+            self.state.document.note_explicit_target(signode)
+            objects = self.env.domaindata['rust']['objects']
+            objects[name] = (self.env.docname, self.objtype)
+        self.indexnode['entries'].append(
+            ('single', '%s (Rust %s)' % (name, self.objtype),
+             anchor, '', None))
+
+    def before_content(self):
+        if self.container and self.names:
+            parents = self.env.ref_context.setdefault('rust:parents', [])
+            parents.append(self.names[-1].split('::')[-1])
+
+    def after_content(self):
+        if self.container and self.names:
+            self.env.ref_context['rust:parents'].pop()
+
+
+class RustFunction(RustObject):
+    keyword = 'fn'
+
+
+class RustStruct(RustObject):
+    keyword = 'struct'
+    container = True
+
+
+class RustEnum(RustObject):
+    keyword = 'enum'
+    container = True
+
+
+class RustError(RustObject):
+    keyword = 'error'
+    container = True
+
+
+class RustVariant(RustObject):
+    pass
+
+
+class RustModule(SphinxDirective):
+    required_arguments = 1
+    has_content = False
+
+    def run(self):
+        name = self.arguments[0].strip()
+        self.env.ref_context['rust:module'] = name
+        self.env.ref_context['rust:parents'] = []
+        self.env.domaindata['rust']['objects'][name] = \
+            (self.env.docname, 'module')
+        target = nodes.target('', '', ids=[_anchor(name)], ismod=True)
+        self.state.document.note_explicit_target(target)
+        return [target]
+
+
+class RustCurrentModule(SphinxDirective):
+    required_arguments = 1
+    has_content = False
+
+    def run(self):
+        self.env.ref_context['rust:module'] = self.arguments[0].strip()
+        self.env.ref_context['rust:parents'] = []
+        return []
+
+
+class RustXRefRole(XRefRole):
+
+    def process_link(self, env, refnode, has_explicit_title, title, target):
+        refnode['rust:module'] = env.ref_context.get('rust:module')
+        refnode['rust:parents'] = list(env.ref_context.get('rust:parents', []))
+        return title, target
+
+
+class RustDomain(Domain):
+    name = 'rust'
+    label = 'Rust'
+    object_types = {
+        'module': ObjType('module', 'mod'),
+        'struct': ObjType('struct', 'struct'),
+        'enum': ObjType('enum', 'enum'),
+        'variant': ObjType('variant', 'variant'),
+        'error': ObjType('error', 'error'),
+        'function': ObjType('function', 'fn')
+    }
+    directives = {
+        'module': RustModule,
+        'currentmodule': RustCurrentModule,
+        'struct': RustStruct,
+        'enum': RustEnum,
+        'variant': RustVariant,
+        'error': RustError,
+        'function': RustFunction
+    }
+    roles = {
+        'mod': RustXRefRole(),
+        'struct': RustXRefRole(),
+        'enum': RustXRefRole(),
+        'variant': RustXRefRole(),
+        'error': RustXRefRole(),
+        'fn': RustXRefRole(fix_parens=True)
+    }
+    initial_data = {
+        'objects': {}
+    }
+
+    def clear_doc(self, docname):
+        objects = self.data['objects']
+        for name, (objdocname, _) in list(objects.items()):
+            if objdocname == docname:
+                del objects[name]
+
+    def merge_domaindata(self, docnames, otherdata):
+        for name, (docname, objtype) in otherdata['objects'].items():
+            if docname in docnames:
+                self.data['objects'][name] = (docname, objtype)
+
+    def resolve_xref(self, env, fromdocname, builder,
+                     typ, target, node, contnode):
+        objects = self.data['objects']
+        target = target.rstrip('()')
+        scope = [node.get('rust:module')] + node.get('rust:parents', [])
+        scope = [x for x in scope if x]
+        for i in range(len(scope), -1, -1):
+            name = '::'.join(scope[:i] + [target])
+            if name in objects:
+                docname, _ = objects[name]
+                return make_refnode(builder, fromdocname, docname,
+                                    _anchor(name), contnode, name)
+        return None
+
+    def resolve_any_xref(self, env, fromdocname, builder,
+                         target, node, contnode):
+        return []
+
+    def get_objects(self):
+        for name, (docname, objtype) in self.data['objects'].items():
+            yield (name, name, objtype, docname, _anchor(name), 1)
+
+
+def setup(app):
+    app.add_domain(RustDomain)
+    return {'version': '1.0', 'parallel_read_safe': True}
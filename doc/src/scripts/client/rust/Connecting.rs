@@ -0,0 +1,15 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT, DEFAULT_STREAM_PORT};
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect(
+        "My Example Program",
+        "192.168.1.10",
+        DEFAULT_RPC_PORT,
+        Some(DEFAULT_STREAM_PORT),
+    )?;
+    let status = client.call(&services::krpc::get_status())?;
+    println!("{}", status.version);
+    Ok(())
+}
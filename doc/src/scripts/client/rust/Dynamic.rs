@@ -0,0 +1,14 @@
+use krpc::dynamic::{DynamicClient, Value};
+use krpc::DEFAULT_RPC_PORT;
+
+fn main() -> krpc::Result<()> {
+    let client = DynamicClient::connect("Dynamic", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let space_center = client.service("SpaceCenter")?;
+    let vessel = space_center.property("ActiveVessel")?;
+    let vessel_class = space_center.class("Vessel")?;
+    if let Value::String(name) = vessel_class.property(&vessel, "Name")? {
+        println!("{}", name);
+    }
+    vessel_class.set_property(&vessel, "Name", "My Vessel".into())?;
+    Ok(())
+}
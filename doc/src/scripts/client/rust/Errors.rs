@@ -0,0 +1,16 @@
+mod services;
+
+use krpc::{Client, Error, DEFAULT_RPC_PORT};
+use services::space_center;
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("Errors", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let vessel = client.call(&space_center::active_vessel())?;
+    let control = client.call(&vessel.control())?;
+    match client.call(&control.activate_next_stage()) {
+        Ok(vessels) => println!("{} vessels after staging", vessels.len()),
+        Err(Error::InvalidOperation(error)) => println!("Cannot stage: {}", error.description),
+        Err(error) => return Err(error),
+    }
+    Ok(())
+}
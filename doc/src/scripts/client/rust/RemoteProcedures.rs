@@ -0,0 +1,13 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT};
+use services::space_center;
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("Remote procedures", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let vessel = client.call(&space_center::active_vessel())?;
+    client.call(&vessel.set_name("My Vessel"))?;
+    let flight_info = client.call(&vessel.flight(None))?;
+    println!("{}", client.call(&flight_info.mean_altitude())?);
+    Ok(())
+}
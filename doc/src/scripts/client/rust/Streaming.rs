@@ -0,0 +1,22 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT, DEFAULT_STREAM_PORT};
+use services::space_center;
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect(
+        "Streaming",
+        "127.0.0.1",
+        DEFAULT_RPC_PORT,
+        Some(DEFAULT_STREAM_PORT),
+    )?;
+    let vessel = client.call(&space_center::active_vessel())?;
+    let orbit = client.call(&vessel.orbit())?;
+    let body = client.call(&orbit.body())?;
+    let frame = client.call(&body.reference_frame())?;
+    let position = client.add_stream(&vessel.position(frame))?;
+    loop {
+        position.wait(None)?;
+        println!("{:?}", position.get()?);
+    }
+}
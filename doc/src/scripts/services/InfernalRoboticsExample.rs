@@ -0,0 +1,29 @@
+mod services;
+
+use std::thread;
+use std::time::Duration;
+
+use krpc::{Client, DEFAULT_RPC_PORT};
+use services::{infernal_robotics, space_center};
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("InfernalRobotics Example", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let vessel = client.call(&space_center::active_vessel())?;
+
+    let group = match client.call(&infernal_robotics::servo_group_with_name(vessel, "MyGroup"))? {
+        Some(group) => group,
+        None => {
+            println!("Group not found");
+            return Ok(());
+        }
+    };
+
+    for servo in client.call(&group.servos())? {
+        println!("{} {}", client.call(&servo.name())?, client.call(&servo.position())?);
+    }
+
+    client.call(&group.move_right())?;
+    thread::sleep(Duration::from_secs(1));
+    client.call(&group.stop())?;
+    Ok(())
+}
@@ -0,0 +1,16 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT};
+use services::kerbal_alarm_clock::{self, AlarmAction, AlarmType};
+use services::space_center;
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("Kerbal Alarm Clock Example", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+
+    let ut = client.call(&space_center::ut())?;
+    let alarm = client.call(&kerbal_alarm_clock::create_alarm(AlarmType::Raw, "My New Alarm", ut + 10.0))?;
+
+    client.call(&alarm.set_notes("10 seconds have now passed since the alarm was created."))?;
+    client.call(&alarm.set_action(AlarmAction::MessageOnly))?;
+    Ok(())
+}
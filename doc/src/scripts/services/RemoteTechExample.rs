@@ -0,0 +1,21 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT};
+use services::{remote_tech, space_center};
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("RemoteTech Example", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let vessel = client.call(&space_center::active_vessel())?;
+
+    // Set a dish target
+    let parts = client.call(&vessel.parts())?;
+    let part = client.call(&parts.with_title("Reflectron KR-7"))?[0];
+    let antenna = client.call(&remote_tech::antenna(part))?;
+    let bodies = client.call(&space_center::bodies())?;
+    client.call(&antenna.set_target_body(bodies["Jool"]))?;
+
+    // Get info about the vessels communications
+    let comms = client.call(&remote_tech::comms(vessel))?;
+    println!("Signal delay = {:.4} seconds", client.call(&comms.signal_delay())?);
+    Ok(())
+}
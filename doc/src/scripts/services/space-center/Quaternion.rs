@@ -0,0 +1,13 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT};
+use services::space_center;
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let vessel = client.call(&space_center::active_vessel())?;
+    let flight = client.call(&vessel.flight(None))?;
+    let (x, y, z, w) = client.call(&flight.rotation())?;
+    println!("{} {} {} {}", x, y, z, w);
+    Ok(())
+}
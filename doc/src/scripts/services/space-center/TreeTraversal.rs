@@ -0,0 +1,19 @@
+mod services;
+
+use krpc::{Client, DEFAULT_RPC_PORT};
+use services::space_center;
+
+fn main() -> krpc::Result<()> {
+    let client = Client::connect("", "127.0.0.1", DEFAULT_RPC_PORT, None)?;
+    let vessel = client.call(&space_center::active_vessel())?;
+
+    let root = client.call(&client.call(&vessel.parts())?.root())?;
+    let mut stack = vec![(root, 0)];
+    while let Some((part, depth)) = stack.pop() {
+        println!("{}{}", " ".repeat(depth), client.call(&part.title())?);
+        for child in client.call(&part.children())? {
+            stack.push((child, depth + 1));
+        }
+    }
+    Ok(())
+}
@@ -70,3 +70,12 @@ def clientgen_python(name, service, defs, out):
         out = out,
         language = 'python'
     )
+
+def clientgen_rust(name, service, defs, out):
+    clientgen(
+        name = name,
+        service = service,
+        defs = defs,
+        out = out,
+        language = 'rust'
+    )
@@ -11,6 +11,7 @@ from .cpp import CppGenerator
 from .java import JavaGenerator
 from .cnano import CnanoGenerator
 from .python import PythonGenerator
+from .rust import RustGenerator
 from ..version import __version__
 from ..servicedefs import servicedefs
 
@@ -19,7 +20,8 @@ GENERATORS = {
     'cpp': CppGenerator,
     'java': JavaGenerator,
     'cnano': CnanoGenerator,
-    'python': PythonGenerator
+    'python': PythonGenerator,
+    'rust': RustGenerator
 }
 
 
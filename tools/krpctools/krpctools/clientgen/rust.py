@@ -0,0 +1,218 @@
+import re
+from krpc.attributes import Attributes
+from krpc.types import ClassType, EnumerationType
+from krpc.utils import snake_case
+from .generator import Generator
+from .docparser import DocParser
+from ..lang.rust import RustLanguage
+from ..utils import as_type, decode_default_value
+
+
+class RustGenerator(Generator):
+
+    language = RustLanguage()
+
+    def __init__(self, macro_template, service, definitions):
+        super(RustGenerator, self).__init__(
+            macro_template, service, definitions)
+        self.language.module = service
+
+    def parse_parameter_type(self, typ):
+        return self.language.parse_parameter_type(typ)
+
+    def parse_default_value(self, value, typ):
+        if isinstance(typ, EnumerationType):
+            if typ.protobuf_type.service != self.service_name:
+                return None
+            enumeration = self._defs['enumerations'][typ.protobuf_type.name]
+            for x in enumeration['values']:
+                if x['value'] == value:
+                    return '%s::%s' % (typ.protobuf_type.name, x['name'])
+            return None
+        return self.language.parse_default_value(value, typ)
+
+    @staticmethod
+    def parse_documentation(documentation):
+        return _rustdoc(RustDocParser().parse(documentation), '///')
+
+    def parse_function_name(self, name):
+        if Attributes.is_a_property_getter(name):
+            return self.parse_name(Attributes.get_property_name(name))
+        elif Attributes.is_a_property_setter(name):
+            return self.language.escape(
+                'set_' + snake_case(Attributes.get_property_name(name)))
+        elif Attributes.is_a_class_property_getter(name):
+            return self.parse_name(Attributes.get_class_member_name(name))
+        elif Attributes.is_a_class_property_setter(name):
+            return self.language.escape(
+                'set_' + snake_case(Attributes.get_class_member_name(name)))
+        elif Attributes.is_a_class_member(name):
+            return self.parse_name(Attributes.get_class_member_name(name))
+        return self.parse_name(name)
+
+    def parse_default_documentation(self, name, default_value, typ):
+        literal = self.parse_default_value(
+            decode_default_value(default_value, typ), typ)
+        if literal is None:
+            return '`%s` takes the server\'s default value when `None`.' % name
+        return '`%s` defaults to `%s` when `None`.' % (name, literal)
+
+    def generate_context_function(self, name, procedure):
+        """ Context for the function that builds a call to a procedure.
+            Instance members take the object they are called on as &self """
+        receiver = Attributes.is_a_class_method(name) or \
+            Attributes.is_a_class_property_accessor(name)
+        parameters = []
+        defaults = []
+        for position, parameter in enumerate(procedure['parameters']):
+            if receiver and position == 0:
+                continue
+            typ = as_type(self.types, parameter['type'])
+            param_type = self.parse_parameter_type(typ)
+            param_name = self.parse_name(parameter['name'])
+            info = {
+                'name': param_name,
+                'position': position,
+                'type': param_type,
+                'value': param_name if param_type.startswith('&')
+                         else '&' + param_name,
+                'optional': 'default_value' in parameter
+            }
+            if info['optional']:
+                defaults.append(self.parse_default_documentation(
+                    param_name, parameter['default_value'], typ))
+            parameters.append(info)
+
+        return_type = self.get_return_type(procedure)
+        return_type_name = self.parse_return_type(return_type)
+        if procedure.get('return_is_nullable', False) and \
+           isinstance(return_type, ClassType):
+            return_type_name = 'Option<%s>' % return_type_name
+
+        documentation = self.parse_documentation(procedure['documentation'])
+        if defaults:
+            if documentation:
+                documentation += '\n///\n'
+            documentation += _rustdoc('\n'.join(defaults), '///')
+
+        signature = ['&self'] if receiver else []
+        for x in parameters:
+            if x['optional']:
+                signature.append('%s: Option<%s>' % (x['name'], x['type']))
+            else:
+                signature.append('%s: %s' % (x['name'], x['type']))
+
+        call = ['::krpc::Call::new("%s", "%s")' % (self.service_name, name)]
+        if receiver:
+            call.append('.arg_at(0, self)')
+        call.extend('.arg_at(%d, %s)' % (x['position'], x['value'])
+                    for x in parameters if not x['optional'])
+
+        return {
+            'name': self.parse_function_name(name),
+            'remote_name': name,
+            'signature': ', '.join(signature),
+            'return_type': return_type_name,
+            'call': call,
+            'optional_parameters': [x for x in parameters if x['optional']],
+            'documentation': documentation
+        }
+
+    def parse_context(self, context):
+        context['documentation'] = _rustdoc(
+            RustDocParser().parse(self._defs.get('documentation', '')), '//!')
+
+        functions = []
+        for cls in context['classes'].values():
+            cls['functions'] = []
+        for name, procedure in self._get_defs('procedures'):
+            function = self.generate_context_function(name, procedure)
+            if Attributes.is_a_class_member(name):
+                class_name = Attributes.get_class_name(name)
+                context['classes'][class_name]['functions'].append(function)
+            else:
+                functions.append(function)
+
+        def sort_functions(x):
+            return sorted(x, key=lambda f: (f['name'], f['remote_name']))
+
+        context['functions'] = sort_functions(functions)
+        for cls in context['classes'].values():
+            cls['functions'] = sort_functions(cls['functions'])
+
+        # Variants keep the names they have in the service
+        for name, enumeration in context['enumerations'].items():
+            values = self._defs['enumerations'][name]['values']
+            for value, definition in zip(enumeration['values'], values):
+                value['name'] = definition['name']
+
+        return context
+
+
+def _rustdoc(markdown, prefix):
+    if markdown == '':
+        return ''
+    return '\n'.join(prefix + (' ' + line if line else '')
+                     for line in markdown.split('\n'))
+
+
+def _collapse_whitespace(text):
+    return re.sub(r'\s+', ' ', text or '')
+
+
+class RustDocParser(DocParser):
+
+    def parse(self, xml):
+        content = super(RustDocParser, self).parse(xml)
+        return '\n'.join(line.strip() for line in content.split('\n')).strip()
+
+    def parse_node(self, node, indent=0):  # pylint: disable=unused-argument
+        content = _collapse_whitespace(node.text)
+        for child in node:
+            content += self.inner_parse_node(child)
+            content += _collapse_whitespace(child.tail)
+        return content
+
+    def parse_summary(self, node):
+        return self.parse_node(node).strip()
+
+    def parse_remarks(self, node):
+        return '\n\n'+self.parse_node(node).strip()
+
+    def parse_param(self, node):
+        return '\n\n* `%s` - %s' % \
+            (node.attrib['name'], self.parse_node(node).strip())
+
+    def parse_returns(self, node):
+        return '\n\n'+self.parse_node(node).strip()
+
+    def parse_see(self, node):
+        return '`%s`' % self.parse_cref(node.attrib['cref'])
+
+    @staticmethod
+    def parse_paramref(node):
+        return '`%s`' % node.attrib['name']
+
+    @staticmethod
+    def parse_a(node):
+        return '[%s](%s)' % \
+            (_collapse_whitespace(node.text).strip(), node.attrib['href'])
+
+    @staticmethod
+    def parse_c(node):
+        return '`%s`' % node.text
+
+    @staticmethod
+    def parse_math(node):
+        return node.text
+
+    def parse_list(self, node):
+        content = ['\n* %s' % self.parse_node(item[0]).strip()
+                   for item in node]
+        return '\n'+''.join(content)+'\n'
+
+    @staticmethod
+    def parse_cref(cref):
+        """ Strip the kind and service, so that M:SpaceCenter.Vessel.Recover
+            becomes Vessel.Recover """
+        return cref[2:].partition('.')[2]
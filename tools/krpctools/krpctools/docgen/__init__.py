@@ -17,6 +17,7 @@ from .csharp import CsharpDomain
 from .java import JavaDomain
 from .lua import LuaDomain
 from .python import PythonDomain
+from .rust import RustDomain
 from .nodes import Service
 from .docgen import DocumentationGenerator
 from .extensions import AppendExtension
@@ -33,7 +34,7 @@ def main():
         version='%s version %s' % (prog, __version__))
     parser.add_argument(
         'language', choices=(
-            'cnano', 'cpp', 'csharp', 'java', 'lua', 'python'),
+            'cnano', 'cpp', 'csharp', 'java', 'lua', 'python', 'rust'),
         help='Language to generate')
     parser.add_argument(
         'source', action='store',
@@ -70,8 +71,10 @@ def main():
         domain = JavaDomain(macros)
     elif args.language == 'lua':
         domain = LuaDomain(macros)
-    else:  # python
+    elif args.language == 'python':
         domain = PythonDomain(macros)
+    else:  # rust
+        domain = RustDomain(macros)
 
     if not os.path.exists(args.order_file):
         raise RuntimeError(
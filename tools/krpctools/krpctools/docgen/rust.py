@@ -0,0 +1,100 @@
+import re
+from krpc.types import ClassType, EnumerationType
+from krpc.utils import snake_case
+from .domain import Domain
+from .nodes import \
+    Procedure, Property, Class, ClassMethod, ClassStaticMethod, \
+    ClassProperty, Enumeration, EnumerationValue, ExceptionNode
+from ..lang.rust import RustLanguage
+
+
+class RustDomain(Domain):
+    name = 'rust'
+    prettyname = 'Rust'
+    sphinxname = 'rust'
+    highlight = 'rust'
+    codeext = 'rs'
+    language = RustLanguage()
+
+    # Exceptions that the client returns as their own variant of krpc::Error
+    error_variants = {
+        'KRPC.InvalidOperationException': 'InvalidOperation',
+        'KRPC.ArgumentException': 'Argument',
+        'KRPC.ArgumentOutOfRangeException': 'ArgumentOutOfRange',
+        'KRPC.ArgumentNullException': 'ArgumentNull'
+    }
+
+    def currentmodule(self, name):
+        super(RustDomain, self).currentmodule(name)
+        return '.. currentmodule:: %s' % self.language.parse_name(name)
+
+    def method_name(self, name):
+        return self.language.parse_name(name)
+
+    def setter_name(self, name):
+        return self.language.escape('set_' + snake_case(name))
+
+    @staticmethod
+    def _path(name):
+        """ Write paths to other services and crates as they are documented,
+            for example super::drawing::Line as drawing::Line and
+            ::krpc::Bytes as krpc::Bytes """
+        name = name.replace('super::', '')
+        return re.sub(r'(^|[<(&, ])::', r'\1', name)
+
+    def type(self, typ):
+        return self._path(self.language.parse_type(typ))
+
+    def return_type(self, typ, nullable=False):
+        if nullable and isinstance(typ, ClassType):
+            return 'Option<%s>' % self.type(typ)
+        return self.type(typ)
+
+    def parameter_type(self, typ):
+        return self._path(self.language.parse_parameter_type(typ))
+
+    def default_value(self, value, typ, services=None):
+        if isinstance(typ, EnumerationType):
+            service = (services or {}).get(typ.protobuf_type.service)
+            if service is None or \
+               typ.protobuf_type.name not in service.enumerations:
+                return None
+            enumeration = service.enumerations[typ.protobuf_type.name]
+            for x in enumeration.values.values():
+                if x.value == value:
+                    return '%s::%s' % (self.type(typ), x.name)
+            return None
+        return self.language.parse_default_value(value, typ)
+
+    def error_variant(self, obj):
+        return self.error_variants.get(obj.fullname, 'Rpc')
+
+    def ref(self, obj):
+        name = obj.fullname.split('.')
+        if isinstance(obj, (Procedure, Property, ClassMethod,
+                            ClassStaticMethod, ClassProperty)):
+            name[-1] = self.method_name(name[-1])
+        if name[0] == self.module:
+            del name[0]
+        else:
+            name[0] = self.language.parse_name(name[0])
+        return '::'.join(name)
+
+    def see(self, obj):
+        if isinstance(obj, (Property, ClassProperty, Procedure,
+                            ClassMethod, ClassStaticMethod)):
+            prefix = 'fn'
+        elif isinstance(obj, Class):
+            prefix = 'struct'
+        elif isinstance(obj, Enumeration):
+            prefix = 'enum'
+        elif isinstance(obj, EnumerationValue):
+            prefix = 'variant'
+        elif isinstance(obj, ExceptionNode):
+            prefix = 'error'
+        else:
+            raise RuntimeError(str(obj))
+        return ':%s:`%s`' % (prefix, self.ref(obj))
+
+    def paramref(self, name):
+        return super(RustDomain, self).paramref(self.method_name(name))
@@ -0,0 +1,107 @@
+from krpc.schema.KRPC_pb2 import Type
+from krpc.types import \
+    ValueType, ClassType, EnumerationType, MessageType, \
+    TupleType, ListType, SetType, DictionaryType
+from krpc.utils import snake_case
+from .language import Language
+
+
+class RustLanguage(Language):
+
+    keywords = set([
+        'abstract', 'as', 'async', 'await', 'become', 'box', 'break',
+        'const', 'continue', 'do', 'dyn', 'else', 'enum', 'extern', 'false',
+        'final', 'fn', 'for', 'gen', 'if', 'impl', 'in', 'let', 'loop',
+        'macro', 'match', 'mod', 'move', 'mut', 'override', 'priv', 'pub',
+        'ref', 'return', 'static', 'struct', 'trait', 'true', 'try', 'type',
+        'typeof', 'unsafe', 'unsized', 'use', 'virtual', 'where', 'while',
+        'yield'
+    ])
+
+    # Keywords that cannot be written as raw identifiers
+    unrawable = set(['crate', 'self', 'super'])
+
+    type_map = {
+        Type.DOUBLE: 'f64',
+        Type.FLOAT: 'f32',
+        Type.SINT32: 'i32',
+        Type.SINT64: 'i64',
+        Type.UINT32: 'u32',
+        Type.UINT64: 'u64',
+        Type.BOOL: 'bool',
+        Type.STRING: 'String',
+        Type.BYTES: '::krpc::Bytes'
+    }
+
+    # Types passed by value rather than borrowed
+    copy_types = set([
+        Type.DOUBLE, Type.FLOAT, Type.SINT32, Type.SINT64,
+        Type.UINT32, Type.UINT64, Type.BOOL
+    ])
+
+    def parse_name(self, name):
+        return self.escape(snake_case(name))
+
+    def escape(self, name):
+        if name in self.unrawable:
+            return '%s_' % name
+        elif name in self.keywords:
+            return 'r#%s' % name
+        return name
+
+    def parse_type(self, typ):
+        if typ is None:
+            return '()'
+        elif isinstance(typ, ValueType):
+            return self.type_map[typ.protobuf_type.code]
+        elif isinstance(typ, MessageType):
+            return '::krpc::schema::%s' % typ.python_type.__name__
+        elif isinstance(typ, ListType):
+            return 'Vec<%s>' % self.parse_type(typ.value_type)
+        elif isinstance(typ, SetType):
+            return '::std::collections::HashSet<%s>' % \
+                self.parse_type(typ.value_type)
+        elif isinstance(typ, DictionaryType):
+            return '::std::collections::HashMap<%s, %s>' % \
+                (self.parse_type(typ.key_type),
+                 self.parse_type(typ.value_type))
+        elif isinstance(typ, TupleType):
+            value_types = [self.parse_type(t) for t in typ.value_types]
+            if len(value_types) == 1:
+                return '(%s,)' % value_types[0]
+            return '(%s)' % ', '.join(value_types)
+        elif isinstance(typ, (ClassType, EnumerationType)):
+            if typ.protobuf_type.service == self.module:
+                return typ.protobuf_type.name
+            return 'super::%s::%s' % \
+                (self.parse_name(typ.protobuf_type.service),
+                 typ.protobuf_type.name)
+        raise RuntimeError('Unknown type \'%s\'' % str(typ))
+
+    def parse_parameter_type(self, typ):
+        """ Strings, bytes, collections and messages are borrowed """
+        if isinstance(typ, ValueType) and \
+           typ.protobuf_type.code == Type.STRING:
+            return '&str'
+        elif isinstance(typ, ValueType) and \
+                typ.protobuf_type.code == Type.BYTES:
+            return '&[u8]'
+        elif (isinstance(typ, ValueType) and
+              typ.protobuf_type.code in self.copy_types) or \
+                isinstance(typ, (ClassType, EnumerationType)):
+            return self.parse_type(typ)
+        return '&%s' % self.parse_type(typ)
+
+    def parse_default_value(self, value, typ):
+        if not isinstance(typ, ValueType):
+            return None
+        code = typ.protobuf_type.code
+        if code == Type.STRING:
+            return '"%s"' % value.replace('\\', '\\\\').replace('"', '\\"')
+        elif code == Type.BOOL:
+            return 'true' if value else 'false'
+        elif code in (Type.DOUBLE, Type.FLOAT):
+            return repr(float(value))
+        elif code in self.copy_types:
+            return str(value)
+        return None
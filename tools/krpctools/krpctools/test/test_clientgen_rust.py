@@ -0,0 +1,12 @@
+import unittest
+from krpctools.test.clientgentest import ClientGenTestCase
+from krpctools.clientgen.rust import RustGenerator
+
+
+class TestClientGenRust(ClientGenTestCase, unittest.TestCase):
+    language = 'rust'
+    generator = RustGenerator
+
+
+if __name__ == '__main__':
+    unittest.main()
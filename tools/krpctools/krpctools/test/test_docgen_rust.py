@@ -0,0 +1,12 @@
+import unittest
+from krpctools.test.docgentest import DocGenTestCase
+from krpctools.docgen.rust import RustDomain
+
+
+class TestDocGenRust(DocGenTestCase, unittest.TestCase):
+    language = 'rust'
+    domain = RustDomain
+
+
+if __name__ == '__main__':
+    unittest.main()